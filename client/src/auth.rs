@@ -0,0 +1,63 @@
+use renet2_netcode::ConnectToken;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use zombrise_shared::protocol::protocol::{
+    ConnectUserData, ProtocolVersion, TOKEN_VERSION_MISMATCH,
+};
+
+use crate::connecting::version_mismatch;
+
+const TOKEN_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Asks the token service at `address` for a connect token. The token carries our client id,
+/// our user data and the address of the game server to connect to.
+pub fn fetch_connect_token(
+    address: &str,
+    user_data: ConnectUserData,
+    protocol_version: &ProtocolVersion,
+) -> Result<ConnectToken, String> {
+    let service_addr = address
+        .to_socket_addrs()
+        .map_err(|e| format!("Cannot resolve token service {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("No address found for token service {}", address))?;
+
+    let mut stream = TcpStream::connect_timeout(&service_addr, TOKEN_REQUEST_TIMEOUT)
+        .map_err(|e| format!("Cannot reach token service {}: {}", service_addr, e))?;
+    stream
+        .set_read_timeout(Some(TOKEN_REQUEST_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let name = user_data.name_bytes();
+    let mut request = Vec::with_capacity(33 + name.len());
+    request.extend_from_slice(&user_data.identity.unwrap_or(0).to_le_bytes());
+    request.extend_from_slice(&user_data.protocol_hash.to_le_bytes());
+    request.extend_from_slice(&user_data.secret.to_le_bytes());
+    request.push(name.len() as u8);
+    request.extend_from_slice(name);
+    stream
+        .write_all(&request)
+        .map_err(|e| format!("Cannot send token request to {}: {}", service_addr, e))?;
+
+    let mut status = [0; 1];
+    stream
+        .read_exact(&mut status)
+        .map_err(|e| format!("No answer from token service {}: {}", service_addr, e))?;
+    if status[0] == TOKEN_VERSION_MISMATCH {
+        let mut len = [0; 1];
+        let mut version = Vec::new();
+        let server_version = stream
+            .read_exact(&mut len)
+            .and_then(|_| {
+                version.resize(len[0] as usize, 0);
+                stream.read_exact(&mut version)
+            })
+            .map(|_| String::from_utf8_lossy(&version).into_owned())
+            .unwrap_or_else(|_| "another version".to_string());
+        return Err(version_mismatch(&server_version, protocol_version));
+    }
+
+    ConnectToken::read(&mut stream)
+        .map_err(|e| format!("Invalid connect token from {}: {}", service_addr, e))
+}
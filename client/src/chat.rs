@@ -0,0 +1,251 @@
+use bevy::prelude::*;
+use bevy_simple_text_input::{
+    TextInput, TextInputInactive, TextInputSettings, TextInputTextColor, TextInputTextFont,
+    TextInputValue,
+};
+use std::collections::VecDeque;
+use zombrise_shared::chat::chat::{
+    ChatMessage, ChatSender, SendChatMessage, MAX_CHAT_MESSAGE_CHARS,
+};
+
+/// Lines kept for scrolling back, older ones are forgotten.
+const MAX_LINES: usize = 100;
+/// Lines shown at once.
+const VISIBLE_LINES: usize = 8;
+/// Seconds a line stays on screen while the chat is closed.
+const LINE_LIFETIME: f32 = 10.0;
+
+struct ChatLine {
+    sender: ChatSender,
+    text: String,
+    /// `Time::elapsed_secs` when the line arrived.
+    received: f32,
+}
+
+/// Chat lines received since joining, oldest first.
+#[derive(Resource, Default)]
+pub struct ChatLog {
+    lines: VecDeque<ChatLine>,
+    /// Lines scrolled back from the newest one.
+    scroll: usize,
+}
+
+/// Whether the chat input is open. Game controls are off while typing.
+#[derive(Resource, Default)]
+pub struct ChatInput {
+    pub open: bool,
+}
+
+#[derive(Component)]
+pub struct ChatOverlay;
+
+#[derive(Component)]
+pub(crate) struct ChatLines;
+
+#[derive(Component)]
+pub(crate) struct ChatInputBox;
+
+/// Run condition for systems that read keys the chat input needs.
+pub fn chat_closed(chat_input: Res<ChatInput>) -> bool {
+    !chat_input.open
+}
+
+pub fn spawn_chat_overlay(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(20.0),
+                bottom: Val::Px(20.0),
+                width: Val::Px(480.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.0),
+                ..default()
+            },
+            // Above the death screen, so the dead can still talk
+            ZIndex(1001),
+            ChatOverlay,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ChatLines,
+            ));
+
+            parent.spawn((
+                Node {
+                    display: Display::None,
+                    width: Val::Percent(100.0),
+                    height: Val::Px(36.0),
+                    align_items: AlignItems::Center,
+                    padding: UiRect::horizontal(Val::Px(8.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.1, 0.1, 0.15, 0.8).into()),
+                TextInput,
+                TextInputInactive(true),
+                TextInputTextFont(TextFont {
+                    font_size: 18.0,
+                    ..default()
+                }),
+                TextInputTextColor(TextColor(Color::srgb(1.0, 1.0, 1.0))),
+                TextInputValue(String::new()),
+                // Sending and clearing is up to `toggle_chat`
+                TextInputSettings {
+                    retain_on_submit: true,
+                    ..default()
+                },
+                ChatInputBox,
+            ));
+        });
+}
+
+pub fn cleanup_chat_overlay(
+    mut commands: Commands,
+    overlay_query: Query<Entity, With<ChatOverlay>>,
+    mut chat_log: ResMut<ChatLog>,
+    mut chat_input: ResMut<ChatInput>,
+) {
+    for entity in overlay_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    *chat_log = ChatLog::default();
+    chat_input.open = false;
+}
+
+pub fn receive_chat_messages(
+    mut messages: MessageReader<ChatMessage>,
+    mut chat_log: ResMut<ChatLog>,
+    time: Res<Time>,
+) {
+    for message in messages.read() {
+        match &message.sender {
+            ChatSender::Player(name) => println!("[chat] {}: {}", name, message.text),
+            ChatSender::System => println!("[chat] {}", message.text),
+        }
+
+        chat_log.lines.push_back(ChatLine {
+            sender: message.sender.clone(),
+            text: message.text.clone(),
+            received: time.elapsed_secs(),
+        });
+        if chat_log.lines.len() > MAX_LINES {
+            chat_log.lines.pop_front();
+        }
+        // Keep the scrolled back view on the same lines
+        if chat_log.scroll > 0 {
+            chat_log.scroll = (chat_log.scroll + 1).min(chat_log.lines.len() - 1);
+        }
+    }
+}
+
+/// Opens the input with Enter, sends with Enter and closes with Escape. Runs after the
+/// game systems so the key that closes the chat isn't seen by them as well.
+pub fn toggle_chat(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut chat_input: ResMut<ChatInput>,
+    mut chat_log: ResMut<ChatLog>,
+    mut input_query: Query<
+        (&mut TextInputValue, &mut TextInputInactive, &mut Node),
+        With<ChatInputBox>,
+    >,
+    mut chat_messages: MessageWriter<SendChatMessage>,
+) {
+    let Ok((mut value, mut inactive, mut node)) = input_query.single_mut() else {
+        return;
+    };
+
+    if !chat_input.open {
+        if keys.just_pressed(KeyCode::Enter) {
+            chat_input.open = true;
+            value.0.clear();
+            inactive.0 = false;
+            node.display = Display::Flex;
+        }
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::PageUp) {
+        let oldest = chat_log.lines.len().saturating_sub(VISIBLE_LINES);
+        chat_log.scroll = (chat_log.scroll + VISIBLE_LINES / 2).min(oldest);
+    }
+    if keys.just_pressed(KeyCode::PageDown) {
+        chat_log.scroll = chat_log.scroll.saturating_sub(VISIBLE_LINES / 2);
+    }
+
+    let send = keys.just_pressed(KeyCode::Enter);
+    if !send && !keys.just_pressed(KeyCode::Escape) {
+        return;
+    }
+
+    let text: String = value
+        .0
+        .trim()
+        .chars()
+        .take(MAX_CHAT_MESSAGE_CHARS)
+        .collect();
+    if send && !text.is_empty() {
+        chat_messages.write(SendChatMessage { text });
+    }
+    chat_input.open = false;
+    chat_log.scroll = 0;
+    value.0.clear();
+    inactive.0 = true;
+    node.display = Display::None;
+}
+
+/// Shows the recent lines, or the scrollback while the input is open.
+pub fn update_chat_lines(
+    mut commands: Commands,
+    chat_log: Res<ChatLog>,
+    chat_input: Res<ChatInput>,
+    lines_query: Query<Entity, With<ChatLines>>,
+    mut shown: Local<Vec<usize>>,
+    time: Res<Time>,
+) {
+    let Ok(lines_entity) = lines_query.single() else {
+        return;
+    };
+
+    let end = chat_log.lines.len() - chat_log.scroll.min(chat_log.lines.len());
+    let start = end.saturating_sub(VISIBLE_LINES);
+    let now = time.elapsed_secs();
+    let visible: Vec<usize> = (start..end)
+        .filter(|&index| chat_input.open || now - chat_log.lines[index].received < LINE_LIFETIME)
+        .collect();
+
+    // Rebuild only when lines come, go or scroll; indices shift when old lines are dropped
+    if !chat_log.is_changed() && *shown == visible {
+        return;
+    }
+    *shown = visible;
+
+    commands.entity(lines_entity).despawn_children();
+    commands.entity(lines_entity).with_children(|parent| {
+        for &index in shown.iter() {
+            let line = &chat_log.lines[index];
+            let (text, color) = match &line.sender {
+                ChatSender::Player(name) => (
+                    format!("{}: {}", name, line.text),
+                    Color::srgb(1.0, 1.0, 1.0),
+                ),
+                ChatSender::System => (line.text.clone(), Color::srgb(0.9, 0.8, 0.3)),
+            };
+            parent.spawn((
+                Text::new(text),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(color),
+                TextShadow {
+                    offset: Vec2::splat(1.5),
+                    ..default()
+                },
+            ));
+        }
+    });
+}
@@ -0,0 +1,490 @@
+use bevy::prelude::*;
+use bevy::tasks::{futures::check_ready, IoTaskPool, Task};
+use bevy::window::{CursorGrabMode, CursorOptions, PrimaryWindow};
+use bevy_replicon::prelude::*;
+use bevy_replicon_renet2::{
+    netcode::{ClientAuthentication, NetcodeClientTransport},
+    renet2::{ConnectionConfig, RenetClient},
+    RenetChannelsExt,
+};
+use renet2_netcode::{ConnectToken, NativeSocket, NetcodeDisconnectReason};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, SystemTime},
+};
+use zombrise_shared::protocol::conditioner::{ConditionedSocket, NetworkConditions};
+use zombrise_shared::protocol::discovery::{DiscoveryQuery, DiscoveryResponse};
+use zombrise_shared::protocol::protocol::ConnectUserData;
+use zombrise_shared::shared::ProtocolVersion;
+
+use crate::auth::fetch_connect_token;
+use crate::identity::PlayerIdentity;
+use crate::profile::{ServerAddress, UserProfile};
+use crate::startup_screen::{AppState, ConnectionError, ServerConfig};
+use crate::MyClientId;
+
+/// Seconds to wait for the server before giving up, including the address lookup.
+const CONNECT_TIMEOUT: f32 = 10.0;
+/// How long to wait for each answer to the version query before asking again.
+const VERSION_QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+/// Version queries sent before connecting anyway.
+const VERSION_QUERY_ATTEMPTS: usize = 3;
+
+/// Where to send the connection request.
+enum ConnectTarget {
+    Unsecure(SocketAddr),
+    Secure(ConnectToken),
+}
+
+/// Connection attempt in progress while in `AppState::Connecting`.
+#[derive(Resource)]
+pub struct ConnectionAttempt {
+    address: String,
+    secure: bool,
+    user_data: ConnectUserData,
+    /// Seconds left before the attempt times out.
+    remaining: f32,
+    /// Address lookup or token request, runs off the main thread so a slow DNS server
+    /// doesn't freeze the UI. `None` once the client has been created.
+    lookup: Option<Task<Result<ConnectTarget, String>>>,
+}
+
+#[derive(Component)]
+pub struct ConnectingScreenMarker;
+
+#[derive(Component)]
+pub(crate) struct ConnectingSpinner;
+
+#[derive(Component)]
+pub(crate) struct CancelConnectButton;
+
+pub fn show_connecting_screen(mut commands: Commands, server_config: Res<ServerConfig>) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.15, 0.15, 0.2).into()),
+            ConnectingScreenMarker,
+        ))
+        .with_children(|parent| {
+            // Ring with one bright side, rotated by `spin_connecting_spinner`
+            parent.spawn((
+                Node {
+                    width: Val::Px(60.0),
+                    height: Val::Px(60.0),
+                    border: UiRect::all(Val::Px(6.0)),
+                    margin: UiRect::bottom(Val::Px(30.0)),
+                    ..default()
+                },
+                BorderColor {
+                    top: Color::srgb(0.9, 0.8, 0.3),
+                    right: Color::srgb(0.3, 0.3, 0.35),
+                    bottom: Color::srgb(0.3, 0.3, 0.35),
+                    left: Color::srgb(0.3, 0.3, 0.35),
+                },
+                BorderRadius::MAX,
+                UiTransform::default(),
+                ConnectingSpinner,
+            ));
+
+            parent.spawn((
+                Text::new(format!("Connecting to {}...", server_config.url)),
+                TextFont {
+                    font_size: 28.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                Node {
+                    margin: UiRect::bottom(Val::Px(30.0)),
+                    ..default()
+                },
+            ));
+
+            parent
+                .spawn((
+                    Button,
+                    Node {
+                        width: Val::Px(160.0),
+                        height: Val::Px(50.0),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                    CancelConnectButton,
+                ))
+                .with_children(|button_parent| {
+                    button_parent.spawn(Text::new("Cancel"));
+                });
+        });
+}
+
+pub fn cleanup_connecting_screen(
+    mut commands: Commands,
+    connecting_screen_query: Query<Entity, With<ConnectingScreenMarker>>,
+) {
+    for entity in connecting_screen_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<ConnectionAttempt>();
+}
+
+pub fn spin_connecting_spinner(
+    mut spinner_query: Query<&mut UiTransform, With<ConnectingSpinner>>,
+    time: Res<Time>,
+) {
+    for mut transform in &mut spinner_query {
+        transform.rotation = Rot2::radians(time.elapsed_secs() * std::f32::consts::TAU);
+    }
+}
+
+/// Starts looking up the server address, or requesting a connect token in secure mode.
+pub fn start_connection(
+    mut commands: Commands,
+    server_config: Res<ServerConfig>,
+    identity: Res<PlayerIdentity>,
+    protocol_version: Res<ProtocolVersion>,
+    profile: Res<UserProfile>,
+) {
+    let address = server_config.url.clone();
+    let secure = server_config.secure;
+    let user_data = identity.user_data(&protocol_version, &profile.name);
+    // Keep the name typed on the startup screen even if the connection fails
+    profile.save();
+
+    // Servers of another version turn us away in the handshake without saying why, so
+    // the version is compared here when a server list told us, and asked for otherwise
+    let listed = server_config.listed.is_some();
+    let mismatch = server_config
+        .listed
+        .as_ref()
+        .filter(|info| info.protocol_hash != protocol_version.hash)
+        .map(|info| version_mismatch(&info.version, &protocol_version));
+
+    let lookup_address = address.clone();
+    let token_user_data = user_data.clone();
+    let task_protocol_version = protocol_version.clone();
+    let lookup = IoTaskPool::get().spawn(async move {
+        if let Some(mismatch) = mismatch {
+            Err(mismatch)
+        } else if secure {
+            // The address is the token service, the token tells us where to connect
+            println!("Requesting connect token from {}", lookup_address);
+            fetch_connect_token(&lookup_address, token_user_data, &task_protocol_version)
+                .map(ConnectTarget::Secure)
+        } else {
+            resolve_server_address(&lookup_address).and_then(|server_addr| {
+                if !listed {
+                    check_server_version(server_addr, &task_protocol_version)?;
+                }
+                Ok(ConnectTarget::Unsecure(server_addr))
+            })
+        }
+    });
+
+    commands.insert_resource(ConnectionAttempt {
+        address,
+        secure,
+        user_data,
+        remaining: CONNECT_TIMEOUT,
+        lookup: Some(lookup),
+    });
+}
+
+fn resolve_server_address(address: &str) -> Result<SocketAddr, String> {
+    let addresses: Vec<SocketAddr> = address
+        .to_socket_addrs()
+        .map_err(|e| format!("Cannot resolve {}: {}", address, e))?
+        .collect();
+
+    // Prefer IPv4, fall back to any address if there is none
+    addresses
+        .iter()
+        .find(|addr| addr.is_ipv4())
+        .or_else(|| addresses.first())
+        .copied()
+        .ok_or_else(|| format!("No address found for {}", address))
+}
+
+/// Asks the game server for its version before the handshake, which a server of another
+/// version would silently ignore. Servers that don't answer are left to the handshake.
+fn check_server_version(
+    server_addr: SocketAddr,
+    protocol_version: &ProtocolVersion,
+) -> Result<(), String> {
+    let local_addr = match server_addr {
+        SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    };
+    let socket = UdpSocket::bind(local_addr)
+        .and_then(|socket| {
+            socket.set_read_timeout(Some(VERSION_QUERY_TIMEOUT))?;
+            Ok(socket)
+        })
+        .map_err(|e| format!("Cannot open a UDP socket: {}", e))?;
+
+    let query = DiscoveryQuery {
+        nonce: rand::random(),
+    };
+    let mut buffer = [0; 512];
+    for _ in 0..VERSION_QUERY_ATTEMPTS {
+        if socket.send_to(&query.to_bytes(), server_addr).is_err() {
+            return Ok(());
+        }
+        while let Ok((len, from)) = socket.recv_from(&mut buffer) {
+            let Some(response) = DiscoveryResponse::from_bytes(&buffer[..len]) else {
+                continue;
+            };
+            if from != server_addr || response.nonce != query.nonce {
+                continue;
+            }
+            if response.info.protocol_hash != protocol_version.hash {
+                return Err(version_mismatch(&response.info.version, protocol_version));
+            }
+            return Ok(());
+        }
+    }
+    println!("{} didn't answer the version query", server_addr);
+    Ok(())
+}
+
+/// Creates the client once the lookup is done and enters the game when the server accepts us.
+pub fn update_connection(
+    mut commands: Commands,
+    mut attempt: ResMut<ConnectionAttempt>,
+    network_channels: Res<RepliconChannels>,
+    client: Option<ResMut<RenetClient>>,
+    transport: Option<Res<NetcodeClientTransport>>,
+    mut connection_error: ResMut<ConnectionError>,
+    mut profile: ResMut<UserProfile>,
+    protocol_version: Res<ProtocolVersion>,
+    mut next_state: ResMut<NextState<AppState>>,
+    time: Res<Time>,
+) {
+    attempt.remaining -= time.delta_secs();
+
+    if let Some(lookup) = &mut attempt.lookup {
+        match check_ready(lookup) {
+            Some(Ok(target)) => {
+                attempt.lookup = None;
+                let result = create_client(
+                    &mut commands,
+                    &network_channels,
+                    &attempt.user_data,
+                    &protocol_version,
+                    target,
+                );
+                if let Err(e) = result {
+                    fail_connection(&mut connection_error, &mut next_state, e);
+                }
+            }
+            Some(Err(e)) => fail_connection(&mut connection_error, &mut next_state, e),
+            None if attempt.remaining <= 0.0 => {
+                let error = format!("Timed out looking up {}", attempt.address);
+                fail_connection(&mut connection_error, &mut next_state, error);
+            }
+            None => {}
+        }
+        // The client created above is only available from the next frame on
+        return;
+    }
+
+    let (Some(mut client), Some(transport)) = (client, transport) else {
+        return;
+    };
+
+    if client.is_connected() {
+        println!("Connected to {}", attempt.address);
+        profile.record_connection(ServerAddress {
+            address: attempt.address.clone(),
+            secure: attempt.secure,
+        });
+        next_state.set(AppState::Playing);
+    } else if let Some(reason) = disconnect_message(&client, &transport) {
+        let error = format!("Cannot connect to {}: {}", attempt.address, reason);
+        fail_connection(&mut connection_error, &mut next_state, error);
+    } else if attempt.remaining <= 0.0 {
+        client.disconnect();
+        let error = format!("Timed out connecting to {}", attempt.address);
+        fail_connection(&mut connection_error, &mut next_state, error);
+    }
+}
+
+fn create_client(
+    commands: &mut Commands,
+    network_channels: &RepliconChannels,
+    user_data: &ConnectUserData,
+    protocol_version: &ProtocolVersion,
+    target: ConnectTarget,
+) -> Result<(), String> {
+    let server_channels_config = network_channels.server_configs();
+    let client_channels_config = network_channels.client_configs();
+
+    let client = RenetClient::new(
+        ConnectionConfig {
+            server_channels_config,
+            client_channels_config,
+            available_bytes_per_tick: 16 * 1024,
+        },
+        false,
+    );
+
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+
+    let (client_id, authentication) = match target {
+        ConnectTarget::Secure(connect_token) => {
+            // The token tells us our client id and where the game server is
+            println!(
+                "Connecting with connect token as client {}",
+                connect_token.client_id
+            );
+            (
+                connect_token.client_id,
+                ClientAuthentication::Secure { connect_token },
+            )
+        }
+        ConnectTarget::Unsecure(server_addr) => {
+            // Random ids instead of timestamps so players joining at the same moment don't collide
+            let client_id = rand::random::<u64>();
+            println!("Connecting to server at: {}", server_addr);
+            (
+                client_id,
+                ClientAuthentication::Unsecure {
+                    client_id,
+                    protocol_id: protocol_version.hash,
+                    server_addr,
+                    socket_id: 0,
+                    user_data: Some(user_data.to_bytes()),
+                },
+            )
+        }
+    };
+
+    let socket =
+        UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("Cannot open a UDP socket: {}", e))?;
+    let native_socket =
+        NativeSocket::new(socket).map_err(|e| format!("Cannot open a UDP socket: {}", e))?;
+    let socket = ConditionedSocket::new(native_socket, simulated_conditions()?);
+    let transport = NetcodeClientTransport::new(current_time, authentication, socket)
+        .map_err(|e| format!("Cannot start the connection: {}", e))?;
+
+    commands.insert_resource(client);
+    commands.insert_resource(transport);
+    commands.insert_resource(MyClientId(client_id));
+    Ok(())
+}
+
+/// Bad network to simulate from `ZOMBRISE_SIMULATE`, e.g. `latency=100,jitter=20,loss=0.05`.
+fn simulated_conditions() -> Result<NetworkConditions, String> {
+    let Ok(spec) = std::env::var("ZOMBRISE_SIMULATE") else {
+        return Ok(NetworkConditions::default());
+    };
+    let conditions: NetworkConditions = spec
+        .parse()
+        .map_err(|e| format!("Invalid ZOMBRISE_SIMULATE: {}", e))?;
+    if conditions.is_active() {
+        println!("Simulating a bad network: {}", conditions);
+    }
+    Ok(conditions)
+}
+
+fn fail_connection(
+    connection_error: &mut ConnectionError,
+    next_state: &mut NextState<AppState>,
+    error: String,
+) {
+    eprintln!("{}", error);
+    connection_error.0 = Some(error);
+    next_state.set(AppState::StartupScreen);
+}
+
+/// Why the connection ended, `None` while it's still up or being established.
+fn disconnect_message(client: &RenetClient, transport: &NetcodeClientTransport) -> Option<String> {
+    // The transport knows more than the client, e.g. that the server turned us down
+    if let Some(reason) = transport.disconnect_reason() {
+        return Some(match reason {
+            NetcodeDisconnectReason::ConnectionDenied => "Server is full".to_string(),
+            reason => reason.to_string(),
+        });
+    }
+    client.disconnect_reason().map(|reason| reason.to_string())
+}
+
+pub fn handle_cancel_connect(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<CancelConnectButton>),
+    >,
+    attempt: Res<ConnectionAttempt>,
+    client: Option<ResMut<RenetClient>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, mut color) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = Color::srgb(0.15, 0.15, 0.2).into();
+                // Before the lookup is done the client is still the one of an earlier game
+                if attempt.lookup.is_none() {
+                    if let Some(mut client) = client {
+                        client.disconnect();
+                    }
+                }
+                next_state.set(AppState::StartupScreen);
+                return;
+            }
+            Interaction::Hovered => {
+                *color = Color::srgb(0.3, 0.3, 0.35).into();
+            }
+            Interaction::None => {
+                *color = Color::srgb(0.2, 0.2, 0.25).into();
+            }
+        }
+    }
+}
+
+/// What the startup screen shows when the server runs another version.
+pub fn version_mismatch(server_version: &str, protocol_version: &ProtocolVersion) -> String {
+    format!(
+        "Server is running version {}, you have {}",
+        server_version,
+        protocol_version.display()
+    )
+}
+
+/// Goes back to the startup screen with the reason when the connection drops during the game.
+pub fn handle_disconnect(
+    client: Res<RenetClient>,
+    transport: Res<NetcodeClientTransport>,
+    mut connection_error: ResMut<ConnectionError>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if let Some(reason) = disconnect_message(&client, &transport) {
+        let error = format!("Disconnected from server: {}", reason);
+        fail_connection(&mut connection_error, &mut next_state, error);
+    }
+}
+
+/// Closes the connection and frees the cursor when going back to the startup screen.
+pub fn leave_game(
+    client: Option<ResMut<RenetClient>>,
+    mut cursor_query: Query<&mut CursorOptions, With<PrimaryWindow>>,
+) {
+    if let Some(mut client) = client {
+        if !client.is_disconnected() {
+            client.disconnect();
+        }
+    }
+
+    if let Some(mut options) = cursor_query.iter_mut().next() {
+        options.grab_mode = CursorGrabMode::None;
+        options.visible = true;
+    }
+}
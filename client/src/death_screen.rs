@@ -1,5 +1,8 @@
 use bevy::prelude::*;
-use zombrise_shared::players::player::{Health, Player, PlayerOwner};
+use bevy::window::{CursorGrabMode, CursorOptions, PrimaryWindow};
+use zombrise_shared::players::player::{
+    Dead, Health, Player, PlayerName, PlayerOwner, RequestRespawn,
+};
 
 #[derive(Resource, Default)]
 pub struct PlayerDied(pub bool);
@@ -7,20 +10,27 @@ pub struct PlayerDied(pub bool);
 #[derive(Component)]
 pub struct DeathScreenMarker;
 
+#[derive(Component)]
+pub(crate) struct RespawnButton;
+
+#[derive(Component)]
+pub(crate) struct RespawnCountdownText;
+
 /// Detects when the player's health reaches zero or when the player entity is despawned
 pub fn detect_player_death(
-    player_query: Query<(&Health, &PlayerOwner), With<Player>>,
+    player_query: Query<(&Health, &PlayerOwner, Has<Dead>), With<Player>>,
     client_id: Res<crate::MyClientId>,
     mut player_died: ResMut<PlayerDied>,
 ) {
     let our_player = player_query
         .iter()
-        .find(|(_, owner)| owner.0 == client_id.0);
+        .find(|(_, owner, _)| owner.0 == client_id.0);
 
-    if let Some((health, _)) = our_player {
-        if health.current <= 0.0 && !player_died.0 {
+    if let Some((health, _, is_dead)) = our_player {
+        let dead = is_dead || health.current <= 0.0;
+        if dead && !player_died.0 {
             player_died.0 = true;
-        } else if health.current > 0.0 && player_died.0 {
+        } else if !dead && player_died.0 {
             player_died.0 = false;
         }
     } else {
@@ -36,18 +46,30 @@ pub fn show_death_screen(
     player_died: Res<PlayerDied>,
     death_screen_query: Query<Entity, With<DeathScreenMarker>>,
     health_ui_query: Query<Entity, With<crate::HealthBarUI>>,
+    mut cursor_query: Query<&mut CursorOptions, With<PrimaryWindow>>,
+    player_query: Query<(&PlayerName, &PlayerOwner)>,
+    client_id: Res<crate::MyClientId>,
 ) {
     if player_died.0 && death_screen_query.is_empty() {
+        // The player entity may already be gone, e.g. after being kicked
+        let subtitle = match player_query
+            .iter()
+            .find(|(_, owner)| owner.0 == client_id.0)
+        {
+            Some((name, _)) => format!("The zombies got you, {}...", name.0),
+            None => "The zombies got you...".to_string(),
+        };
+
         // Clean up health bar UI when showing death screen
         for entity in health_ui_query.iter() {
             commands.entity(entity).despawn();
         }
 
-        // Unlock cursor when dead
-        // if let Ok(mut window) = window_query.single_mut() {
-        //     window.cursor.grab_mode = CursorGrabMode::None;
-        //     window.cursor.visible = true;
-        // }
+        // Unlock cursor when dead so the respawn button can be clicked
+        if let Some(mut options) = cursor_query.iter_mut().next() {
+            options.grab_mode = CursorGrabMode::None;
+            options.visible = true;
+        }
 
         // Spawn death screen UI
         commands
@@ -84,7 +106,7 @@ pub fn show_death_screen(
 
                 // Subtitle text
                 parent.spawn((
-                    Text::new("The zombies got you..."),
+                    Text::new(subtitle),
                     TextFont {
                         font_size: 32.0,
                         ..default()
@@ -96,9 +118,50 @@ pub fn show_death_screen(
                     },
                 ));
 
+                // Respawn countdown
+                parent.spawn((
+                    Text::new(""),
+                    TextFont {
+                        font_size: 24.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.85, 0.85, 0.85)),
+                    Node {
+                        margin: UiRect::top(Val::Px(30.0)),
+                        ..default()
+                    },
+                    RespawnCountdownText,
+                ));
+
+                // Respawn button
+                parent
+                    .spawn((
+                        Button,
+                        Node {
+                            width: Val::Px(200.0),
+                            height: Val::Px(60.0),
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            margin: UiRect::top(Val::Px(20.0)),
+                            ..default()
+                        },
+                        BackgroundColor(Color::srgb(0.2, 0.6, 0.2).into()),
+                        RespawnButton,
+                    ))
+                    .with_children(|button_parent| {
+                        button_parent.spawn((
+                            Text::new("Respawn"),
+                            TextFont {
+                                font_size: 30.0,
+                                ..default()
+                            },
+                            TextColor(Color::srgb(1.0, 1.0, 1.0)),
+                        ));
+                    });
+
                 // Info text
                 parent.spawn((
-                    Text::new("Press ESC to return to menu"),
+                    Text::new("Press R to respawn or ESC to return to menu"),
                     TextFont {
                         font_size: 24.0,
                         ..default()
@@ -117,10 +180,66 @@ pub fn show_death_screen(
         }
 
         // Re-lock cursor
-        // if let Ok(mut window) = window_query.single_mut() {
-        //     window.cursor.grab_mode = CursorGrabMode::Locked;
-        //     window.cursor.visible = false;
-        // }
+        if let Some(mut options) = cursor_query.iter_mut().next() {
+            options.grab_mode = CursorGrabMode::Locked;
+            options.visible = false;
+        }
+    }
+}
+
+/// Shows how long the player has to wait before respawning
+pub fn update_respawn_countdown(
+    player_query: Query<(&Dead, &PlayerOwner), With<Player>>,
+    client_id: Res<crate::MyClientId>,
+    mut countdown_query: Query<&mut Text, With<RespawnCountdownText>>,
+) {
+    let Ok(mut text) = countdown_query.single_mut() else {
+        return;
+    };
+
+    let dead = player_query
+        .iter()
+        .find(|(_, owner)| owner.0 == client_id.0)
+        .map(|(dead, _)| dead);
+
+    text.0 = match dead {
+        Some(dead) if dead.respawn_in > 0.0 => {
+            format!("Respawn available in {:.0}s", dead.respawn_in.ceil())
+        }
+        Some(_) => "Ready to respawn".to_string(),
+        None => String::new(),
+    };
+}
+
+/// Asks the server to respawn us when the respawn button is clicked or R is pressed
+pub fn handle_respawn_button(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<RespawnButton>),
+    >,
+    keys: Res<ButtonInput<KeyCode>>,
+    player_died: Res<PlayerDied>,
+    mut respawn_events: MessageWriter<RequestRespawn>,
+) {
+    let mut requested = player_died.0 && keys.just_pressed(KeyCode::KeyR);
+
+    for (interaction, mut color) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = Color::srgb(0.15, 0.5, 0.15).into();
+                requested = true;
+            }
+            Interaction::Hovered => {
+                *color = Color::srgb(0.25, 0.7, 0.25).into();
+            }
+            Interaction::None => {
+                *color = Color::srgb(0.2, 0.6, 0.2).into();
+            }
+        }
+    }
+
+    if requested {
+        respawn_events.write(RequestRespawn);
     }
 }
 
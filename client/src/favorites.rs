@@ -0,0 +1,394 @@
+use bevy::prelude::*;
+use bevy_simple_text_input::{
+    TextInput, TextInputInactive, TextInputSettings, TextInputTextColor, TextInputTextFont,
+    TextInputValue,
+};
+
+use crate::profile::{time_ago, FavoriteServer, ServerAddress, UserProfile};
+use crate::startup_screen::{join_server, AppState, ConnectionError, ServerConfig, ServerUrlInput};
+
+/// Favorite whose name the editor on the favorites tab currently changes.
+#[derive(Resource, Default)]
+pub struct FavoriteEditor {
+    editing: Option<usize>,
+}
+
+#[derive(Component)]
+pub(crate) struct FavoriteNameInput;
+
+#[derive(Component)]
+pub(crate) struct FavoriteSaveText;
+
+#[derive(Component)]
+pub(crate) struct FavoriteList;
+
+#[derive(Component)]
+pub(crate) struct HistoryList;
+
+#[derive(Component, Clone, Copy)]
+pub(crate) enum ProfileButton {
+    /// Adds the address field as a favorite, or renames the favorite being edited.
+    SaveFavorite,
+    JoinFavorite(usize),
+    RenameFavorite(usize),
+    DeleteFavorite(usize),
+    JoinHistory(usize),
+    FavoriteHistory(usize),
+}
+
+/// Editor for adding and renaming favorites, then the favorites filled by
+/// `update_profile_lists`.
+pub fn spawn_favorites_panel(parent: &mut ChildSpawnerCommands) {
+    parent
+        .spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(8.0),
+            margin: UiRect::bottom(Val::Px(6.0)),
+            ..default()
+        })
+        .with_children(|editor| {
+            editor.spawn((
+                Node {
+                    width: Val::Px(300.0),
+                    height: Val::Px(36.0),
+                    align_items: AlignItems::Center,
+                    padding: UiRect::horizontal(Val::Px(8.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                TextInput,
+                TextInputInactive(true),
+                Interaction::None,
+                TextInputTextFont(TextFont {
+                    font_size: 16.0,
+                    ..default()
+                }),
+                TextInputTextColor(TextColor(Color::srgb(1.0, 1.0, 1.0))),
+                TextInputValue(String::new()),
+                TextInputSettings {
+                    retain_on_submit: true,
+                    ..default()
+                },
+                FavoriteNameInput,
+            ));
+
+            editor
+                .spawn((
+                    Button,
+                    Node {
+                        width: Val::Px(180.0),
+                        height: Val::Px(36.0),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                    ProfileButton::SaveFavorite,
+                ))
+                .with_children(|button_parent| {
+                    button_parent.spawn((
+                        Text::new(save_text(None)),
+                        TextFont {
+                            font_size: 16.0,
+                            ..default()
+                        },
+                        FavoriteSaveText,
+                    ));
+                });
+        });
+
+    parent.spawn((
+        Node {
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(4.0),
+            max_height: Val::Px(220.0),
+            overflow: Overflow::scroll_y(),
+            ..default()
+        },
+        FavoriteList,
+    ));
+}
+
+pub fn spawn_history_panel(parent: &mut ChildSpawnerCommands) {
+    parent.spawn((
+        Node {
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(4.0),
+            max_height: Val::Px(260.0),
+            overflow: Overflow::scroll_y(),
+            ..default()
+        },
+        HistoryList,
+    ));
+}
+
+fn save_text(editing: Option<usize>) -> &'static str {
+    if editing.is_some() {
+        "Rename favorite"
+    } else {
+        "Add address as favorite"
+    }
+}
+
+/// One line of a list: the label joins the server, the small buttons act on the entry.
+fn spawn_entry(
+    parent: &mut ChildSpawnerCommands,
+    label: String,
+    join: ProfileButton,
+    actions: &[(&str, ProfileButton)],
+) {
+    parent
+        .spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(4.0),
+            ..default()
+        })
+        .with_children(|row| {
+            row.spawn((
+                Button,
+                Node {
+                    flex_grow: 1.0,
+                    padding: UiRect::axes(Val::Px(10.0), Val::Px(6.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                join,
+            ))
+            .with_children(|button_parent| {
+                button_parent.spawn((
+                    Text::new(label),
+                    TextFont {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                ));
+            });
+
+            for (action_label, action) in actions {
+                row.spawn((
+                    Button,
+                    Node {
+                        width: Val::Px(80.0),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                    *action,
+                ))
+                .with_children(|button_parent| {
+                    button_parent.spawn((
+                        Text::new(*action_label),
+                        TextFont {
+                            font_size: 16.0,
+                            ..default()
+                        },
+                    ));
+                });
+            }
+        });
+}
+
+fn spawn_empty_note(parent: &mut ChildSpawnerCommands, note: &str) {
+    parent.spawn((
+        Text::new(note),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.6, 0.6, 0.65)),
+    ));
+}
+
+/// Rebuilds the favorites and history lists whenever the profile changes.
+pub fn update_profile_lists(
+    mut commands: Commands,
+    profile: Res<UserProfile>,
+    editor: Res<FavoriteEditor>,
+    favorite_list_query: Query<Entity, With<FavoriteList>>,
+    history_list_query: Query<Entity, With<HistoryList>>,
+    added_list_query: Query<(), Or<(Added<FavoriteList>, Added<HistoryList>)>>,
+    mut save_text_query: Query<&mut Text, With<FavoriteSaveText>>,
+) {
+    if !profile.is_changed() && !editor.is_changed() && added_list_query.is_empty() {
+        return;
+    }
+
+    if let Ok(mut text) = save_text_query.single_mut() {
+        text.0 = save_text(editor.editing).to_string();
+    }
+
+    if let Ok(list) = favorite_list_query.single() {
+        commands.entity(list).despawn_children();
+        commands.entity(list).with_children(|parent| {
+            if profile.favorites.is_empty() {
+                spawn_empty_note(parent, "No favorites yet");
+            }
+            for (index, favorite) in profile.favorites.iter().enumerate() {
+                let secure = if favorite.server.secure {
+                    ", secure"
+                } else {
+                    ""
+                };
+                spawn_entry(
+                    parent,
+                    format!(
+                        "{}   ({}{})",
+                        favorite.name, favorite.server.address, secure
+                    ),
+                    ProfileButton::JoinFavorite(index),
+                    &[
+                        ("Rename", ProfileButton::RenameFavorite(index)),
+                        ("Delete", ProfileButton::DeleteFavorite(index)),
+                    ],
+                );
+            }
+        });
+    }
+
+    if let Ok(list) = history_list_query.single() {
+        commands.entity(list).despawn_children();
+        commands.entity(list).with_children(|parent| {
+            if profile.history.is_empty() {
+                spawn_empty_note(parent, "Servers you connect to show up here");
+            }
+            for (index, entry) in profile.history.iter().enumerate() {
+                let secure = if entry.server.secure { ", secure" } else { "" };
+                spawn_entry(
+                    parent,
+                    format!(
+                        "{}   ({}{})",
+                        entry.server.address,
+                        time_ago(entry.connected_at),
+                        secure
+                    ),
+                    ProfileButton::JoinHistory(index),
+                    &[("Favorite", ProfileButton::FavoriteHistory(index))],
+                );
+            }
+        });
+    }
+}
+
+pub fn handle_profile_buttons(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &ProfileButton),
+        Changed<Interaction>,
+    >,
+    mut input_query: Query<(
+        &mut TextInputValue,
+        &mut TextInputInactive,
+        Has<FavoriteNameInput>,
+        Has<ServerUrlInput>,
+    )>,
+    mut profile: ResMut<UserProfile>,
+    mut editor: ResMut<FavoriteEditor>,
+    mut server_config: ResMut<ServerConfig>,
+    mut connection_error: ResMut<ConnectionError>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = Color::srgb(0.15, 0.15, 0.2).into();
+            }
+            Interaction::Hovered => {
+                *color = Color::srgb(0.3, 0.3, 0.35).into();
+                continue;
+            }
+            Interaction::None => {
+                *color = Color::srgb(0.2, 0.2, 0.25).into();
+                continue;
+            }
+        }
+
+        match *button {
+            ProfileButton::SaveFavorite => {
+                let mut name = String::new();
+                let mut address = String::new();
+                for (mut value, _, is_name, is_address) in &mut input_query {
+                    if is_name {
+                        name = value.0.trim().to_string();
+                        value.0.clear();
+                    } else if is_address {
+                        address = value.0.trim().to_string();
+                    }
+                }
+
+                if let Some(index) = editor.editing.take() {
+                    if let Some(favorite) = profile.favorites.get_mut(index) {
+                        if !name.is_empty() {
+                            favorite.name = name;
+                        }
+                    }
+                } else if !address.is_empty() {
+                    let server = ServerAddress {
+                        address: address.clone(),
+                        secure: server_config.secure,
+                    };
+                    let name = if name.is_empty() { address } else { name };
+                    // Saving a known address again only renames it
+                    match profile.favorites.iter_mut().find(|f| f.server == server) {
+                        Some(favorite) => favorite.name = name,
+                        None => profile.favorites.push(FavoriteServer { name, server }),
+                    }
+                }
+                profile.save();
+            }
+            ProfileButton::RenameFavorite(index) => {
+                let Some(favorite) = profile.favorites.get(index) else {
+                    continue;
+                };
+                editor.editing = Some(index);
+                // Put the name into the editor and type there right away
+                for (mut value, mut inactive, is_name, _) in &mut input_query {
+                    if is_name {
+                        value.0 = favorite.name.clone();
+                    }
+                    inactive.0 = !is_name;
+                }
+            }
+            ProfileButton::DeleteFavorite(index) => {
+                if index < profile.favorites.len() {
+                    profile.favorites.remove(index);
+                    editor.editing = None;
+                    profile.save();
+                }
+            }
+            ProfileButton::FavoriteHistory(index) => {
+                let Some(entry) = profile.history.get(index).cloned() else {
+                    continue;
+                };
+                if !profile.favorites.iter().any(|f| f.server == entry.server) {
+                    profile.favorites.push(FavoriteServer {
+                        name: entry.server.address.clone(),
+                        server: entry.server,
+                    });
+                    profile.save();
+                }
+            }
+            ProfileButton::JoinFavorite(index) => {
+                if let Some(favorite) = profile.favorites.get(index) {
+                    join_server(
+                        &favorite.server,
+                        None,
+                        &mut server_config,
+                        &mut connection_error,
+                        &mut next_state,
+                    );
+                }
+            }
+            ProfileButton::JoinHistory(index) => {
+                if let Some(entry) = profile.history.get(index) {
+                    join_server(
+                        &entry.server,
+                        None,
+                        &mut server_config,
+                        &mut connection_error,
+                        &mut next_state,
+                    );
+                }
+            }
+        }
+    }
+}
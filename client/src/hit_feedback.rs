@@ -0,0 +1,122 @@
+use bevy::prelude::*;
+use zombrise_shared::zombie::zombie::ZombieAttackHit;
+
+use crate::MyClientId;
+
+/// Seconds the screen stays tinted after a zombie hit us.
+const SCREEN_FLASH_SECONDS: f32 = 0.5;
+/// Damage that tints the screen fully, weaker hits tint it less.
+const FULL_FLASH_DAMAGE: f32 = 40.0;
+/// Seconds the burst over another hit player takes to fade.
+const HIT_MARKER_SECONDS: f32 = 0.4;
+
+/// Red tint over the whole screen that fades after a zombie hit us.
+#[derive(Component)]
+pub struct DamageOverlay {
+    remaining: f32,
+    /// Alpha at the start of the fade.
+    strength: f32,
+}
+
+/// Burst over another player a zombie hit.
+#[derive(Component)]
+pub struct HitMarker {
+    remaining: f32,
+}
+
+/// Flashes the screen when a zombie hits us and marks hits on other players.
+pub fn show_zombie_attack_hits(
+    mut commands: Commands,
+    mut hits: MessageReader<ZombieAttackHit>,
+    my_client_id: Res<MyClientId>,
+    mut overlay_query: Query<&mut DamageOverlay>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for hit in hits.read() {
+        if hit.target == my_client_id.0 {
+            let strength = 0.2 + 0.4 * (hit.damage / FULL_FLASH_DAMAGE).clamp(0.0, 1.0);
+            if let Ok(mut overlay) = overlay_query.single_mut() {
+                overlay.remaining = SCREEN_FLASH_SECONDS;
+                overlay.strength = overlay.strength.max(strength);
+                continue;
+            }
+            commands.spawn((
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.8, 0.0, 0.0, strength)),
+                GlobalZIndex(10),
+                DamageOverlay {
+                    remaining: SCREEN_FLASH_SECONDS,
+                    strength,
+                },
+            ));
+        } else {
+            commands.spawn((
+                Mesh3d(meshes.add(Sphere::new(0.3))),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color: Color::srgba(0.9, 0.1, 0.1, 0.8),
+                    emissive: LinearRgba::rgb(0.6, 0.0, 0.0),
+                    alpha_mode: AlphaMode::Blend,
+                    unlit: true,
+                    ..default()
+                })),
+                Transform::from_translation(hit.position + Vec3::Y),
+                HitMarker {
+                    remaining: HIT_MARKER_SECONDS,
+                },
+            ));
+        }
+    }
+}
+
+/// Fades the screen tint and lets hit markers grow and fade, removing them once gone.
+pub fn fade_hit_feedback(
+    mut commands: Commands,
+    mut overlay_query: Query<(Entity, &mut DamageOverlay, &mut BackgroundColor)>,
+    mut marker_query: Query<(
+        Entity,
+        &mut HitMarker,
+        &mut Transform,
+        &MeshMaterial3d<StandardMaterial>,
+    )>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    time: Res<Time>,
+) {
+    for (entity, mut overlay, mut color) in &mut overlay_query {
+        overlay.remaining -= time.delta_secs();
+        if overlay.remaining <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let alpha = overlay.strength * overlay.remaining / SCREEN_FLASH_SECONDS;
+        color.0 = color.0.with_alpha(alpha);
+    }
+
+    for (entity, mut marker, mut transform, material) in &mut marker_query {
+        marker.remaining -= time.delta_secs();
+        if marker.remaining <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let progress = 1.0 - marker.remaining / HIT_MARKER_SECONDS;
+        transform.scale = Vec3::splat(1.0 + progress);
+        if let Some(material) = materials.get_mut(&material.0) {
+            material.base_color = material.base_color.with_alpha(0.8 * (1.0 - progress));
+        }
+    }
+}
+
+pub fn cleanup_hit_feedback(
+    mut commands: Commands,
+    overlay_query: Query<Entity, With<DamageOverlay>>,
+    marker_query: Query<Entity, With<HitMarker>>,
+) {
+    for entity in overlay_query.iter().chain(marker_query.iter()) {
+        commands.entity(entity).despawn();
+    }
+}
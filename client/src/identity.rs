@@ -0,0 +1,78 @@
+use bevy::prelude::*;
+use std::path::PathBuf;
+use zombrise_shared::protocol::protocol::{ConnectUserData, ProtocolVersion};
+
+const IDENTITY_FILE: &str = "identity";
+
+/// Random id of this installation, kept across restarts so the server can recognise us
+/// when we reconnect and give back our player. The secret proves the id is ours.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PlayerIdentity {
+    pub id: u64,
+    pub secret: u128,
+}
+
+impl PlayerIdentity {
+    /// Reads the identity from disk or creates and stores a new one. The file holds the id
+    /// and the secret in hex; files written before there was a secret get one added.
+    pub fn load_or_create() -> Self {
+        let path = data_dir().join(IDENTITY_FILE);
+
+        let mut id = None;
+        if let Ok(contents) = std::fs::read_to_string(&path) {
+            let mut parts = contents.split_whitespace();
+            match parts.next().map(str::parse::<u64>) {
+                Some(Ok(stored)) if stored != 0 => {
+                    let secret = parts
+                        .next()
+                        .and_then(|secret| u128::from_str_radix(secret, 16).ok())
+                        .filter(|&secret| secret != 0);
+                    if let Some(secret) = secret {
+                        return Self { id: stored, secret };
+                    }
+                    id = Some(stored);
+                }
+                _ => eprintln!("Ignoring invalid identity file {}", path.display()),
+            }
+        }
+
+        let identity = Self {
+            id: id.unwrap_or_else(|| rand::random::<u64>().max(1)),
+            secret: rand::random::<u128>().max(1),
+        };
+        if let Err(e) = std::fs::create_dir_all(data_dir()).and_then(|_| {
+            std::fs::write(&path, format!("{} {:032x}", identity.id, identity.secret))
+        }) {
+            eprintln!("Cannot store identity in {}: {}", path.display(), e);
+        }
+        identity
+    }
+
+    /// User data sent with the connection request.
+    pub fn user_data(self, protocol_version: &ProtocolVersion, name: &str) -> ConnectUserData {
+        ConnectUserData {
+            identity: Some(self.id),
+            protocol_hash: protocol_version.hash,
+            secret: self.secret,
+            name: name.trim().to_string(),
+        }
+    }
+}
+
+/// Per-user directory for files the client keeps between runs.
+/// `ZOMBRISE_HOME` overrides it, e.g. to run two clients with separate identities.
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("ZOMBRISE_HOME") {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return PathBuf::from(dir).join("zombrise");
+    }
+    if let Some(dir) = std::env::var_os("APPDATA") {
+        return PathBuf::from(dir).join("zombrise");
+    }
+    if let Some(dir) = std::env::var_os("HOME") {
+        return PathBuf::from(dir).join(".config").join("zombrise");
+    }
+    PathBuf::from(".")
+}
@@ -0,0 +1,311 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use zombrise_shared::players::player::{Player, PlayerOwner, ViewTime};
+use zombrise_shared::replication::transform::NetTransform;
+use zombrise_shared::shared::ServerTime;
+use zombrise_shared::zombie::zombie::{Spit, Zombie};
+
+use crate::MyClientId;
+
+/// Delay used when `ZOMBRISE_INTERPOLATION_DELAY` isn't set, in milliseconds.
+const DEFAULT_DELAY_MS: u64 = 100;
+/// Snapshots kept per entity, only reached when the delay is very long.
+const MAX_SNAPSHOTS: usize = 32;
+/// Moves longer than this between two snapshots are teleports (respawns) and not interpolated.
+const TELEPORT_DISTANCE: f32 = 5.0;
+/// Seconds without a snapshot while other entities got some after which an entity
+/// counts as standing still. Shorter gaps happen when an update is split over packets.
+const STILL_AFTER: f64 = 0.1;
+/// Clock offsets further off than this are taken over at once, e.g. after joining another server.
+const CLOCK_RESYNC: f64 = 1.0;
+/// Share of the difference to a new clock sample taken over.
+const CLOCK_SMOOTHING: f64 = 0.1;
+
+/// How remote players and zombies are smoothed.
+#[derive(Resource)]
+pub struct InterpolationSettings {
+    /// How far behind the estimated server time remote entities are shown, in seconds. Longer
+    /// delays hide more jitter and loss, at the cost of seeing everyone later.
+    pub delay: f32,
+    /// How long entities keep moving along their last velocity when snapshots are late.
+    pub max_extrapolation: f32,
+    /// Draws the snapshot buffers, toggled with F3.
+    pub debug: bool,
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        let delay_ms = std::env::var("ZOMBRISE_INTERPOLATION_DELAY")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_DELAY_MS);
+        Self {
+            delay: delay_ms as f32 / 1000.0,
+            max_extrapolation: 0.25,
+            debug: false,
+        }
+    }
+}
+
+/// `ServerTime` of the last snapshot of any entity. Replication only sends transforms that
+/// changed, so an entity without news while others got some is standing still, not late.
+#[derive(Resource, Default)]
+pub struct SnapshotClock {
+    latest: f64,
+}
+
+/// Estimated `ServerTime` minus our `Time::elapsed_secs_f64`, which includes the latency.
+/// Smoothed, since single updates arrive early or late.
+#[derive(Resource, Default)]
+pub struct ServerClock {
+    offset: Option<f64>,
+}
+
+struct Snapshot {
+    /// `ServerTime` the snapshot was taken at. Using the server's clock instead of when it
+    /// arrived keeps network jitter out of the movement.
+    time: f64,
+    translation: Vec3,
+    rotation: Quat,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum BufferState {
+    #[default]
+    Interpolating,
+    Extrapolating,
+    /// Past the last snapshot of an entity that stopped moving.
+    Holding,
+}
+
+/// Replicated transforms of a remote entity, which is shown at `ViewTime`, the estimated
+/// server time minus `InterpolationSettings::delay`.
+#[derive(Component, Default)]
+pub struct SnapshotBuffer {
+    /// Oldest first.
+    snapshots: VecDeque<Snapshot>,
+    state: BufferState,
+}
+
+/// Gives remote players, zombies and spits a snapshot buffer. Our own player is predicted
+/// instead.
+pub fn add_snapshot_buffers(
+    mut commands: Commands,
+    query: Query<
+        (Entity, Option<&PlayerOwner>),
+        (
+            Or<(Added<Player>, Added<Zombie>, Added<Spit>)>,
+            Without<SnapshotBuffer>,
+        ),
+    >,
+    my_client_id: Res<MyClientId>,
+) {
+    for (entity, owner) in &query {
+        if owner.is_some_and(|owner| owner.0 == my_client_id.0) {
+            continue;
+        }
+        commands.entity(entity).insert(SnapshotBuffer::default());
+    }
+}
+
+/// Records arriving snapshots and places every buffered entity where it was at `ViewTime`.
+pub fn interpolate_remote_entities(
+    mut query: Query<(&mut Transform, Ref<NetTransform>, &mut SnapshotBuffer)>,
+    server_time_query: Query<&ServerTime>,
+    settings: Res<InterpolationSettings>,
+    view_time: Res<ViewTime>,
+    mut clock: ResMut<SnapshotClock>,
+) {
+    // The server time arrives with every update, so it tells when the transforms were taken
+    let Some(server_time) = server_time_query.iter().next() else {
+        return;
+    };
+    let snapshot_time = server_time.0;
+    let mut received = false;
+
+    for (_, net_transform, mut buffer) in &mut query {
+        if !net_transform.is_changed() {
+            continue;
+        }
+        received = true;
+
+        // After standing still, start from where the entity rested when the others last
+        // got news, instead of stretching one step over the whole pause
+        if let Some(last) = buffer.snapshots.back() {
+            if clock.latest - last.time > STILL_AFTER {
+                let rest = Snapshot {
+                    time: clock.latest,
+                    translation: last.translation,
+                    rotation: last.rotation,
+                };
+                buffer.snapshots.push_back(rest);
+            }
+        }
+        buffer.snapshots.push_back(Snapshot {
+            time: snapshot_time,
+            translation: net_transform.translation(),
+            rotation: net_transform.rotation(),
+        });
+        if buffer.snapshots.len() > MAX_SNAPSHOTS {
+            buffer.snapshots.pop_front();
+        }
+    }
+    if received {
+        clock.latest = snapshot_time;
+    }
+
+    let Some(render_time) = view_time.0 else {
+        return;
+    };
+    for (mut transform, _, mut buffer) in &mut query {
+        // Keep one snapshot at or before the render time to interpolate from
+        while buffer.snapshots.len() > 2 && buffer.snapshots[1].time <= render_time {
+            buffer.snapshots.pop_front();
+        }
+
+        let Some((translation, rotation, state)) =
+            sample(&buffer.snapshots, render_time, clock.latest, &settings)
+        else {
+            continue;
+        };
+        buffer.state = state;
+
+        transform.translation = translation;
+        transform.rotation = rotation;
+    }
+}
+
+/// Position and rotation at `render_time`, `None` without snapshots.
+fn sample(
+    snapshots: &VecDeque<Snapshot>,
+    render_time: f64,
+    latest_snapshot: f64,
+    settings: &InterpolationSettings,
+) -> Option<(Vec3, Quat, BufferState)> {
+    let last = snapshots.back()?;
+
+    if let Some(index) = snapshots
+        .iter()
+        .position(|snapshot| snapshot.time > render_time)
+    {
+        let to = &snapshots[index];
+        let Some(from) = index.checked_sub(1).map(|index| &snapshots[index]) else {
+            // Still before the first snapshot
+            return Some((to.translation, to.rotation, BufferState::Interpolating));
+        };
+        if from.translation.distance(to.translation) > TELEPORT_DISTANCE {
+            return Some((to.translation, to.rotation, BufferState::Interpolating));
+        }
+
+        let t = ((render_time - from.time) / (to.time - from.time)) as f32;
+        return Some((
+            from.translation.lerp(to.translation, t),
+            from.rotation.slerp(to.rotation, t),
+            BufferState::Interpolating,
+        ));
+    }
+
+    // Past the last snapshot: hold still entities, keep late ones moving for a moment
+    if latest_snapshot > last.time || snapshots.len() < 2 {
+        return Some((last.translation, last.rotation, BufferState::Holding));
+    }
+    let previous = &snapshots[snapshots.len() - 2];
+    let step = (last.time - previous.time) as f32;
+    if step <= 0.0 || previous.translation.distance(last.translation) > TELEPORT_DISTANCE {
+        return Some((last.translation, last.rotation, BufferState::Holding));
+    }
+    let velocity = (last.translation - previous.translation) / step;
+    let ahead = ((render_time - last.time) as f32).min(settings.max_extrapolation);
+    Some((
+        last.translation + velocity * ahead,
+        last.rotation,
+        BufferState::Extrapolating,
+    ))
+}
+
+/// Keeps `ViewTime` at the server moment remote entities are shown at, the estimated
+/// server time minus `InterpolationSettings::delay`.
+pub fn update_view_time(
+    server_time_query: Query<&ServerTime, Changed<ServerTime>>,
+    mut clock: ResMut<ServerClock>,
+    mut view_time: ResMut<ViewTime>,
+    settings: Res<InterpolationSettings>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs_f64();
+    for server_time in &server_time_query {
+        let sample = server_time.0 - now;
+        clock.offset = Some(match clock.offset {
+            Some(offset) if (sample - offset).abs() < CLOCK_RESYNC => {
+                offset + (sample - offset) * CLOCK_SMOOTHING
+            }
+            _ => sample,
+        });
+    }
+    view_time.0 = clock
+        .offset
+        .map(|offset| now + offset - settings.delay as f64);
+}
+
+pub fn reset_server_clock(
+    mut clock: ResMut<ServerClock>,
+    mut snapshot_clock: ResMut<SnapshotClock>,
+    mut view_time: ResMut<ViewTime>,
+) {
+    clock.offset = None;
+    snapshot_clock.latest = 0.0;
+    view_time.0 = None;
+}
+
+pub fn toggle_interpolation_debug(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<InterpolationSettings>,
+) {
+    if keys.just_pressed(KeyCode::F3) {
+        settings.debug = !settings.debug;
+        println!(
+            "Interpolation debug {} (delay {} ms)",
+            if settings.debug { "on" } else { "off" },
+            (settings.delay * 1000.0).round()
+        );
+    }
+}
+
+/// Draws the buffered snapshots of every remote entity as a trail, and where the entity
+/// is shown: green while interpolating, orange while extrapolating, grey while holding.
+pub fn draw_interpolation_debug(
+    query: Query<(&Transform, &SnapshotBuffer)>,
+    settings: Res<InterpolationSettings>,
+    mut gizmos: Gizmos,
+) {
+    if !settings.debug {
+        return;
+    }
+
+    let snapshot_color = Color::srgb(0.3, 0.6, 1.0);
+    for (transform, buffer) in &query {
+        let mut previous: Option<Vec3> = None;
+        for snapshot in &buffer.snapshots {
+            gizmos.sphere(
+                Isometry3d::from_translation(snapshot.translation),
+                0.08,
+                snapshot_color,
+            );
+            if let Some(previous) = previous {
+                gizmos.line(previous, snapshot.translation, snapshot_color);
+            }
+            previous = Some(snapshot.translation);
+        }
+
+        let shown_color = match buffer.state {
+            BufferState::Interpolating => Color::srgb(0.2, 0.9, 0.2),
+            BufferState::Extrapolating => Color::srgb(1.0, 0.6, 0.1),
+            BufferState::Holding => Color::srgb(0.6, 0.6, 0.6),
+        };
+        gizmos.sphere(
+            Isometry3d::from_translation(transform.translation),
+            0.15,
+            shown_color,
+        );
+    }
+}
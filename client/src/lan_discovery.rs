@@ -0,0 +1,240 @@
+use bevy::prelude::*;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use zombrise_shared::protocol::discovery::{
+    DiscoveryQuery, DiscoveryResponse, ServerInfo, DISCOVERY_PORT,
+};
+use zombrise_shared::shared::ProtocolVersion;
+
+use crate::profile::ServerAddress;
+use crate::startup_screen::{join_server, AppState, ConnectionError, ServerConfig};
+
+/// Seconds between discovery broadcasts.
+const QUERY_INTERVAL: f32 = 2.0;
+/// Servers that stop answering for this long are dropped from the list.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Game server that answered a discovery broadcast.
+pub struct LanServer {
+    /// Game address, the address the answer came from with the announced game port.
+    pub address: SocketAddr,
+    pub info: ServerInfo,
+    /// Round trip time of the last answer.
+    pub ping: Duration,
+    last_seen: Instant,
+}
+
+/// Broadcast socket, only open while the startup screen is shown.
+#[derive(Resource)]
+pub struct LanDiscovery {
+    socket: UdpSocket,
+    /// Nonce of the last query, answers to older ones are ignored.
+    nonce: u64,
+    sent_at: Instant,
+    /// Seconds until the next broadcast.
+    next_query: f32,
+}
+
+/// Servers found on the local network, sorted by name.
+#[derive(Resource, Default)]
+pub struct LanServers(pub Vec<LanServer>);
+
+#[derive(Component)]
+pub(crate) struct LanServerList;
+
+#[derive(Component)]
+pub(crate) struct LanServerButton(SocketAddr);
+
+pub fn start_lan_discovery(mut commands: Commands) {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).and_then(|socket| {
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    });
+    match socket {
+        Ok(socket) => commands.insert_resource(LanDiscovery {
+            socket,
+            nonce: rand::random(),
+            sent_at: Instant::now(),
+            next_query: 0.0,
+        }),
+        Err(e) => eprintln!("LAN discovery unavailable: {}", e),
+    }
+}
+
+pub fn stop_lan_discovery(mut commands: Commands, mut servers: ResMut<LanServers>) {
+    commands.remove_resource::<LanDiscovery>();
+    servers.0.clear();
+}
+
+/// Broadcasts discovery queries and collects the answers into `LanServers`.
+pub fn discover_lan_servers(
+    mut discovery: ResMut<LanDiscovery>,
+    mut servers: ResMut<LanServers>,
+    time: Res<Time>,
+) {
+    discovery.next_query -= time.delta_secs();
+    if discovery.next_query <= 0.0 {
+        discovery.next_query = QUERY_INTERVAL;
+        discovery.nonce = discovery.nonce.wrapping_add(1);
+        discovery.sent_at = Instant::now();
+        let query = DiscoveryQuery {
+            nonce: discovery.nonce,
+        };
+        // Fails without a network, there is nothing to find then
+        let _ = discovery
+            .socket
+            .send_to(&query.to_bytes(), (Ipv4Addr::BROADCAST, DISCOVERY_PORT));
+    }
+
+    let mut buffer = [0; 1024];
+    while let Ok((len, from)) = discovery.socket.recv_from(&mut buffer) {
+        let Some(response) = DiscoveryResponse::from_bytes(&buffer[..len]) else {
+            continue;
+        };
+        if response.nonce != discovery.nonce {
+            continue;
+        }
+
+        let address = SocketAddr::new(from.ip(), response.info.game_port);
+        let server = LanServer {
+            address,
+            info: response.info,
+            ping: discovery.sent_at.elapsed(),
+            last_seen: Instant::now(),
+        };
+        match servers.0.iter_mut().find(|known| known.address == address) {
+            Some(known) => *known = server,
+            None => {
+                servers.0.push(server);
+                servers.0.sort_by(|a, b| a.info.name.cmp(&b.info.name));
+            }
+        }
+    }
+
+    if servers
+        .0
+        .iter()
+        .any(|server| server.last_seen.elapsed() > SERVER_TIMEOUT)
+    {
+        servers
+            .0
+            .retain(|server| server.last_seen.elapsed() <= SERVER_TIMEOUT);
+    }
+}
+
+/// Rebuilds the server list on the startup screen whenever `LanServers` changes.
+pub fn update_lan_server_list(
+    mut commands: Commands,
+    servers: Res<LanServers>,
+    protocol_version: Res<ProtocolVersion>,
+    list_query: Query<Entity, With<LanServerList>>,
+    added_list_query: Query<(), Added<LanServerList>>,
+) {
+    if !servers.is_changed() && added_list_query.is_empty() {
+        return;
+    }
+    let Ok(list) = list_query.single() else {
+        return;
+    };
+
+    commands.entity(list).despawn_children();
+    commands.entity(list).with_children(|parent| {
+        if servers.0.is_empty() {
+            parent.spawn((
+                Text::new("Searching for servers on your network..."),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.6, 0.6, 0.65)),
+            ));
+        }
+
+        for server in &servers.0 {
+            let info = &server.info;
+            let compatible = info.protocol_hash == protocol_version.hash;
+            let mut label = format!(
+                "{}   {}/{} players   wave {}   {} ms",
+                info.name,
+                info.players,
+                info.max_players,
+                info.wave,
+                server.ping.as_millis()
+            );
+            if !compatible {
+                label.push_str(&format!("   (version {})", info.version));
+            }
+
+            parent
+                .spawn((
+                    Button,
+                    Node {
+                        width: Val::Percent(100.0),
+                        padding: UiRect::axes(Val::Px(10.0), Val::Px(8.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                    LanServerButton(server.address),
+                ))
+                .with_children(|button_parent| {
+                    button_parent.spawn((
+                        Text::new(label),
+                        TextFont {
+                            font_size: 18.0,
+                            ..default()
+                        },
+                        TextColor(if compatible {
+                            Color::srgb(0.9, 0.9, 0.9)
+                        } else {
+                            Color::srgb(0.9, 0.3, 0.3)
+                        }),
+                    ));
+                });
+        }
+    });
+}
+
+pub fn handle_lan_server_buttons(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &LanServerButton),
+        Changed<Interaction>,
+    >,
+    servers: Res<LanServers>,
+    mut server_config: ResMut<ServerConfig>,
+    mut connection_error: ResMut<ConnectionError>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = Color::srgb(0.15, 0.15, 0.2).into();
+                let info = servers
+                    .0
+                    .iter()
+                    .find(|listed| listed.address == button.0)
+                    .map(|listed| &listed.info);
+                // Secure servers are joined through their token service
+                let (address, secure) =
+                    info.map_or((button.0, false), |info| info.join_address(button.0));
+                let server = ServerAddress {
+                    address: address.to_string(),
+                    secure,
+                };
+                join_server(
+                    &server,
+                    info,
+                    &mut server_config,
+                    &mut connection_error,
+                    &mut next_state,
+                );
+            }
+            Interaction::Hovered => {
+                *color = Color::srgb(0.3, 0.3, 0.35).into();
+            }
+            Interaction::None => {
+                *color = Color::srgb(0.2, 0.2, 0.25).into();
+            }
+        }
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 use bevy::animation::{AnimationPlayer, AnimationTarget};
 use bevy::camera::primitives::Aabb;
 use bevy::ecs::hierarchy::ChildOf;
@@ -11,54 +13,100 @@ use bevy::prelude::*;
 use bevy::scene::SceneRoot;
 use bevy::window::{CursorGrabMode, CursorOptions, PresentMode, PrimaryWindow, WindowPlugin};
 use bevy_replicon::prelude::*;
-use bevy_replicon_renet2::{
-    netcode::{ClientAuthentication, NetcodeClientTransport},
-    renet2::{ConnectionConfig, RenetClient},
-    RenetChannelsExt, RepliconRenetPlugins,
-};
+use bevy_replicon_renet2::RepliconRenetPlugins;
 use bevy_simple_text_input::TextInputPlugin;
-use renet2_netcode::NativeSocket;
-use std::{
-    net::{SocketAddr, ToSocketAddrs, UdpSocket},
-    time::SystemTime,
-};
+use zombrise_shared::players::movement::INPUT_RATE;
 use zombrise_shared::players::player::{
-    handle_input, CameraRotation, DamageFlash, Health, MainCamera, Player, PlayerOwner,
+    handle_input, send_move_input, CameraRotation, DamageFlash, Health, InputSequence, MainCamera,
+    Player, PlayerOwner, ViewTime,
 };
+use zombrise_shared::replication::transform::apply_static_transforms;
 use zombrise_shared::shared::{MapMarker, SharedPlugin, TreeMarker};
 use zombrise_shared::zombie::zombie::{
-    add_zombie_animation_events, control_zombie_animation, handle_zombie_animation_events,
-    setup_zombie_animation, update_zombie_animation_state, Zombie, ZombieAnimationEvent,
-    ZombieAnimationEventsState,
+    control_zombie_animation, setup_zombie_animation, tint_zombie_materials,
+    update_zombie_animation_state, Spit, Zombie, ZombieAnimationConfig, ZombieKind, SPIT_RADIUS,
+};
+
+mod auth;
+
+mod chat;
+use chat::{
+    chat_closed, cleanup_chat_overlay, receive_chat_messages, spawn_chat_overlay, toggle_chat,
+    update_chat_lines, ChatInput, ChatLog,
+};
+
+mod connecting;
+use connecting::{
+    cleanup_connecting_screen, handle_cancel_connect, handle_disconnect, leave_game,
+    show_connecting_screen, spin_connecting_spinner, start_connection, update_connection,
+};
+
+mod favorites;
+use favorites::{handle_profile_buttons, update_profile_lists, FavoriteEditor};
+
+mod hit_feedback;
+use hit_feedback::{cleanup_hit_feedback, fade_hit_feedback, show_zombie_attack_hits};
+
+mod identity;
+use identity::PlayerIdentity;
+
+mod interpolation;
+use interpolation::{
+    add_snapshot_buffers, draw_interpolation_debug, interpolate_remote_entities,
+    reset_server_clock, toggle_interpolation_debug, update_view_time, InterpolationSettings,
+    ServerClock, SnapshotClock,
+};
+
+mod lan_discovery;
+use lan_discovery::{
+    discover_lan_servers, handle_lan_server_buttons, start_lan_discovery, stop_lan_discovery,
+    update_lan_server_list, LanDiscovery, LanServers,
 };
 
 mod map;
 use map::{spawn_snow_landscape, SnowLandscapeConfig};
 
+mod nameplates;
+use nameplates::{cleanup_nameplates, spawn_nameplates, update_nameplates};
+
+mod prediction;
+use prediction::{
+    predict_local_player, reconcile_local_player, reset_prediction, send_idle_input,
+    show_local_player, Prediction,
+};
+
+mod profile;
+use profile::UserProfile;
+
+mod server_browser;
+use server_browser::{
+    close_server_browser, handle_browser_buttons, open_server_browser, ping_listed_servers,
+    poll_server_list, update_name_filter, update_server_table, BrowserPing, BrowserView,
+    MasterServerAddress, ServerBrowser,
+};
+
 mod startup_screen;
 use startup_screen::{
-    cleanup_startup_screen, handle_copy_paste, handle_quick_connect_buttons, handle_startup_ui,
-    show_startup_screen, AppState, ServerConfig,
+    cleanup_startup_screen, focus_text_inputs, handle_copy_paste, handle_quick_connect_buttons,
+    handle_secure_toggle, handle_server_list_tabs, handle_startup_ui, show_startup_screen,
+    update_player_name, AppState, ConnectionError, ServerConfig, ServerListTab,
 };
 
-mod death_screen;
-use death_screen::{detect_player_death, handle_death_screen_input, show_death_screen, PlayerDied};
+mod wave_hud;
+use wave_hud::{cleanup_wave_hud, display_wave_hud};
 
-fn client_event_system(client: Res<RenetClient>, mut player_died: ResMut<PlayerDied>) {
-    if client.is_disconnected() {
-        if !player_died.0 {
-            println!("Client disconnected");
-            player_died.0 = true;
-        }
-    } else if player_died.0 {
-        player_died.0 = false;
-    }
-}
+mod death_screen;
+use death_screen::{
+    detect_player_death, handle_death_screen_input, handle_respawn_button, show_death_screen,
+    update_respawn_countdown, DeathScreenMarker, PlayerDied,
+};
 
 #[derive(Resource)]
 pub struct MyClientId(pub u64);
 
 fn main() {
+    let profile = UserProfile::load();
+
     App::new()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -72,14 +120,30 @@ fn main() {
         .add_plugins(SharedPlugin)
         .add_plugins(TextInputPlugin)
         .init_state::<AppState>()
-        .init_resource::<ServerConfig>()
+        .insert_resource(Time::<Fixed>::from_hz(INPUT_RATE))
+        .insert_resource(ServerConfig::from_profile(&profile))
+        .insert_resource(profile)
+        .init_resource::<ConnectionError>()
+        .init_resource::<ServerListTab>()
+        .init_resource::<FavoriteEditor>()
+        .init_resource::<LanServers>()
+        .init_resource::<MasterServerAddress>()
+        .init_resource::<ServerBrowser>()
+        .init_resource::<BrowserView>()
+        .insert_resource(PlayerIdentity::load_or_create())
         .insert_resource(CameraRotation {
             yaw: 0.0,
             pitch: -0.3,
         })
         .init_resource::<PlayerDied>()
-        .init_resource::<ZombieAnimationEventsState>()
-        .add_message::<ZombieAnimationEvent>()
+        .init_resource::<ChatLog>()
+        .init_resource::<ChatInput>()
+        .init_resource::<InputSequence>()
+        .init_resource::<Prediction>()
+        .init_resource::<InterpolationSettings>()
+        .init_resource::<SnapshotClock>()
+        .init_resource::<ServerClock>()
+        .init_resource::<ViewTime>()
         .add_systems(Startup, setup_camera)
         .register_type::<Transform>()
         .register_type::<GlobalTransform>()
@@ -102,104 +166,152 @@ fn main() {
         .register_type::<GltfSceneExtras>()
         .register_type::<GltfMeshExtras>()
         .register_type::<GltfMaterialExtras>()
-        .add_systems(OnEnter(AppState::StartupScreen), show_startup_screen)
-        .add_systems(OnExit(AppState::StartupScreen), cleanup_startup_screen)
+        .add_systems(
+            OnEnter(AppState::StartupScreen),
+            (
+                show_startup_screen,
+                start_lan_discovery,
+                open_server_browser,
+            ),
+        )
+        .add_systems(
+            OnExit(AppState::StartupScreen),
+            (
+                cleanup_startup_screen,
+                stop_lan_discovery,
+                close_server_browser,
+            ),
+        )
         .add_systems(
             Update,
             (
                 handle_startup_ui,
                 handle_copy_paste,
                 handle_quick_connect_buttons,
+                handle_secure_toggle,
+                discover_lan_servers.run_if(resource_exists::<LanDiscovery>),
+                update_lan_server_list.after(discover_lan_servers),
+                handle_lan_server_buttons,
+                focus_text_inputs,
+                poll_server_list,
+                ping_listed_servers.run_if(resource_exists::<BrowserPing>),
+                update_name_filter,
+                update_server_table
+                    .after(poll_server_list)
+                    .after(ping_listed_servers)
+                    .after(update_name_filter),
+                handle_browser_buttons,
+                handle_server_list_tabs,
+                update_profile_lists,
+                handle_profile_buttons,
+                update_player_name,
             )
                 .run_if(in_state(AppState::StartupScreen)),
         )
+        .add_systems(
+            OnEnter(AppState::Connecting),
+            (show_connecting_screen, start_connection),
+        )
+        .add_systems(OnExit(AppState::Connecting), cleanup_connecting_screen)
+        .add_systems(
+            Update,
+            (
+                update_connection,
+                handle_cancel_connect,
+                spin_connecting_spinner,
+            )
+                .run_if(in_state(AppState::Connecting)),
+        )
         .add_systems(
             OnEnter(AppState::Playing),
-            (setup, setup_client, lock_cursor, activate_game_cameras),
+            (
+                setup,
+                lock_cursor,
+                activate_game_cameras,
+                spawn_chat_overlay,
+            ),
+        )
+        .add_systems(
+            OnExit(AppState::Playing),
+            (
+                cleanup_playing_state,
+                cleanup_wave_hud,
+                cleanup_nameplates,
+                cleanup_chat_overlay,
+                cleanup_hit_feedback,
+                reset_prediction,
+                reset_server_clock,
+                leave_game,
+            ),
         )
-        .add_systems(OnExit(AppState::Playing), cleanup_playing_state)
         .add_systems(
             Update,
             (
-                client_event_system,
-                handle_input,
+                handle_disconnect,
+                handle_input.run_if(chat_closed),
                 handle_camera_rotation,
-                camera_follow,
+                camera_follow.after(show_local_player),
                 spawn_player_visuals,
                 spawn_map_visuals,
                 spawn_zombie_visuals,
                 setup_zombie_animation,
                 update_zombie_animation_state,
                 control_zombie_animation,
-                add_zombie_animation_events,
-                handle_zombie_animation_events,
                 spawn_tree_visuals,
                 animate_player_damage,
                 display_health_bar,
                 detect_player_death,
                 show_death_screen,
-                handle_death_screen_input,
-                handle_escape_key,
-                handle_lock_key,
+                handle_death_screen_input.run_if(chat_closed),
+                handle_escape_key.run_if(chat_closed),
+                handle_lock_key.run_if(chat_closed),
+            )
+                .run_if(in_state(AppState::Playing)),
+        )
+        .add_systems(
+            Update,
+            (
+                tint_zombie_materials,
+                update_respawn_countdown,
+                handle_respawn_button.run_if(chat_closed),
+                display_wave_hud,
+                spawn_nameplates,
+                update_nameplates.after(spawn_nameplates),
+                receive_chat_messages,
+                update_chat_lines.after(receive_chat_messages),
+                reconcile_local_player,
+                show_local_player.after(reconcile_local_player),
+                add_snapshot_buffers,
+                interpolate_remote_entities
+                    .after(add_snapshot_buffers)
+                    .after(update_view_time),
+                toggle_interpolation_debug.run_if(chat_closed),
+                draw_interpolation_debug.after(interpolate_remote_entities),
+                update_view_time.before(handle_input),
+                apply_static_transforms,
+                spawn_spit_visuals,
+            )
+                .run_if(in_state(AppState::Playing)),
+        )
+        .add_systems(
+            Update,
+            (show_zombie_attack_hits, fade_hit_feedback).run_if(in_state(AppState::Playing)),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                send_move_input.run_if(chat_closed),
+                send_idle_input.run_if(not(chat_closed)),
+                predict_local_player
+                    .after(send_move_input)
+                    .after(send_idle_input),
             )
                 .run_if(in_state(AppState::Playing)),
         )
+        .add_systems(PostUpdate, toggle_chat.run_if(in_state(AppState::Playing)))
         .run();
 }
 
-fn setup_client(
-    mut commands: Commands,
-    network_channels: Res<RepliconChannels>,
-    server_config: Res<ServerConfig>,
-) {
-    let server_channels_config = network_channels.server_configs();
-    let client_channels_config = network_channels.client_configs();
-
-    let client = RenetClient::new(
-        ConnectionConfig {
-            server_channels_config,
-            client_channels_config,
-            available_bytes_per_tick: 16 * 1024,
-        },
-        false,
-    );
-
-    let current_time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
-    let client_id = current_time.as_millis() as u64;
-
-    let server_addr: SocketAddr = server_config
-        .url
-        .to_socket_addrs()
-        .expect("Failed to resolve server address")
-        .find(|addr| addr.is_ipv4()) // Prefer IPv4
-        .or_else(|| {
-            // Fallback to any address if no IPv4 found
-            server_config.url.to_socket_addrs().ok()?.next()
-        })
-        .expect("No address found for server");
-
-    println!("Connecting to server at: {}", server_addr);
-
-    let authentication = ClientAuthentication::Unsecure {
-        client_id,
-        protocol_id: 0,
-        server_addr,
-        socket_id: 0,
-        user_data: None,
-    };
-
-    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
-    let native_socket = NativeSocket::new(socket).unwrap();
-    let transport =
-        NetcodeClientTransport::new(current_time, authentication, native_socket).unwrap();
-
-    commands.insert_resource(client);
-    commands.insert_resource(transport);
-    commands.insert_resource(MyClientId(client_id));
-}
-
 fn setup_camera(mut commands: Commands) {
     println!("=== SETUP_CAMERA CALLED ===");
 
@@ -268,10 +380,13 @@ fn setup(mut commands: Commands) {
 fn cleanup_playing_state(
     mut commands: Commands,
     health_ui_query: Query<Entity, With<HealthBarUI>>,
+    death_screen_query: Query<Entity, With<DeathScreenMarker>>,
+    mut player_died: ResMut<PlayerDied>,
 ) {
-    for entity in health_ui_query.iter() {
+    for entity in health_ui_query.iter().chain(death_screen_query.iter()) {
         commands.entity(entity).despawn();
     }
+    player_died.0 = false;
 }
 
 fn spawn_map_visuals(
@@ -386,10 +501,12 @@ fn spawn_player_visuals(
 
 fn spawn_zombie_visuals(
     mut commands: Commands,
-    query: Query<Entity, (Added<Zombie>, Without<ZombieVisualsSpawned>)>,
+    query: Query<(Entity, Option<&ZombieKind>), (Added<Zombie>, Without<ZombieVisualsSpawned>)>,
     asset_server: Res<AssetServer>,
 ) {
-    for entity in query.iter() {
+    for (entity, kind) in query.iter() {
+        let config = ZombieAnimationConfig::for_kind(kind.copied().unwrap_or_default());
+
         commands.entity(entity).insert((
             ZombieVisualsSpawned,
             Visibility::default(),
@@ -399,17 +516,37 @@ fn spawn_zombie_visuals(
 
         commands.entity(entity).with_children(|parent| {
             parent.spawn((
-                SceneRoot(asset_server.load("zombie.glb#Scene0")),
+                SceneRoot(asset_server.load(config.model_path)),
                 Visibility::default(),
                 InheritedVisibility::default(),
                 ViewVisibility::default(),
-                Transform::from_rotation(Quat::from_rotation_y(std::f32::consts::PI)),
+                Transform::from_rotation(Quat::from_rotation_y(std::f32::consts::PI))
+                    .with_scale(Vec3::splat(config.scale)),
                 GlobalTransform::default(),
             ));
         });
     }
 }
 
+fn spawn_spit_visuals(
+    mut commands: Commands,
+    query: Query<Entity, Added<Spit>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for entity in &query {
+        // Smaller than the radius it hits within, dodging a spit should feel fair
+        commands.entity(entity).insert((
+            Mesh3d(meshes.add(Sphere::new(SPIT_RADIUS * 0.4))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgb(0.5, 0.9, 0.3),
+                emissive: LinearRgba::rgb(0.3, 0.8, 0.1),
+                ..default()
+            })),
+        ));
+    }
+}
+
 fn camera_follow(
     player_query: Query<(&Transform, &PlayerOwner), (With<Player>, Without<MainCamera>)>,
     mut camera_query: Query<&mut Transform, With<MainCamera>>,
@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+use zombrise_shared::players::player::{Dead, Health, MainCamera, Player, PlayerName, PlayerOwner};
+
+use crate::MyClientId;
+
+const NAMEPLATE_WIDTH: f32 = 120.0;
+/// Height above the player's origin the nameplate is pinned to.
+const NAMEPLATE_HEIGHT: f32 = 1.4;
+/// On-screen height of the name and the health bar together.
+const NAMEPLATE_PIXEL_HEIGHT: f32 = 26.0;
+
+/// Name and health shown above another player, kept facing the screen by placing it
+/// in UI space at the player's projected position every frame.
+#[derive(Component)]
+pub struct Nameplate {
+    player: Entity,
+    text: Entity,
+    health_fill: Entity,
+}
+
+/// Adds a nameplate to every player except our own.
+pub fn spawn_nameplates(
+    mut commands: Commands,
+    player_query: Query<(Entity, &PlayerName, &PlayerOwner), Added<PlayerName>>,
+    nameplate_query: Query<&Nameplate>,
+    my_client_id: Res<MyClientId>,
+) {
+    for (player, name, owner) in &player_query {
+        if owner.0 == my_client_id.0 || nameplate_query.iter().any(|plate| plate.player == player) {
+            continue;
+        }
+
+        let mut text = Entity::PLACEHOLDER;
+        let mut health_fill = Entity::PLACEHOLDER;
+        let mut nameplate = commands.spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Px(NAMEPLATE_WIDTH),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(2.0),
+                ..default()
+            },
+            Visibility::Hidden,
+        ));
+        nameplate.with_children(|parent| {
+            text = parent
+                .spawn((
+                    Text::new(name.0.clone()),
+                    TextFont {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(1.0, 1.0, 1.0)),
+                ))
+                .id();
+
+            parent
+                .spawn((
+                    Node {
+                        width: Val::Px(NAMEPLATE_WIDTH * 0.6),
+                        height: Val::Px(5.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.2, 0.2, 0.2).into()),
+                ))
+                .with_children(|parent| {
+                    health_fill = parent
+                        .spawn((
+                            Node {
+                                width: Val::Percent(100.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            BackgroundColor(Color::srgb(0.2, 0.8, 0.2).into()),
+                        ))
+                        .id();
+                });
+        });
+        nameplate.insert(Nameplate {
+            player,
+            text,
+            health_fill,
+        });
+    }
+}
+
+/// Moves nameplates above their players and updates name and health. Nameplates of
+/// players that left are removed.
+pub fn update_nameplates(
+    mut commands: Commands,
+    mut nameplate_query: Query<(Entity, &Nameplate, &mut Node, &mut Visibility)>,
+    player_query: Query<(&GlobalTransform, &PlayerName, &Health, Has<Dead>), With<Player>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut text_query: Query<&mut Text>,
+    mut fill_query: Query<&mut Node, Without<Nameplate>>,
+) {
+    let Ok((camera, camera_transform)) = camera_query.single() else {
+        return;
+    };
+
+    for (entity, nameplate, mut node, mut visibility) in &mut nameplate_query {
+        let Ok((transform, name, health, dead)) = player_query.get(nameplate.player) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+
+        let position = transform.translation() + Vec3::Y * NAMEPLATE_HEIGHT;
+        // Projecting fails for players behind the camera
+        let screen_position = match camera.world_to_viewport(camera_transform, position) {
+            Ok(screen_position) if !dead => screen_position,
+            _ => {
+                visibility.set_if_neq(Visibility::Hidden);
+                continue;
+            }
+        };
+        visibility.set_if_neq(Visibility::Inherited);
+        // Centered on the point, with the health bar resting on it
+        node.left = Val::Px(screen_position.x - NAMEPLATE_WIDTH / 2.0);
+        node.top = Val::Px(screen_position.y - NAMEPLATE_PIXEL_HEIGHT);
+
+        if let Ok(mut text) = text_query.get_mut(nameplate.text) {
+            if text.0 != name.0 {
+                text.0 = name.0.clone();
+            }
+        }
+        if let Ok(mut fill) = fill_query.get_mut(nameplate.health_fill) {
+            let health_percent = (health.current / health.max * 100.0).clamp(0.0, 100.0);
+            fill.width = Val::Percent(health_percent);
+        }
+    }
+}
+
+pub fn cleanup_nameplates(mut commands: Commands, nameplate_query: Query<Entity, With<Nameplate>>) {
+    for entity in nameplate_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use zombrise_shared::players::movement::player_movement;
+use zombrise_shared::players::player::{
+    CameraRotation, Dead, Health, InputAck, InputSequence, MovePlayer, Player, PlayerOwner,
+};
+use zombrise_shared::replication::transform::NetTransform;
+
+use crate::MyClientId;
+
+/// Inputs kept for replaying. The server acknowledges inputs every tick, so this is only
+/// reached when it stops answering.
+const MAX_PENDING_INPUTS: usize = 256;
+/// Corrections longer than this are applied at once, shorter ones are blended in.
+const SNAP_DISTANCE: f32 = 1.0;
+/// Share of a small correction applied per reconciliation.
+const CORRECTION_RATE: f32 = 0.3;
+
+/// An input sent to the server, with the velocity predicted from it and how long it was held.
+struct PendingInput {
+    sequence: u32,
+    velocity: Vec3,
+    duration: f32,
+}
+
+/// Prediction of our own player, which moves as soon as a key is pressed instead of
+/// waiting for the server. Other players are interpolated instead.
+#[derive(Resource, Default)]
+pub struct Prediction {
+    /// Inputs sent but not acknowledged yet, oldest first.
+    pending: VecDeque<PendingInput>,
+    /// Last position the server sent.
+    server_translation: Option<Vec3>,
+    /// Predicted position after the last fixed tick and the one before it, the `Transform`
+    /// is placed between the two so frames between ticks move smoothly.
+    predicted_translation: Vec3,
+    previous_translation: Vec3,
+    predicted_rotation: Option<Quat>,
+}
+
+/// Keeps sending standing still inputs while `send_move_input` is off for the chat,
+/// otherwise the server keeps walking with the keys held when the chat opened.
+pub fn send_idle_input(
+    mut move_events: MessageWriter<MovePlayer>,
+    mut sequence: ResMut<InputSequence>,
+    camera_rotation: Res<CameraRotation>,
+) {
+    move_events.write(MovePlayer {
+        sequence: sequence.advance(),
+        direction: Vec3::ZERO,
+        camera_yaw: camera_rotation.yaw,
+    });
+}
+
+/// Rebuilds the predicted position whenever the server's state arrives: the server's
+/// position plus every input it hasn't applied yet.
+pub fn reconcile_local_player(
+    mut prediction: ResMut<Prediction>,
+    player_query: Query<(Ref<NetTransform>, Ref<InputAck>, &PlayerOwner), With<Player>>,
+    my_client_id: Res<MyClientId>,
+) {
+    let Some((net_transform, ack, _)) = player_query
+        .iter()
+        .find(|(_, _, owner)| owner.0 == my_client_id.0)
+    else {
+        return;
+    };
+
+    let replicated = net_transform.is_changed();
+    // Nothing to blend from before the first position arrived
+    let first = prediction.server_translation.is_none();
+    if replicated {
+        prediction.server_translation = Some(net_transform.translation());
+    }
+    if !replicated && !ack.is_changed() {
+        return;
+    }
+
+    prediction.pending.retain(|input| input.sequence > ack.0);
+    let Some(server_translation) = prediction.server_translation else {
+        return;
+    };
+    let corrected = prediction
+        .pending
+        .iter()
+        .fold(server_translation, |position, input| {
+            position + input.velocity * input.duration
+        });
+
+    // Small differences come from the server stepping physics at its own rate,
+    // blending them in hides the jitter. Respawns and collisions snap.
+    let error = corrected - prediction.predicted_translation;
+    let mut translation = if first || error.length() > SNAP_DISTANCE {
+        corrected
+    } else {
+        prediction.predicted_translation + error * CORRECTION_RATE
+    };
+    // Falling and jumping aren't predicted
+    translation.y = corrected.y;
+
+    // Move the tick before along, so the correction doesn't show up as a step back
+    let shift = translation - prediction.predicted_translation;
+    prediction.previous_translation += shift;
+    prediction.predicted_translation = translation;
+}
+
+/// Moves the predicted position of our own player with the inputs sent this fixed tick,
+/// the same way the server will once they arrive.
+pub fn predict_local_player(
+    mut move_events: MessageReader<MovePlayer>,
+    mut prediction: ResMut<Prediction>,
+    player_query: Query<(&Health, Has<Dead>, &PlayerOwner), With<Player>>,
+    my_client_id: Res<MyClientId>,
+    time: Res<Time>,
+) {
+    let inputs: Vec<&MovePlayer> = move_events.read().collect();
+    prediction.previous_translation = prediction.predicted_translation;
+    if inputs.is_empty() {
+        return;
+    }
+    let Some((health, dead, _)) = player_query
+        .iter()
+        .find(|(_, _, owner)| owner.0 == my_client_id.0)
+    else {
+        return;
+    };
+    // The server still acknowledges inputs of dead players, but doesn't move them
+    let alive = !dead && health.current > 0.0;
+
+    for input in inputs {
+        let movement = player_movement(input);
+        let velocity = if alive { movement.velocity } else { Vec3::ZERO };
+
+        prediction.pending.push_back(PendingInput {
+            sequence: input.sequence,
+            velocity,
+            duration: time.delta_secs(),
+        });
+        if prediction.pending.len() > MAX_PENDING_INPUTS {
+            prediction.pending.pop_front();
+        }
+
+        prediction.predicted_translation += velocity * time.delta_secs();
+        if let Some(rotation) = movement.rotation.filter(|_| alive) {
+            prediction.predicted_rotation = Some(rotation);
+        }
+    }
+}
+
+/// Places our own player between the last two predicted ticks, by how far the frame is
+/// into the next tick.
+pub fn show_local_player(
+    prediction: Res<Prediction>,
+    mut player_query: Query<(&mut Transform, &NetTransform, &PlayerOwner), With<Player>>,
+    my_client_id: Res<MyClientId>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    if prediction.server_translation.is_none() {
+        return;
+    }
+    let Some((mut transform, net_transform, _)) = player_query
+        .iter_mut()
+        .find(|(_, _, owner)| owner.0 == my_client_id.0)
+    else {
+        return;
+    };
+
+    transform.translation = prediction.previous_translation.lerp(
+        prediction.predicted_translation,
+        fixed_time.overstep_fraction(),
+    );
+    transform.rotation = prediction
+        .predicted_rotation
+        .unwrap_or_else(|| net_transform.rotation());
+}
+
+pub fn reset_prediction(mut prediction: ResMut<Prediction>) {
+    *prediction = Prediction::default();
+}
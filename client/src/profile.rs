@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+use crate::identity::data_dir;
+
+const PROFILE_FILE: &str = "profile.ron";
+/// Connections kept in the history, older ones are forgotten.
+const MAX_HISTORY: usize = 10;
+
+/// Address as typed on the startup screen, plus how to connect to it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ServerAddress {
+    pub address: String,
+    /// `address` is a token service, see `ServerConfig::secure`.
+    #[serde(default)]
+    pub secure: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FavoriteServer {
+    pub name: String,
+    pub server: ServerAddress,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryEntry {
+    pub server: ServerAddress,
+    /// Unix time in seconds of the last successful connection.
+    pub connected_at: u64,
+}
+
+/// Everything the client remembers between runs besides the identity, stored as RON
+/// next to the identity file.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct UserProfile {
+    /// Display name sent when connecting, the server picks one if it is empty.
+    pub name: String,
+    pub favorites: Vec<FavoriteServer>,
+    /// Filled into the address field on startup.
+    pub last_server: Option<ServerAddress>,
+    /// Most recent first.
+    pub history: Vec<HistoryEntry>,
+}
+
+impl Default for UserProfile {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            favorites: vec![FavoriteServer {
+                name: "Zombrise public server".to_string(),
+                server: ServerAddress {
+                    address: "138.199.203.159:5000".to_string(),
+                    secure: false,
+                },
+            }],
+            last_server: None,
+            history: Vec::new(),
+        }
+    }
+}
+
+impl UserProfile {
+    /// Reads the profile, or starts a new one if there is none or it can't be read.
+    /// An unreadable profile is copied aside first so the next save doesn't lose it.
+    pub fn load() -> Self {
+        let path = data_dir().join(PROFILE_FILE);
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        ron::from_str(&contents).unwrap_or_else(|e| {
+            let backup = path.with_extension("ron.bak");
+            match std::fs::copy(&path, &backup) {
+                Ok(_) => eprintln!(
+                    "Ignoring invalid profile {}, kept a copy at {}: {}",
+                    path.display(),
+                    backup.display(),
+                    e
+                ),
+                Err(copy_error) => eprintln!(
+                    "Ignoring invalid profile {}: {} (cannot back it up: {})",
+                    path.display(),
+                    e,
+                    copy_error
+                ),
+            }
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) {
+        let path = data_dir().join(PROFILE_FILE);
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())
+            .and_then(|contents| {
+                std::fs::create_dir_all(data_dir())
+                    .and_then(|_| std::fs::write(&path, contents))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            eprintln!("Cannot save profile to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Remembers a successful connection as the last server and at the top of the history.
+    pub fn record_connection(&mut self, server: ServerAddress) {
+        let connected_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        self.history.retain(|entry| entry.server != server);
+        self.history.insert(
+            0,
+            HistoryEntry {
+                server: server.clone(),
+                connected_at,
+            },
+        );
+        self.history.truncate(MAX_HISTORY);
+        self.last_server = Some(server);
+        self.save();
+    }
+}
+
+/// How long ago a history entry was made, e.g. `5 min ago`.
+pub fn time_ago(connected_at: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let seconds = now.saturating_sub(connected_at);
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
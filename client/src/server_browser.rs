@@ -0,0 +1,667 @@
+use bevy::prelude::*;
+use bevy::tasks::{futures::check_ready, IoTaskPool, Task};
+use bevy_simple_text_input::{
+    TextInput, TextInputInactive, TextInputSettings, TextInputTextColor, TextInputTextFont,
+    TextInputValue,
+};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+use zombrise_shared::protocol::discovery::{DiscoveryQuery, DiscoveryResponse, ServerInfo};
+use zombrise_shared::protocol::master::{ServerList, ServerListRequest, MASTER_PORT};
+use zombrise_shared::shared::ProtocolVersion;
+
+use crate::profile::ServerAddress;
+use crate::startup_screen::{join_server, AppState, ConnectionError, ServerConfig};
+
+const MASTER_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Seconds between two pings of the listed servers.
+const PING_INTERVAL: f32 = 5.0;
+/// Choices of the ping filter in milliseconds, `None` shows every server.
+const MAX_PING_STEPS: [Option<u32>; 4] = [None, Some(50), Some(100), Some(200)];
+
+const NAME_WIDTH: f32 = 230.0;
+const PLAYERS_WIDTH: f32 = 90.0;
+const WAVE_WIDTH: f32 = 60.0;
+const PING_WIDTH: f32 = 80.0;
+const VERSION_WIDTH: f32 = 160.0;
+
+/// Master server the browser gets its list from. Set `ZOMBRISE_MASTER` to use another one.
+#[derive(Resource)]
+pub struct MasterServerAddress(pub String);
+
+impl Default for MasterServerAddress {
+    fn default() -> Self {
+        Self(
+            std::env::var("ZOMBRISE_MASTER")
+                .unwrap_or_else(|_| format!("127.0.0.1:{}", MASTER_PORT)),
+        )
+    }
+}
+
+/// Game server from the master server list.
+pub struct BrowserServer {
+    pub address: SocketAddr,
+    pub info: ServerInfo,
+    /// Round trip time of the last ping answer, `None` until the server answered.
+    pub ping: Option<Duration>,
+}
+
+#[derive(Resource, Default)]
+pub struct ServerBrowser {
+    pub servers: Vec<BrowserServer>,
+    /// Why the last refresh failed.
+    pub error: Option<String>,
+    fetch: Option<Task<Result<ServerList, String>>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrowserColumn {
+    Name,
+    Players,
+    Ping,
+    Version,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrowserFilter {
+    MaxPing,
+    HideFull,
+    SameVersion,
+}
+
+/// Sorting and filters of the browser table, kept between visits of the startup screen.
+#[derive(Resource)]
+pub struct BrowserView {
+    sort: BrowserColumn,
+    descending: bool,
+    /// Lowercase part of the name servers must contain.
+    name_filter: String,
+    max_ping: Option<u32>,
+    hide_full: bool,
+    same_version_only: bool,
+}
+
+impl Default for BrowserView {
+    fn default() -> Self {
+        Self {
+            sort: BrowserColumn::Ping,
+            descending: false,
+            name_filter: String::new(),
+            max_ping: None,
+            hide_full: false,
+            same_version_only: false,
+        }
+    }
+}
+
+impl BrowserView {
+    fn shows(&self, server: &BrowserServer, protocol_version: &ProtocolVersion) -> bool {
+        let info = &server.info;
+        if !info.name.to_lowercase().contains(&self.name_filter) {
+            return false;
+        }
+        if let Some(max_ping) = self.max_ping {
+            // Servers that never answered a ping can't be below the limit
+            if server
+                .ping
+                .is_none_or(|ping| ping.as_millis() > max_ping as u128)
+            {
+                return false;
+            }
+        }
+        if self.hide_full && info.is_full() {
+            return false;
+        }
+        !self.same_version_only || info.protocol_hash == protocol_version.hash
+    }
+
+    fn sort(&self, servers: &mut [&BrowserServer]) {
+        servers.sort_by(|a, b| {
+            let order = match self.sort {
+                BrowserColumn::Name => {
+                    let (a, b) = (a.info.name.to_lowercase(), b.info.name.to_lowercase());
+                    a.cmp(&b)
+                }
+                BrowserColumn::Players => a.info.players.cmp(&b.info.players),
+                BrowserColumn::Ping => {
+                    // Unanswered pings sort after every answered one
+                    let key = |server: &BrowserServer| (server.ping.is_none(), server.ping);
+                    key(a).cmp(&key(b))
+                }
+                BrowserColumn::Version => a.info.version.cmp(&b.info.version),
+            };
+            if self.descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+    }
+
+    fn filter_text(&self, filter: BrowserFilter) -> String {
+        match filter {
+            BrowserFilter::MaxPing => match self.max_ping {
+                Some(max_ping) => format!("Ping < {} ms", max_ping),
+                None => "Ping: any".to_string(),
+            },
+            BrowserFilter::HideFull if self.hide_full => "Full: hidden".to_string(),
+            BrowserFilter::HideFull => "Full: shown".to_string(),
+            BrowserFilter::SameVersion if self.same_version_only => "Versions: mine".to_string(),
+            BrowserFilter::SameVersion => "Versions: all".to_string(),
+        }
+    }
+
+    fn column_title(&self, column: BrowserColumn) -> String {
+        let title = match column {
+            BrowserColumn::Name => "Name",
+            BrowserColumn::Players => "Players",
+            BrowserColumn::Ping => "Ping",
+            BrowserColumn::Version => "Version",
+        };
+        match (self.sort == column, self.descending) {
+            (true, false) => format!("{} ^", title),
+            (true, true) => format!("{} v", title),
+            (false, _) => title.to_string(),
+        }
+    }
+}
+
+/// Socket the browser pings the listed servers' query ports with.
+#[derive(Resource)]
+pub struct BrowserPing {
+    socket: UdpSocket,
+    /// Nonce of the last round of pings, answers to older rounds are ignored.
+    nonce: u64,
+    sent_at: Instant,
+    /// Seconds until the next round of pings.
+    next_ping: f32,
+}
+
+#[derive(Component)]
+pub(crate) struct ServerBrowserTable;
+
+#[derive(Component)]
+pub(crate) struct BrowserNameFilter;
+
+#[derive(Component)]
+pub(crate) struct BrowserFilterText(BrowserFilter);
+
+#[derive(Component, Clone, Copy)]
+pub(crate) enum BrowserButton {
+    Refresh,
+    Sort(BrowserColumn),
+    Filter(BrowserFilter),
+    Join(SocketAddr),
+}
+
+/// Spawns the browser panel: filters, then a table filled by `update_server_table`.
+pub fn spawn_server_browser(parent: &mut ChildSpawnerCommands, view: &BrowserView) {
+    parent
+        .spawn(Node {
+            width: Val::Px(NAME_WIDTH + PLAYERS_WIDTH + WAVE_WIDTH + PING_WIDTH + VERSION_WIDTH),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(8.0),
+            ..default()
+        })
+        .with_children(|panel| {
+            panel
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    justify_content: JustifyContent::SpaceBetween,
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|header| {
+                    header.spawn((
+                        Text::new("Server Browser:"),
+                        TextFont {
+                            font_size: 24.0,
+                            ..default()
+                        },
+                        TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                    ));
+                    spawn_browser_button(header, BrowserButton::Refresh, "Refresh", 100.0);
+                });
+
+            panel
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(8.0),
+                    ..default()
+                })
+                .with_children(|filters| {
+                    filters.spawn((
+                        Node {
+                            width: Val::Px(200.0),
+                            height: Val::Px(36.0),
+                            align_items: AlignItems::Center,
+                            padding: UiRect::horizontal(Val::Px(8.0)),
+                            ..default()
+                        },
+                        BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                        TextInput,
+                        // Typing goes to the server address until this field is clicked
+                        TextInputInactive(true),
+                        Interaction::None,
+                        TextInputTextFont(TextFont {
+                            font_size: 16.0,
+                            ..default()
+                        }),
+                        TextInputTextColor(TextColor(Color::srgb(1.0, 1.0, 1.0))),
+                        TextInputValue(view.name_filter.clone()),
+                        TextInputSettings {
+                            retain_on_submit: true,
+                            ..default()
+                        },
+                        BrowserNameFilter,
+                    ));
+
+                    for filter in [
+                        BrowserFilter::MaxPing,
+                        BrowserFilter::HideFull,
+                        BrowserFilter::SameVersion,
+                    ] {
+                        filters
+                            .spawn((
+                                Button,
+                                Node {
+                                    width: Val::Px(130.0),
+                                    height: Val::Px(36.0),
+                                    align_items: AlignItems::Center,
+                                    justify_content: JustifyContent::Center,
+                                    ..default()
+                                },
+                                BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                                BrowserButton::Filter(filter),
+                            ))
+                            .with_children(|button_parent| {
+                                button_parent.spawn((
+                                    Text::new(view.filter_text(filter)),
+                                    TextFont {
+                                        font_size: 16.0,
+                                        ..default()
+                                    },
+                                    BrowserFilterText(filter),
+                                ));
+                            });
+                    }
+                });
+
+            panel.spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.0),
+                    max_height: Val::Px(220.0),
+                    overflow: Overflow::scroll_y(),
+                    ..default()
+                },
+                ServerBrowserTable,
+            ));
+        });
+}
+
+fn spawn_browser_button(
+    parent: &mut ChildSpawnerCommands,
+    button: BrowserButton,
+    label: &str,
+    width: f32,
+) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(width),
+                height: Val::Px(32.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+            button,
+        ))
+        .with_children(|button_parent| {
+            button_parent.spawn((
+                Text::new(label),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Asks the master server for the list and opens the socket used to ping the servers on it.
+pub fn open_server_browser(
+    mut commands: Commands,
+    mut browser: ResMut<ServerBrowser>,
+    master: Res<MasterServerAddress>,
+) {
+    start_fetch(&mut browser, &master);
+
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket));
+    match socket {
+        Ok(socket) => commands.insert_resource(BrowserPing {
+            socket,
+            nonce: rand::random(),
+            sent_at: Instant::now(),
+            next_ping: 0.0,
+        }),
+        Err(e) => eprintln!("Cannot ping servers: {}", e),
+    }
+}
+
+pub fn close_server_browser(mut commands: Commands, mut browser: ResMut<ServerBrowser>) {
+    browser.fetch = None;
+    commands.remove_resource::<BrowserPing>();
+}
+
+fn start_fetch(browser: &mut ServerBrowser, master: &MasterServerAddress) {
+    let address = master.0.clone();
+    browser.error = None;
+    browser.fetch = Some(IoTaskPool::get().spawn(async move { fetch_server_list(&address) }));
+}
+
+fn fetch_server_list(address: &str) -> Result<ServerList, String> {
+    let master_addr = address
+        .to_socket_addrs()
+        .map_err(|e| format!("Cannot resolve master server {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("No address found for master server {}", address))?;
+
+    let mut stream = TcpStream::connect_timeout(&master_addr, MASTER_REQUEST_TIMEOUT)
+        .map_err(|e| format!("Cannot reach master server {}: {}", master_addr, e))?;
+    stream
+        .set_read_timeout(Some(MASTER_REQUEST_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    stream
+        .write_all(&ServerListRequest.to_bytes())
+        .map_err(|e| format!("Cannot send request to {}: {}", master_addr, e))?;
+
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .map_err(|e| format!("Cannot read server list from {}: {}", master_addr, e))?;
+    ServerList::from_bytes(&response)
+        .ok_or_else(|| format!("Invalid server list from {}", master_addr))
+}
+
+/// Takes over the server list once the master server answered.
+pub fn poll_server_list(mut browser: ResMut<ServerBrowser>, ping: Option<ResMut<BrowserPing>>) {
+    let Some(fetch) = &mut browser.fetch else {
+        return;
+    };
+    let Some(result) = check_ready(fetch) else {
+        return;
+    };
+    browser.fetch = None;
+
+    match result {
+        Ok(list) => {
+            browser.servers = list
+                .servers
+                .into_iter()
+                .map(|server| BrowserServer {
+                    address: server.address,
+                    info: server.info,
+                    ping: None,
+                })
+                .collect();
+            // Ping the new servers right away
+            if let Some(mut ping) = ping {
+                ping.next_ping = 0.0;
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            browser.servers.clear();
+            browser.error = Some(e);
+        }
+    }
+}
+
+/// Measures the ping of every listed server with a discovery query to its query port.
+pub fn ping_listed_servers(
+    mut ping: ResMut<BrowserPing>,
+    mut browser: ResMut<ServerBrowser>,
+    time: Res<Time>,
+) {
+    ping.next_ping -= time.delta_secs();
+    if ping.next_ping <= 0.0 && !browser.servers.is_empty() {
+        ping.next_ping = PING_INTERVAL;
+        ping.nonce = ping.nonce.wrapping_add(1);
+        ping.sent_at = Instant::now();
+        let query = DiscoveryQuery { nonce: ping.nonce }.to_bytes();
+        for server in &browser.servers {
+            let _ = ping
+                .socket
+                .send_to(&query, (server.address.ip(), server.info.query_port));
+        }
+    }
+
+    let mut buffer = [0; 1024];
+    while let Ok((len, from)) = ping.socket.recv_from(&mut buffer) {
+        let Some(response) = DiscoveryResponse::from_bytes(&buffer[..len]) else {
+            continue;
+        };
+        if response.nonce != ping.nonce {
+            continue;
+        }
+        let address = SocketAddr::new(from.ip(), response.info.game_port);
+        if let Some(server) = browser.servers.iter_mut().find(|s| s.address == address) {
+            server.ping = Some(ping.sent_at.elapsed());
+            // Fresher than what the master server knew at its last heartbeat
+            server.info = response.info;
+        }
+    }
+}
+
+pub fn update_name_filter(
+    input_query: Query<&TextInputValue, (Changed<TextInputValue>, With<BrowserNameFilter>)>,
+    mut view: ResMut<BrowserView>,
+) {
+    if let Ok(value) = input_query.single() {
+        let name_filter = value.0.trim().to_lowercase();
+        if view.name_filter != name_filter {
+            view.name_filter = name_filter;
+        }
+    }
+}
+
+/// Rebuilds the table when the list, the pings, the sorting or the filters change.
+pub fn update_server_table(
+    mut commands: Commands,
+    browser: Res<ServerBrowser>,
+    view: Res<BrowserView>,
+    protocol_version: Res<ProtocolVersion>,
+    table_query: Query<Entity, With<ServerBrowserTable>>,
+    added_table_query: Query<(), Added<ServerBrowserTable>>,
+    mut filter_text_query: Query<(&mut Text, &BrowserFilterText)>,
+) {
+    if !browser.is_changed() && !view.is_changed() && added_table_query.is_empty() {
+        return;
+    }
+    let Ok(table) = table_query.single() else {
+        return;
+    };
+
+    for (mut text, filter_text) in &mut filter_text_query {
+        text.0 = view.filter_text(filter_text.0);
+    }
+
+    let mut servers: Vec<&BrowserServer> = browser
+        .servers
+        .iter()
+        .filter(|server| view.shows(server, &protocol_version))
+        .collect();
+    view.sort(&mut servers);
+
+    commands.entity(table).despawn_children();
+    commands.entity(table).with_children(|parent| {
+        parent
+            .spawn(Node {
+                flex_direction: FlexDirection::Row,
+                ..default()
+            })
+            .with_children(|header| {
+                for (column, width) in [
+                    (BrowserColumn::Name, NAME_WIDTH),
+                    (BrowserColumn::Players, PLAYERS_WIDTH + WAVE_WIDTH),
+                    (BrowserColumn::Ping, PING_WIDTH),
+                    (BrowserColumn::Version, VERSION_WIDTH),
+                ] {
+                    spawn_browser_button(
+                        header,
+                        BrowserButton::Sort(column),
+                        &view.column_title(column),
+                        width,
+                    );
+                }
+            });
+
+        let status = if let Some(error) = &browser.error {
+            Some(error.clone())
+        } else if browser.fetch.is_some() {
+            Some("Loading server list...".to_string())
+        } else if browser.servers.is_empty() {
+            Some("No servers are registered".to_string())
+        } else if servers.is_empty() {
+            Some("No servers match the filters".to_string())
+        } else {
+            None
+        };
+        if let Some(status) = status {
+            parent.spawn((
+                Text::new(status),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.6, 0.6, 0.65)),
+            ));
+        }
+
+        for server in servers {
+            let info = &server.info;
+            let compatible = info.protocol_hash == protocol_version.hash;
+            let text_color = if compatible {
+                Color::srgb(0.9, 0.9, 0.9)
+            } else {
+                Color::srgb(0.9, 0.3, 0.3)
+            };
+            let ping = match server.ping {
+                Some(ping) => format!("{} ms", ping.as_millis()),
+                None => "?".to_string(),
+            };
+
+            parent
+                .spawn((
+                    Button,
+                    Node {
+                        flex_direction: FlexDirection::Row,
+                        padding: UiRect::vertical(Val::Px(6.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                    BrowserButton::Join(server.address),
+                ))
+                .with_children(|row| {
+                    for (cell, width) in [
+                        (info.name.clone(), NAME_WIDTH),
+                        (
+                            format!("{}/{}", info.players, info.max_players),
+                            PLAYERS_WIDTH,
+                        ),
+                        (format!("wave {}", info.wave), WAVE_WIDTH),
+                        (ping, PING_WIDTH),
+                        (info.version.clone(), VERSION_WIDTH),
+                    ] {
+                        row.spawn((
+                            Text::new(cell),
+                            TextFont {
+                                font_size: 16.0,
+                                ..default()
+                            },
+                            TextColor(text_color),
+                            Node {
+                                width: Val::Px(width),
+                                padding: UiRect::horizontal(Val::Px(6.0)),
+                                ..default()
+                            },
+                        ));
+                    }
+                });
+        }
+    });
+}
+
+pub fn handle_browser_buttons(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &BrowserButton),
+        Changed<Interaction>,
+    >,
+    mut browser: ResMut<ServerBrowser>,
+    mut view: ResMut<BrowserView>,
+    master: Res<MasterServerAddress>,
+    mut server_config: ResMut<ServerConfig>,
+    mut connection_error: ResMut<ConnectionError>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = Color::srgb(0.15, 0.15, 0.2).into();
+                match *button {
+                    BrowserButton::Refresh => start_fetch(&mut browser, &master),
+                    BrowserButton::Sort(column) => {
+                        view.descending = view.sort == column && !view.descending;
+                        view.sort = column;
+                    }
+                    BrowserButton::Filter(BrowserFilter::MaxPing) => {
+                        let step = MAX_PING_STEPS
+                            .iter()
+                            .position(|max_ping| *max_ping == view.max_ping)
+                            .unwrap_or(0);
+                        view.max_ping = MAX_PING_STEPS[(step + 1) % MAX_PING_STEPS.len()];
+                    }
+                    BrowserButton::Filter(BrowserFilter::HideFull) => {
+                        view.hide_full = !view.hide_full;
+                    }
+                    BrowserButton::Filter(BrowserFilter::SameVersion) => {
+                        view.same_version_only = !view.same_version_only;
+                    }
+                    BrowserButton::Join(address) => {
+                        let Some(info) = browser
+                            .servers
+                            .iter()
+                            .find(|listed| listed.address == address)
+                            .map(|listed| &listed.info)
+                        else {
+                            continue;
+                        };
+                        // Secure servers are joined through their token service
+                        let (join_address, secure) = info.join_address(address);
+                        let server = ServerAddress {
+                            address: join_address.to_string(),
+                            secure,
+                        };
+                        join_server(
+                            &server,
+                            Some(info),
+                            &mut server_config,
+                            &mut connection_error,
+                            &mut next_state,
+                        );
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *color = Color::srgb(0.3, 0.3, 0.35).into();
+            }
+            Interaction::None => {
+                *color = Color::srgb(0.2, 0.2, 0.25).into();
+            }
+        }
+    }
+}
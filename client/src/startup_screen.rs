@@ -1,36 +1,72 @@
 use bevy::input::keyboard::KeyboardInput;
 use bevy::prelude::*;
 use bevy_simple_text_input::{
-    TextInput, TextInputSettings, TextInputTextColor, TextInputTextFont, TextInputValue,
+    TextInput, TextInputInactive, TextInputSettings, TextInputTextColor, TextInputTextFont,
+    TextInputValue,
 };
 
+use zombrise_shared::protocol::discovery::ServerInfo;
+
+use crate::favorites::{spawn_favorites_panel, spawn_history_panel};
+use crate::lan_discovery::LanServerList;
+use crate::profile::{ServerAddress, UserProfile};
+use crate::server_browser::{spawn_server_browser, BrowserView};
+
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub enum AppState {
     #[default]
     StartupScreen,
+    Connecting,
     Playing,
 }
 
 #[derive(Resource)]
 pub struct ServerConfig {
     pub url: String,
+    /// Treat `url` as a token service and connect with the connect token it hands out.
+    pub secure: bool,
+    /// What a server list said about the server, so other versions are refused before
+    /// connecting. `None` for typed addresses, favorites and history.
+    pub listed: Option<ServerInfo>,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             url: "127.0.0.1:5000".to_string(),
-            // url: "138.199.203.159:5000".to_string(),
+            secure: false,
+            listed: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Starts out with the server of the last successful connection.
+    pub fn from_profile(profile: &UserProfile) -> Self {
+        match &profile.last_server {
+            Some(server) => Self {
+                url: server.address.clone(),
+                secure: server.secure,
+                listed: None,
+            },
+            None => Self::default(),
         }
     }
 }
 
+/// Why the last connection attempt failed, shown on the startup screen.
+#[derive(Resource, Default)]
+pub struct ConnectionError(pub Option<String>);
+
 #[derive(Component)]
 pub struct StartupScreenMarker;
 
 #[derive(Component)]
 pub(crate) struct ServerUrlInput;
 
+#[derive(Component)]
+pub(crate) struct PlayerNameInput;
+
 #[derive(Component)]
 pub(crate) struct ConnectButton;
 
@@ -38,9 +74,56 @@ pub(crate) struct ConnectButton;
 pub(crate) struct LocalButton;
 
 #[derive(Component)]
-pub(crate) struct RemoteButton;
+pub(crate) struct SecureToggleButton;
+
+#[derive(Component)]
+pub(crate) struct SecureToggleText;
+
+/// Server list shown below the connect button, one at a time.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum ServerListTab {
+    #[default]
+    Favorites,
+    History,
+    Lan,
+    Browser,
+}
 
-pub fn show_startup_screen(mut commands: Commands, server_config: Res<ServerConfig>) {
+impl ServerListTab {
+    const ALL: [Self; 4] = [Self::Favorites, Self::History, Self::Lan, Self::Browser];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Favorites => "Favorites",
+            Self::History => "History",
+            Self::Lan => "LAN",
+            Self::Browser => "Internet",
+        }
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct ServerListTabButton(ServerListTab);
+
+#[derive(Component)]
+pub(crate) struct ServerListPanel(ServerListTab);
+
+fn secure_toggle_text(secure: bool) -> &'static str {
+    if secure {
+        "Secure: On"
+    } else {
+        "Secure: Off"
+    }
+}
+
+pub fn show_startup_screen(
+    mut commands: Commands,
+    server_config: Res<ServerConfig>,
+    connection_error: Res<ConnectionError>,
+    browser_view: Res<BrowserView>,
+    server_list_tab: Res<ServerListTab>,
+    profile: Res<UserProfile>,
+) {
     println!("=== SHOW_STARTUP_SCREEN ===");
     let root_entity = commands
         .spawn((
@@ -73,6 +156,62 @@ pub fn show_startup_screen(mut commands: Commands, server_config: Res<ServerConf
                 .id();
             println!("Title spawned: {:?}", title_entity);
 
+            if let Some(error) = &connection_error.0 {
+                parent.spawn((
+                    Text::new(error.clone()),
+                    TextFont {
+                        font_size: 22.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.9, 0.3, 0.3)),
+                    Node {
+                        margin: UiRect::bottom(Val::Px(20.0)),
+                        ..default()
+                    },
+                ));
+            }
+
+            // Player name label
+            parent.spawn((
+                Text::new("Player Name:"),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                Node {
+                    margin: UiRect::bottom(Val::Px(10.0)),
+                    ..default()
+                },
+            ));
+
+            parent.spawn((
+                Node {
+                    width: Val::Px(400.0),
+                    height: Val::Px(50.0),
+                    align_items: AlignItems::Center,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    margin: UiRect::bottom(Val::Px(20.0)),
+                    border: UiRect::all(Val::Px(2.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                TextInput,
+                TextInputInactive(true),
+                Interaction::None,
+                TextInputTextFont(TextFont {
+                    font_size: 20.0,
+                    ..default()
+                }),
+                TextInputTextColor(TextColor(Color::srgb(1.0, 1.0, 1.0))),
+                TextInputValue(profile.name.clone()),
+                TextInputSettings {
+                    retain_on_submit: true,
+                    ..default()
+                },
+                PlayerNameInput,
+            ));
+
             // Server URL label
             parent.spawn((
                 Text::new("Server Address:"),
@@ -101,6 +240,8 @@ pub fn show_startup_screen(mut commands: Commands, server_config: Res<ServerConf
                 BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
                 // BorderColor::all(Color::srgb(0.4, 0.4, 0.5).into()),
                 TextInput,
+                TextInputInactive(false),
+                Interaction::None,
                 TextInputTextFont(TextFont {
                     font_size: 20.0,
                     ..default()
@@ -114,7 +255,7 @@ pub fn show_startup_screen(mut commands: Commands, server_config: Res<ServerConf
                 ServerUrlInput,
             ));
 
-            // local or remote connection buttons for quick configuration
+            // local connection button for quick configuration
             parent
                 .spawn(Node {
                     flex_direction: FlexDirection::Row,
@@ -140,21 +281,25 @@ pub fn show_startup_screen(mut commands: Commands, server_config: Res<ServerConf
                             button_parent.spawn(Text::new("Local"));
                         });
 
+                    // Secure servers are reached through their token service address
                     row_parent
                         .spawn((
                             Button,
                             Node {
-                                width: Val::Px(100.0),
+                                width: Val::Px(140.0),
                                 height: Val::Px(40.0),
                                 align_items: AlignItems::Center,
                                 justify_content: JustifyContent::Center,
                                 ..default()
                             },
                             BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
-                            RemoteButton,
+                            SecureToggleButton,
                         ))
                         .with_children(|button_parent| {
-                            button_parent.spawn(Text::new("Remote"));
+                            button_parent.spawn((
+                                Text::new(secure_toggle_text(server_config.secure)),
+                                SecureToggleText,
+                            ));
                         });
                 });
 
@@ -182,6 +327,79 @@ pub fn show_startup_screen(mut commands: Commands, server_config: Res<ServerConf
                         TextColor(Color::srgb(1.0, 1.0, 1.0)),
                     ));
                 });
+
+            // Favorites, history, LAN servers and the server browser as tabs
+            parent
+                .spawn(Node {
+                    width: Val::Px(640.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(10.0),
+                    margin: UiRect::top(Val::Px(40.0)),
+                    ..default()
+                })
+                .with_children(|lists_parent| {
+                    lists_parent
+                        .spawn(Node {
+                            flex_direction: FlexDirection::Row,
+                            column_gap: Val::Px(6.0),
+                            ..default()
+                        })
+                        .with_children(|tabs_parent| {
+                            for tab in ServerListTab::ALL {
+                                tabs_parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Px(120.0),
+                                            height: Val::Px(36.0),
+                                            align_items: AlignItems::Center,
+                                            justify_content: JustifyContent::Center,
+                                            ..default()
+                                        },
+                                        BackgroundColor(Color::srgb(0.2, 0.2, 0.25).into()),
+                                        ServerListTabButton(tab),
+                                    ))
+                                    .with_children(|button_parent| {
+                                        button_parent.spawn(Text::new(tab.label()));
+                                    });
+                            }
+                        });
+
+                    for tab in ServerListTab::ALL {
+                        lists_parent
+                            .spawn((
+                                Node {
+                                    display: if tab == *server_list_tab {
+                                        Display::Flex
+                                    } else {
+                                        Display::None
+                                    },
+                                    flex_direction: FlexDirection::Column,
+                                    row_gap: Val::Px(6.0),
+                                    ..default()
+                                },
+                                ServerListPanel(tab),
+                            ))
+                            .with_children(|panel_parent| match tab {
+                                ServerListTab::Favorites => spawn_favorites_panel(panel_parent),
+                                ServerListTab::History => spawn_history_panel(panel_parent),
+                                ServerListTab::Lan => {
+                                    // Servers answering LAN discovery, filled by `update_lan_server_list`
+                                    panel_parent.spawn((
+                                        Node {
+                                            flex_direction: FlexDirection::Column,
+                                            row_gap: Val::Px(6.0),
+                                            ..default()
+                                        },
+                                        LanServerList,
+                                    ));
+                                }
+                                ServerListTab::Browser => {
+                                    spawn_server_browser(panel_parent, &browser_view)
+                                }
+                            });
+                    }
+                });
         })
         .id();
     println!("Root entity spawned: {:?}", root_entity);
@@ -204,6 +422,7 @@ pub fn handle_startup_ui(
     >,
     mut next_state: ResMut<NextState<AppState>>,
     mut server_config: ResMut<ServerConfig>,
+    mut connection_error: ResMut<ConnectionError>,
     input_query: Query<&TextInputValue, With<ServerUrlInput>>,
 ) {
     // Handle button interaction
@@ -215,7 +434,9 @@ pub fn handle_startup_ui(
                 if let Ok(input_value) = input_query.single() {
                     server_config.url = input_value.0.clone();
                 }
-                next_state.set(AppState::Playing);
+                server_config.listed = None;
+                connection_error.0 = None;
+                next_state.set(AppState::Connecting);
             }
             Interaction::Hovered => {
                 *color = Color::srgb(0.25, 0.7, 0.25).into();
@@ -227,8 +448,34 @@ pub fn handle_startup_ui(
     }
 }
 
+/// Keeps the profile's name in sync with the name field, it is saved when connecting.
+pub fn update_player_name(
+    input_query: Query<&TextInputValue, (Changed<TextInputValue>, With<PlayerNameInput>)>,
+    mut profile: ResMut<UserProfile>,
+) {
+    if let Ok(input_value) = input_query.single() {
+        // The favorites and history lists don't show the name, don't rebuild them on every key
+        profile.bypass_change_detection().name = input_value.0.clone();
+    }
+}
+
+/// Sends typing to the text field that was clicked last.
+pub fn focus_text_inputs(
+    clicked_query: Query<(Entity, &Interaction), (Changed<Interaction>, With<TextInput>)>,
+    mut input_query: Query<(Entity, &mut TextInputInactive)>,
+) {
+    for (clicked, interaction) in &clicked_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        for (entity, mut inactive) in &mut input_query {
+            inactive.0 = entity != clicked;
+        }
+    }
+}
+
 pub fn handle_copy_paste(
-    mut input_query: Query<&mut TextInputValue, With<ServerUrlInput>>,
+    mut input_query: Query<(&mut TextInputValue, &TextInputInactive)>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
     mut evr_kbd: bevy::prelude::MessageReader<KeyboardInput>,
 ) {
@@ -248,7 +495,11 @@ pub fn handle_copy_paste(
             continue;
         }
 
-        if let Ok(mut input_value) = input_query.single_mut() {
+        let focused_input = input_query
+            .iter_mut()
+            .find(|(_, inactive)| !inactive.0)
+            .map(|(input_value, _)| input_value);
+        if let Some(mut input_value) = focused_input {
             match ev.key_code {
                 // Copy: Ctrl+C
                 KeyCode::KeyC => {
@@ -292,14 +543,6 @@ pub fn handle_quick_connect_buttons(
         (&Interaction, &mut BackgroundColor),
         (Changed<Interaction>, With<LocalButton>),
     >,
-    mut remote_button_query: Query<
-        (&Interaction, &mut BackgroundColor),
-        (
-            Changed<Interaction>,
-            With<RemoteButton>,
-            Without<LocalButton>,
-        ),
-    >,
     mut input_query: Query<&mut TextInputValue, With<ServerUrlInput>>,
 ) {
     // Handle Local button
@@ -320,15 +563,23 @@ pub fn handle_quick_connect_buttons(
             }
         }
     }
+}
 
-    // Handle Remote button
-    for (interaction, mut color) in &mut remote_button_query {
+pub fn handle_secure_toggle(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<SecureToggleButton>),
+    >,
+    mut text_query: Query<&mut Text, With<SecureToggleText>>,
+    mut server_config: ResMut<ServerConfig>,
+) {
+    for (interaction, mut color) in &mut button_query {
         match *interaction {
             Interaction::Pressed => {
                 *color = Color::srgb(0.15, 0.15, 0.2).into();
-                // Set to remote server address
-                if let Ok(mut input_value) = input_query.single_mut() {
-                    input_value.0 = "138.199.203.159:5000".to_string();
+                server_config.secure = !server_config.secure;
+                if let Ok(mut text) = text_query.single_mut() {
+                    text.0 = secure_toggle_text(server_config.secure).to_string();
                 }
             }
             Interaction::Hovered => {
@@ -340,3 +591,54 @@ pub fn handle_quick_connect_buttons(
         }
     }
 }
+
+/// Switches the server list tab and shows only its panel.
+pub fn handle_server_list_tabs(
+    mut tab_button_query: Query<(&Interaction, &mut BackgroundColor, &ServerListTabButton)>,
+    mut panel_query: Query<(&mut Node, &ServerListPanel)>,
+    mut server_list_tab: ResMut<ServerListTab>,
+) {
+    for (interaction, _, button) in &tab_button_query {
+        if *interaction == Interaction::Pressed {
+            server_list_tab.set_if_neq(button.0);
+        }
+    }
+
+    for (interaction, mut color, button) in &mut tab_button_query {
+        let target = if button.0 == *server_list_tab {
+            Color::srgb(0.35, 0.35, 0.45)
+        } else if *interaction == Interaction::Hovered {
+            Color::srgb(0.3, 0.3, 0.35)
+        } else {
+            Color::srgb(0.2, 0.2, 0.25)
+        };
+        color.set_if_neq(BackgroundColor(target));
+    }
+
+    for (mut node, panel) in &mut panel_query {
+        let display = if panel.0 == *server_list_tab {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        if node.display != display {
+            node.display = display;
+        }
+    }
+}
+
+/// Connects to a server picked from one of the server lists, `info` is what the list knows
+/// about it.
+pub fn join_server(
+    server: &ServerAddress,
+    info: Option<&ServerInfo>,
+    server_config: &mut ServerConfig,
+    connection_error: &mut ConnectionError,
+    next_state: &mut NextState<AppState>,
+) {
+    server_config.url = server.address.clone();
+    server_config.secure = server.secure;
+    server_config.listed = info.cloned();
+    connection_error.0 = None;
+    next_state.set(AppState::Connecting);
+}
@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+use zombrise_shared::wave::wave::WaveStatus;
+
+#[derive(Component)]
+pub struct WaveHudMarker;
+
+#[derive(Component)]
+pub(crate) struct WaveHudText;
+
+/// Shows the current wave, remaining zombies and the intermission countdown
+pub fn display_wave_hud(
+    mut commands: Commands,
+    status_query: Query<&WaveStatus>,
+    hud_query: Query<Entity, With<WaveHudMarker>>,
+    mut text_query: Query<&mut Text, With<WaveHudText>>,
+) {
+    let Ok(status) = status_query.single() else {
+        return;
+    };
+
+    if hud_query.is_empty() {
+        commands
+            .spawn((
+                Node {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(20.0),
+                    top: Val::Px(20.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::FlexEnd,
+                    ..default()
+                },
+                WaveHudMarker,
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    Text::new(wave_text(status)),
+                    TextFont {
+                        font_size: 24.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.9, 0.8, 0.3)),
+                    WaveHudText,
+                ));
+            });
+        return;
+    }
+
+    if let Ok(mut text) = text_query.single_mut() {
+        let new_text = wave_text(status);
+        if text.0 != new_text {
+            text.0 = new_text;
+        }
+    }
+}
+
+fn wave_text(status: &WaveStatus) -> String {
+    if status.intermission > 0.0 {
+        format!(
+            "Wave {} starts in {:.0}s",
+            status.wave + 1,
+            status.intermission
+        )
+    } else {
+        format!(
+            "Wave {}\nZombies remaining: {}",
+            status.wave, status.zombies_remaining
+        )
+    }
+}
+
+pub fn cleanup_wave_hud(mut commands: Commands, hud_query: Query<Entity, With<WaveHudMarker>>) {
+    for entity in hud_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,267 @@
+use clap::Parser;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zombrise_shared::protocol::discovery::ServerInfo;
+use zombrise_shared::protocol::master::{
+    Heartbeat, ListedServer, ServerList, ServerListRequest, MASTER_PORT,
+};
+
+/// Servers above this count are not accepted, so a flood of fake heartbeats can't grow
+/// the list without bounds.
+const MAX_SERVERS: usize = 1000;
+/// List requests answered at the same time, further connections are closed right away.
+const MAX_OPEN_REQUESTS: usize = 64;
+/// Time a client gets to send its request and read the list.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Lightweight master server: game servers register with UDP heartbeats, clients fetch
+/// the list of live servers over TCP. Both use the same port.
+#[derive(Parser, Debug)]
+#[command(name = "zombrise_master", about = "Zombrise master server list")]
+struct Cli {
+    /// Address the UDP and TCP sockets are bound to.
+    #[arg(
+        long,
+        env = "ZOMBRISE_MASTER_BIND",
+        default_value_t = SocketAddr::from(([0, 0, 0, 0], MASTER_PORT))
+    )]
+    bind: SocketAddr,
+    /// Seconds without a heartbeat after which a server is dropped from the list.
+    #[arg(long, env = "ZOMBRISE_MASTER_EXPIRE_SECONDS", default_value_t = 30)]
+    expire_seconds: u64,
+}
+
+struct Registration {
+    info: ServerInfo,
+    last_heartbeat: Instant,
+}
+
+/// Registered servers by game address.
+struct Registry {
+    servers: HashMap<SocketAddr, Registration>,
+    expire_after: Duration,
+}
+
+impl Registry {
+    fn remove_expired(&mut self) {
+        let expire_after = self.expire_after;
+        self.servers
+            .retain(|_, registration| registration.last_heartbeat.elapsed() <= expire_after);
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let heartbeat_socket = UdpSocket::bind(cli.bind).unwrap_or_else(|e| {
+        eprintln!("Cannot bind UDP {}: {}", cli.bind, e);
+        std::process::exit(1);
+    });
+    let listener = TcpListener::bind(cli.bind).unwrap_or_else(|e| {
+        eprintln!("Cannot bind TCP {}: {}", cli.bind, e);
+        std::process::exit(1);
+    });
+
+    println!("Master server listening on {}", cli.bind);
+    serve(
+        heartbeat_socket,
+        listener,
+        Duration::from_secs(cli.expire_seconds),
+    );
+}
+
+/// Receives heartbeats on a background thread and answers list requests until the
+/// listener fails.
+fn serve(heartbeat_socket: UdpSocket, listener: TcpListener, expire_after: Duration) {
+    let registry = Arc::new(Mutex::new(Registry {
+        servers: HashMap::new(),
+        expire_after,
+    }));
+
+    let heartbeat_registry = registry.clone();
+    std::thread::spawn(move || receive_heartbeats(heartbeat_socket, heartbeat_registry));
+
+    let open_requests = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("List request failed: {}", e);
+                continue;
+            }
+        };
+        // Every request gets its own thread, so a slow client can't hold up the others
+        if open_requests.fetch_add(1, Ordering::SeqCst) >= MAX_OPEN_REQUESTS {
+            open_requests.fetch_sub(1, Ordering::SeqCst);
+            continue;
+        }
+        let registry = registry.clone();
+        let open_requests = open_requests.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = send_server_list(stream, &registry) {
+                eprintln!("List request failed: {}", e);
+            }
+            open_requests.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+fn receive_heartbeats(socket: UdpSocket, registry: Arc<Mutex<Registry>>) {
+    let mut buffer = [0; 1024];
+    loop {
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("Cannot receive heartbeat: {}", e);
+                continue;
+            }
+        };
+        let Some(mut heartbeat) = Heartbeat::from_bytes(&buffer[..len]) else {
+            continue;
+        };
+        if heartbeat.info.game_port == 0 || heartbeat.info.name.trim().is_empty() {
+            continue;
+        }
+
+        // The game server is reached on the address the heartbeat came from, which is
+        // the right one behind NAT as well
+        let address = SocketAddr::new(from.ip(), heartbeat.info.game_port);
+        // Heartbeats come from the socket that answers pings, and the NAT mapping of
+        // their source port is the one that lets the pings through
+        heartbeat.info.query_port = from.port();
+        let mut registry = registry.lock().unwrap();
+        registry.remove_expired();
+        if !registry.servers.contains_key(&address) {
+            if registry.servers.len() >= MAX_SERVERS {
+                continue;
+            }
+            println!("Registered {} ({})", heartbeat.info.name, address);
+        }
+        registry.servers.insert(
+            address,
+            Registration {
+                info: heartbeat.info,
+                last_heartbeat: Instant::now(),
+            },
+        );
+    }
+}
+
+fn send_server_list(mut stream: TcpStream, registry: &Mutex<Registry>) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(REQUEST_TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+
+    let mut request = [0; 4];
+    stream
+        .read_exact(&mut request)
+        .map_err(|e| format!("Incomplete request: {}", e))?;
+    ServerListRequest::from_bytes(&request).ok_or("Not a server list request")?;
+
+    let list = {
+        let mut registry = registry.lock().unwrap();
+        registry.remove_expired();
+        ServerList {
+            servers: registry
+                .servers
+                .iter()
+                .map(|(address, registration)| ListedServer {
+                    address: *address,
+                    info: registration.info.clone(),
+                })
+                .collect(),
+        }
+    };
+
+    stream
+        .write_all(&list.to_bytes())
+        .and_then(|_| stream.flush())
+        .map_err(|e| format!("Cannot send list: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_info() -> ServerInfo {
+        ServerInfo {
+            name: "Test server".to_string(),
+            game_port: 5000,
+            query_port: 1,
+            token_port: None,
+            players: 2,
+            max_players: 10,
+            wave: 3,
+            version: "1.0.0".to_string(),
+            protocol_hash: 42,
+        }
+    }
+
+    fn request_list(master: SocketAddr) -> ServerList {
+        let mut stream = TcpStream::connect(master).unwrap();
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT)).unwrap();
+        stream.write_all(&ServerListRequest.to_bytes()).unwrap();
+        let mut bytes = Vec::new();
+        stream.read_to_end(&mut bytes).unwrap();
+        ServerList::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn heartbeat_shows_up_in_the_list() {
+        let heartbeat_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let heartbeat_address = heartbeat_socket.local_addr().unwrap();
+        let list_address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(heartbeat_socket, listener, Duration::from_secs(30)));
+
+        assert!(request_list(list_address).servers.is_empty());
+
+        let game_server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let heartbeat = Heartbeat { info: test_info() };
+        game_server
+            .send_to(&heartbeat.to_bytes(), heartbeat_address)
+            .unwrap();
+
+        // Heartbeats are handled on another thread
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let list = loop {
+            let list = request_list(list_address);
+            if !list.servers.is_empty() || Instant::now() > deadline {
+                break list;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        let query_port = game_server.local_addr().unwrap().port();
+        assert_eq!(
+            list.servers,
+            vec![ListedServer {
+                address: SocketAddr::from(([127, 0, 0, 1], 5000)),
+                info: ServerInfo {
+                    query_port,
+                    ..test_info()
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn slow_request_does_not_block_others() {
+        let heartbeat_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let list_address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(heartbeat_socket, listener, Duration::from_secs(30)));
+
+        // Connects but never sends its request
+        let _idle = TcpStream::connect(list_address).unwrap();
+
+        let started = Instant::now();
+        request_list(list_address);
+        assert!(started.elapsed() < REQUEST_TIMEOUT);
+    }
+}
@@ -0,0 +1,258 @@
+use renet2_netcode::{ConnectToken, NETCODE_KEY_BYTES};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+use zombrise_shared::protocol::protocol::{
+    ConnectUserData, ProtocolVersion, TOKEN_ISSUED, TOKEN_VERSION_MISMATCH,
+};
+
+use crate::config::ServerConfig;
+
+/// Reads the hex encoded private key shared by the game server and the token service.
+pub fn load_private_key(path: &Path) -> Result<[u8; NETCODE_KEY_BYTES], String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        format!(
+            "Cannot read private key {}: {} (create one with the generate-key subcommand)",
+            path.display(),
+            e
+        )
+    })?;
+    let hex = contents.trim();
+    if hex.len() != NETCODE_KEY_BYTES * 2 {
+        return Err(format!(
+            "Private key {} must be {} hex characters",
+            path.display(),
+            NETCODE_KEY_BYTES * 2
+        ));
+    }
+
+    let mut key = [0; NETCODE_KEY_BYTES];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("Private key {} is not valid hex", path.display()))?;
+    }
+    Ok(key)
+}
+
+/// Writes a new random private key, refusing to replace an existing one unless `force` is set.
+pub fn generate_private_key(path: &Path, force: bool) -> Result<(), String> {
+    if path.exists() && !force {
+        return Err(format!(
+            "{} already exists, pass --force to replace it",
+            path.display()
+        ));
+    }
+
+    let key: [u8; NETCODE_KEY_BYTES] = rand::random();
+    let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+    std::fs::write(path, hex + "\n")
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    println!("Private key written to {}", path.display());
+    Ok(())
+}
+
+/// Token requests answered at the same time, further connections are closed right away.
+const MAX_OPEN_REQUESTS: usize = 64;
+/// Time a client gets to send its request and read the answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Hands out a signed connect token to everyone who opens a TCP connection and sends their
+/// player identity and protocol hash (8 bytes each) and identity secret (16 bytes), followed
+/// by the length of their name (1 byte) and the name. The token picks the client id, so
+/// clients can no longer choose their own, and carries the rest as user data for the game
+/// server. The answer starts with `TOKEN_ISSUED` and the token, or `TOKEN_VERSION_MISMATCH`
+/// and the server's readable version (length prefixed) for clients of another version.
+pub fn run_token_service(
+    config: &ServerConfig,
+    protocol_version: &ProtocolVersion,
+) -> Result<(), String> {
+    let private_key = load_private_key(&config.auth.private_key_file)?;
+    let address = config.auth.token_service_address;
+    let listener =
+        TcpListener::bind(address).map_err(|e| format!("Cannot bind {}: {}", address, e))?;
+    println!(
+        "Token service listening on {}, issuing tokens for {}",
+        address,
+        config.network.public_address()
+    );
+
+    serve_tokens(listener, config, protocol_version, &private_key);
+    Ok(())
+}
+
+/// Answers token requests until the listener fails.
+fn serve_tokens(
+    listener: TcpListener,
+    config: &ServerConfig,
+    protocol_version: &ProtocolVersion,
+    private_key: &[u8; NETCODE_KEY_BYTES],
+) {
+    let open_requests = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Token request failed: {}", e);
+                    continue;
+                }
+            };
+            // Every request gets its own thread, so a client that never sends its request
+            // can't hold up everyone else's login
+            if open_requests.fetch_add(1, Ordering::SeqCst) >= MAX_OPEN_REQUESTS {
+                open_requests.fetch_sub(1, Ordering::SeqCst);
+                continue;
+            }
+            let open_requests = &open_requests;
+            scope.spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(e) = answer_token_request(stream, config, protocol_version, private_key)
+                {
+                    eprintln!("Token request from {:?} failed: {}", peer, e);
+                }
+                open_requests.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+}
+
+fn answer_token_request(
+    mut stream: TcpStream,
+    config: &ServerConfig,
+    protocol_version: &ProtocolVersion,
+    private_key: &[u8; NETCODE_KEY_BYTES],
+) -> Result<(), String> {
+    let peer = stream.peer_addr().ok();
+    stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(REQUEST_TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+
+    let mut request = [0; 32];
+    let mut name_len = [0; 1];
+    stream
+        .read_exact(&mut request)
+        .and_then(|_| stream.read_exact(&mut name_len))
+        .map_err(|e| format!("Incomplete request: {}", e))?;
+    let mut name = vec![0; (name_len[0] as usize).min(ConnectUserData::MAX_NAME_BYTES)];
+    stream
+        .read_exact(&mut name)
+        .map_err(|e| format!("Incomplete request: {}", e))?;
+
+    let identity = u64::from_le_bytes(request[..8].try_into().unwrap());
+    let user_data = ConnectUserData {
+        identity: (identity != 0).then_some(identity),
+        protocol_hash: u64::from_le_bytes(request[8..16].try_into().unwrap()),
+        secret: u128::from_le_bytes(request[16..].try_into().unwrap()),
+        name: String::from_utf8_lossy(&name).into_owned(),
+    };
+
+    // The token would get them past the handshake, so other versions are turned away here
+    if user_data.protocol_hash != protocol_version.hash {
+        println!(
+            "Refusing token request from {:?}: protocol version {:016x}, server runs {}",
+            peer,
+            user_data.protocol_hash,
+            protocol_version.display()
+        );
+        let version = protocol_version.display();
+        let mut answer = vec![TOKEN_VERSION_MISMATCH, version.len() as u8];
+        answer.extend_from_slice(version.as_bytes());
+        return stream
+            .write_all(&answer)
+            .and_then(|_| stream.flush())
+            .map_err(|e| format!("Cannot send answer: {}", e));
+    }
+
+    let client_id: u64 = rand::random();
+    let token = issue_token(config, client_id, protocol_version, user_data, private_key)
+        .map_err(|e| format!("Cannot generate connect token: {}", e))?;
+
+    stream
+        .write_all(&[TOKEN_ISSUED])
+        .and_then(|_| token.write(&mut stream))
+        .and_then(|_| stream.flush())
+        .map_err(|e| format!("Cannot send token: {}", e))?;
+    println!("Issued token for client {} to {:?}", client_id, peer);
+    Ok(())
+}
+
+fn issue_token(
+    config: &ServerConfig,
+    client_id: u64,
+    protocol_version: &ProtocolVersion,
+    user_data: ConnectUserData,
+    private_key: &[u8; NETCODE_KEY_BYTES],
+) -> Result<ConnectToken, String> {
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+
+    ConnectToken::generate(
+        current_time,
+        config.network.netcode_protocol_id(protocol_version),
+        config.auth.token_expire_seconds,
+        client_id,
+        config.auth.connection_timeout_seconds,
+        0,
+        vec![config.network.public_address()],
+        Some(&user_data.to_bytes()),
+        private_key,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn request_token(service: std::net::SocketAddr, protocol_hash: u64) -> u8 {
+        let mut stream = TcpStream::connect(service).unwrap();
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT)).unwrap();
+        let mut request = vec![0; 8];
+        request.extend_from_slice(&protocol_hash.to_le_bytes());
+        request.extend_from_slice(&[0; 16]);
+        request.push(0);
+        stream.write_all(&request).unwrap();
+        let mut status = [0; 1];
+        stream.read_exact(&mut status).unwrap();
+        status[0]
+    }
+
+    fn start_service() -> (std::net::SocketAddr, ProtocolVersion) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let protocol_version = ProtocolVersion::new(&["Test".to_string()]);
+        let service_version = protocol_version.clone();
+        std::thread::spawn(move || {
+            let config = ServerConfig::default();
+            serve_tokens(listener, &config, &service_version, &[7; NETCODE_KEY_BYTES]);
+        });
+        (address, protocol_version)
+    }
+
+    #[test]
+    fn issues_tokens_for_the_same_version_only() {
+        let (address, protocol_version) = start_service();
+        assert_eq!(request_token(address, protocol_version.hash), TOKEN_ISSUED);
+        assert_eq!(
+            request_token(address, protocol_version.hash ^ 1),
+            TOKEN_VERSION_MISMATCH
+        );
+    }
+
+    #[test]
+    fn idle_connection_does_not_block_others() {
+        let (address, protocol_version) = start_service();
+
+        // Connects but never sends its request
+        let _idle = TcpStream::connect(address).unwrap();
+
+        let started = Instant::now();
+        assert_eq!(request_token(address, protocol_version.hash), TOKEN_ISSUED);
+        assert!(started.elapsed() < REQUEST_TIMEOUT);
+    }
+}
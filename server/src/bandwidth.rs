@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+use bevy_replicon_renet2::renet2::RenetServer;
+use std::collections::HashMap;
+use zombrise_shared::players::player::Player;
+use zombrise_shared::zombie::zombie::Zombie;
+
+/// Seconds between two bandwidth reports.
+const REPORT_INTERVAL: f32 = 10.0;
+
+/// Bytes per second sent to one client, summed over the frames of a report.
+#[derive(Default)]
+pub struct ClientBandwidth {
+    total: f64,
+    peak: f64,
+    samples: u32,
+}
+
+/// Prints the average and peak bandwidth sent to every client, together with how many
+/// entities were replicated, so changes to replication can be compared with real numbers.
+pub fn log_bandwidth(
+    server: Res<RenetServer>,
+    player_query: Query<(), With<Player>>,
+    zombie_query: Query<(), With<Zombie>>,
+    mut clients: Local<HashMap<u64, ClientBandwidth>>,
+    mut elapsed: Local<f32>,
+    time: Res<Time>,
+) {
+    for client_id in server.clients_id() {
+        let Ok(info) = server.network_info(client_id) else {
+            continue;
+        };
+        let client = clients.entry(client_id).or_default();
+        client.total += info.bytes_sent_per_second;
+        client.peak = client.peak.max(info.bytes_sent_per_second);
+        client.samples += 1;
+    }
+
+    *elapsed += time.delta_secs();
+    if *elapsed < REPORT_INTERVAL {
+        return;
+    }
+    *elapsed = 0.0;
+
+    if clients.is_empty() {
+        return;
+    }
+    println!(
+        "Bandwidth over the last {} s ({} players, {} zombies):",
+        REPORT_INTERVAL,
+        player_query.iter().count(),
+        zombie_query.iter().count()
+    );
+    for (client_id, client) in clients.drain() {
+        println!(
+            "  client {}: {:.1} KiB/s average, {:.1} KiB/s peak",
+            client_id,
+            client.total / client.samples.max(1) as f64 / 1024.0,
+            client.peak / 1024.0
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon::shared::backend::connected_client::NetworkId;
+use std::collections::{HashMap, VecDeque};
+use zombrise_shared::chat::chat::{ChatMessage, ChatSender, SendChatMessage};
+use zombrise_shared::players::player::PlayerName;
+use zombrise_shared::wave::wave::WaveStatus;
+
+use crate::config::{ChatConfig, ServerConfig};
+use crate::names::is_invisible;
+use crate::{sender_network_id, PlayerIndex};
+
+/// When each client sent its recent messages, by renet client id.
+#[derive(Resource, Default)]
+pub struct ChatLimiter(HashMap<u64, VecDeque<f32>>);
+
+/// Chat line from the server to everyone.
+pub fn system_message(text: String) -> ToClients<ChatMessage> {
+    ToClients {
+        mode: SendMode::Broadcast,
+        message: ChatMessage {
+            sender: ChatSender::System,
+            text,
+        },
+    }
+}
+
+/// Filters chat messages from players and passes them on to everyone.
+pub fn handle_chat_messages(
+    mut events: MessageReader<FromClient<SendChatMessage>>,
+    network_ids: Query<&NetworkId>,
+    player_index: Res<PlayerIndex>,
+    name_query: Query<&PlayerName>,
+    mut limiter: ResMut<ChatLimiter>,
+    mut chat: MessageWriter<ToClients<ChatMessage>>,
+    config: Res<ServerConfig>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    let window = config.chat.rate_window;
+
+    // Forget messages that left the window, and clients that went quiet
+    limiter.0.retain(|_, sent| {
+        while sent.front().is_some_and(|&sent_at| now - sent_at > window) {
+            sent.pop_front();
+        }
+        !sent.is_empty()
+    });
+
+    for FromClient { client_id, message } in events.read() {
+        let Some(owner) = sender_network_id(*client_id, &network_ids) else {
+            continue;
+        };
+        let Some(name) = player_index
+            .0
+            .get(&owner)
+            .and_then(|&entity| name_query.get(entity).ok())
+        else {
+            continue;
+        };
+
+        let sent = limiter.0.entry(owner).or_default();
+        if sent.len() >= config.chat.rate_limit {
+            chat.write(ToClients {
+                mode: SendMode::Direct(*client_id),
+                message: ChatMessage {
+                    sender: ChatSender::System,
+                    text: "You are sending messages too fast".to_string(),
+                },
+            });
+            continue;
+        }
+
+        let Some(text) = filter_message(&message.text, &config.chat) else {
+            continue;
+        };
+        sent.push_back(now);
+
+        println!("[chat] {}: {}", name.0, text);
+        chat.write(ToClients {
+            mode: SendMode::Broadcast,
+            message: ChatMessage {
+                sender: ChatSender::Player(name.0.clone()),
+                text,
+            },
+        });
+    }
+}
+
+/// Drops control and invisible characters, cuts the message to `max_length` and masks
+/// blocked words. Invisible characters go first, or they would hide blocked words from
+/// `mask_word`. `None` if nothing is left to say.
+fn filter_message(text: &str, config: &ChatConfig) -> Option<String> {
+    let text: String = text
+        .chars()
+        .filter(|c| !c.is_control() && !is_invisible(*c))
+        .take(config.max_length)
+        .collect();
+    let mut text = text.trim().to_string();
+    if text.is_empty() {
+        return None;
+    }
+
+    for word in &config.blocked_words {
+        text = mask_word(&text, word.trim());
+    }
+    Some(text)
+}
+
+/// Masks `word` where it stands on its own, so blocking "ass" leaves "class" and "pass" alone.
+fn mask_word(text: &str, word: &str) -> String {
+    if word.is_empty() {
+        return text.to_string();
+    }
+
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+    let mut masked = String::with_capacity(text.len());
+    let mut previous = None;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let matches = is_boundary(previous)
+            && rest
+                .get(..word.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(word))
+            && is_boundary(rest[word.len()..].chars().next());
+        if matches {
+            masked.push_str(&"*".repeat(word.chars().count()));
+            previous = word.chars().last();
+            rest = &rest[word.len()..];
+        } else {
+            masked.push(c);
+            previous = Some(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    masked
+}
+
+/// Tells everyone in the chat when a wave starts.
+pub fn announce_waves(
+    status_query: Query<&WaveStatus, Changed<WaveStatus>>,
+    mut last_wave: Local<u32>,
+    mut chat: MessageWriter<ToClients<ChatMessage>>,
+) {
+    for status in &status_query {
+        if status.wave > *last_wave {
+            *last_wave = status.wave;
+            chat.write(system_message(format!(
+                "Wave {} started, {} zombies incoming",
+                status.wave, status.zombies_remaining
+            )));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_whole_words_only() {
+        assert_eq!(mask_word("you ass", "ass"), "you ***");
+        assert_eq!(mask_word("Ass, that class", "ass"), "***, that class");
+        assert_eq!(mask_word("pass the assassin", "ass"), "pass the assassin");
+        assert_eq!(mask_word("ass-ass", "ass"), "***-***");
+    }
+
+    #[test]
+    fn masks_ignoring_case() {
+        assert_eq!(mask_word("Darn it, DARN", "darn"), "**** it, ****");
+    }
+
+    #[test]
+    fn empty_word_changes_nothing() {
+        assert_eq!(mask_word("hello", ""), "hello");
+    }
+
+    #[test]
+    fn filters_control_characters_and_blocked_words() {
+        let config = ChatConfig {
+            max_length: 20,
+            blocked_words: vec![" darn ".to_string()],
+            ..default()
+        };
+        assert_eq!(
+            filter_message("oh\u{7} darn, classic", &config).as_deref(),
+            Some("oh ****, classic")
+        );
+        assert_eq!(filter_message(" \n ", &config), None);
+    }
+
+    #[test]
+    fn invisible_characters_dont_hide_blocked_words() {
+        let config = ChatConfig {
+            blocked_words: vec!["darn".to_string()],
+            ..default()
+        };
+        assert_eq!(
+            filter_message("d\u{200B}ar\u{2060}n it", &config).as_deref(),
+            Some("**** it")
+        );
+        assert_eq!(
+            filter_message("\u{FEFF}da\u{00AD}rn", &config).as_deref(),
+            Some("****")
+        );
+        assert_eq!(filter_message("\u{200B}\u{2060}", &config), None);
+    }
+}
@@ -0,0 +1,446 @@
+use bevy::prelude::*;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use zombrise_shared::chat::chat::MAX_CHAT_MESSAGE_CHARS;
+use zombrise_shared::protocol::conditioner::NetworkConditions;
+use zombrise_shared::protocol::protocol::ProtocolVersion;
+
+use crate::lag_compensation::HISTORY_SECONDS;
+use crate::waves::WaveSettings;
+use crate::zombie_kinds::DEFAULT_ZOMBIE_KINDS_FILE;
+
+/// Config file looked up in the working directory when `--config` isn't given.
+const DEFAULT_CONFIG_FILE: &str = "server.ron";
+
+/// Command-line flags of the server. Every flag can also be set through the
+/// environment variable next to it and overrides the value from the config file.
+#[derive(Parser, Debug)]
+#[command(name = "zombrise_server", about = "Dedicated Zombrise game server")]
+pub struct Cli {
+    /// RON config file, defaults to `server.ron` in the working directory if it exists.
+    #[arg(short, long, env = "ZOMBRISE_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Name shown in the server lists of clients.
+    #[arg(long, env = "ZOMBRISE_NAME")]
+    pub name: Option<String>,
+    /// Address the UDP socket is bound to, e.g. `0.0.0.0:5000`.
+    #[arg(long, env = "ZOMBRISE_BIND")]
+    pub bind: Option<SocketAddr>,
+    /// Address clients use to reach the server, defaults to the bind address.
+    #[arg(long, env = "ZOMBRISE_PUBLIC_ADDRESS")]
+    pub public_address: Option<SocketAddr>,
+    /// Master server to register with, e.g. `master.example.com:5060`.
+    #[arg(long, env = "ZOMBRISE_MASTER_SERVER")]
+    pub master_server: Option<String>,
+    #[arg(long, env = "ZOMBRISE_MAX_CLIENTS")]
+    pub max_clients: Option<usize>,
+    #[arg(long, env = "ZOMBRISE_PROTOCOL_ID")]
+    pub protocol_id: Option<u64>,
+    /// Fixed simulation ticks per second.
+    #[arg(long, env = "ZOMBRISE_TICK_RATE")]
+    pub tick_rate: Option<f64>,
+    /// Maximum number of zombies alive at the same time.
+    #[arg(long, env = "ZOMBRISE_MAX_ZOMBIES")]
+    pub max_zombies: Option<usize>,
+    /// Zombie kind stats file, see `data/zombie_kinds.ron`.
+    #[arg(long, env = "ZOMBRISE_ZOMBIE_KINDS")]
+    pub zombie_kinds: Option<PathBuf>,
+    #[arg(long, env = "ZOMBRISE_AUTH")]
+    pub auth: Option<AuthMode>,
+    /// File with the private key shared by the game server and the token service.
+    #[arg(long, env = "ZOMBRISE_PRIVATE_KEY_FILE")]
+    pub private_key_file: Option<PathBuf>,
+    /// Simulates a bad network for testing, e.g. `latency=100,jitter=20,loss=0.05`.
+    /// Also takes `duplicate`, `reorder` and `seed`.
+    #[arg(long, env = "ZOMBRISE_SIMULATE")]
+    pub simulate: Option<NetworkConditions>,
+    /// Prints how many bytes every client is sent, every 10 seconds.
+    #[arg(long, env = "ZOMBRISE_LOG_BANDWIDTH")]
+    pub log_bandwidth: bool,
+    /// Runs the game server when omitted.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Issues signed connect tokens for the game server described by the config.
+    TokenService,
+    /// Writes a new random private key to the configured private key file.
+    GenerateKey {
+        /// Replace an existing key file.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// How clients prove they may join.
+#[derive(Deserialize, ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AuthMode {
+    /// Anyone can connect with any client id, meant for LAN play.
+    Unsecure,
+    /// Clients need a connect token signed with the private key.
+    Secure,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Name shown in the server lists of clients.
+    pub name: String,
+    pub bind_address: SocketAddr,
+    /// Address handed to clients, `None` uses the bind address.
+    pub public_address: Option<SocketAddr>,
+    pub max_clients: usize,
+    /// Mixed into the netcode protocol id next to the protocol version. Other values than 0
+    /// keep out every client that doesn't get its connect token from this server's token service.
+    pub protocol_id: u64,
+    /// Bandwidth budget per client and tick.
+    pub available_bytes_per_tick: u64,
+    /// Seconds a disconnected player is kept around so they can resume their session.
+    pub session_grace_period: f32,
+    /// Answer LAN discovery broadcasts so clients on the local network list the server.
+    /// `None` answers unless `public_address` is set, servers meant for the internet
+    /// shouldn't answer queries from anyone.
+    pub lan_discovery: Option<bool>,
+    /// Master server (`host:port`) to send heartbeats to so the server shows up in the
+    /// server browser, `None` keeps it unlisted.
+    pub master_server: Option<String>,
+    /// Latency, jitter and packet loss added to the server's socket, for testing.
+    pub simulate: NetworkConditions,
+    /// Print the bandwidth used per client every 10 seconds.
+    pub log_bandwidth: bool,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            name: "Zombrise server".to_string(),
+            bind_address: "0.0.0.0:5000".parse().unwrap(),
+            public_address: None,
+            max_clients: 10,
+            protocol_id: 0,
+            available_bytes_per_tick: 16 * 1024,
+            session_grace_period: 60.0,
+            lan_discovery: None,
+            master_server: None,
+            simulate: NetworkConditions::default(),
+            log_bandwidth: false,
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn public_address(&self) -> SocketAddr {
+        self.public_address.unwrap_or(self.bind_address)
+    }
+
+    pub fn lan_discovery(&self) -> bool {
+        self.lan_discovery.unwrap_or(self.public_address.is_none())
+    }
+
+    /// Netcode protocol id of this server. Clients use their `ProtocolVersion::hash`, so ones
+    /// running another version fail the handshake.
+    pub fn netcode_protocol_id(&self, protocol_version: &ProtocolVersion) -> u64 {
+        protocol_version.hash ^ self.protocol_id
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    /// Fixed physics and AI ticks per second.
+    pub tick_rate: f64,
+    /// Frames per second of the main loop that handles networking.
+    pub update_rate: f64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            tick_rate: 60.0,
+            update_rate: 60.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameplayConfig {
+    pub waves: WaveSettings,
+    /// Seconds a dead player has to wait before they can respawn.
+    pub respawn_delay: f32,
+    /// Looked up relative to the working directory. The built-in stats are used if the
+    /// default file is missing, other files have to exist.
+    pub zombie_kinds: PathBuf,
+    /// Seconds attacks may be judged in the past to make up for the attacker's latency,
+    /// zero judges every attack against the current positions.
+    pub lag_compensation: f32,
+}
+
+impl Default for GameplayConfig {
+    fn default() -> Self {
+        Self {
+            waves: WaveSettings::default(),
+            respawn_delay: 5.0,
+            zombie_kinds: PathBuf::from(DEFAULT_ZOMBIE_KINDS_FILE),
+            lag_compensation: 0.25,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatConfig {
+    /// Longest message in characters, longer ones are cut.
+    pub max_length: usize,
+    /// Messages a player may send within `rate_window` seconds, more are dropped.
+    pub rate_limit: usize,
+    pub rate_window: f32,
+    /// Words replaced with asterisks, matched ignoring ASCII case.
+    pub blocked_words: Vec<String>,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            max_length: MAX_CHAT_MESSAGE_CHARS,
+            rate_limit: 5,
+            rate_window: 10.0,
+            blocked_words: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub mode: AuthMode,
+    /// Hex encoded 32 byte key, create one with the `generate-key` subcommand.
+    pub private_key_file: PathBuf,
+    /// TCP address the token service listens on.
+    pub token_service_address: SocketAddr,
+    /// Seconds an issued token can be used to connect.
+    pub token_expire_seconds: u64,
+    /// Seconds without packets before a connection made with the token times out.
+    pub connection_timeout_seconds: i32,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            mode: AuthMode::Unsecure,
+            private_key_file: PathBuf::from("server.key"),
+            token_service_address: "0.0.0.0:5001".parse().unwrap(),
+            token_expire_seconds: 300,
+            connection_timeout_seconds: 15,
+        }
+    }
+}
+
+/// Settings of the server, loaded once at startup.
+#[derive(Resource, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub network: NetworkConfig,
+    pub simulation: SimulationConfig,
+    pub gameplay: GameplayConfig,
+    pub chat: ChatConfig,
+    pub auth: AuthConfig,
+}
+
+impl ServerConfig {
+    /// Builds the config from the defaults, the config file and the command-line/env overrides,
+    /// in that order. Returns every problem found so they can be fixed in one go.
+    pub fn load(cli: &Cli) -> Result<Self, Vec<String>> {
+        let mut config = match &cli.config {
+            Some(path) => Self::read(path)?,
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_FILE);
+                if path.exists() {
+                    Self::read(&path)?
+                } else {
+                    Self::default()
+                }
+            }
+        };
+
+        config.apply_overrides(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn read(path: &PathBuf) -> Result<Self, Vec<String>> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| vec![format!("Cannot read {}: {}", path.display(), e)])?;
+        println!("Loading server config from {}", path.display());
+        ron::from_str(&contents).map_err(|e| vec![format!("Invalid {}: {}", path.display(), e)])
+    }
+
+    fn apply_overrides(&mut self, cli: &Cli) {
+        if let Some(name) = &cli.name {
+            self.network.name = name.clone();
+        }
+        if let Some(bind) = cli.bind {
+            self.network.bind_address = bind;
+        }
+        if let Some(public_address) = cli.public_address {
+            self.network.public_address = Some(public_address);
+        }
+        if let Some(master_server) = &cli.master_server {
+            self.network.master_server = Some(master_server.clone());
+        }
+        if let Some(max_clients) = cli.max_clients {
+            self.network.max_clients = max_clients;
+        }
+        if let Some(protocol_id) = cli.protocol_id {
+            self.network.protocol_id = protocol_id;
+        }
+        if let Some(tick_rate) = cli.tick_rate {
+            self.simulation.tick_rate = tick_rate;
+        }
+        if let Some(max_zombies) = cli.max_zombies {
+            self.gameplay.waves.max_alive = max_zombies;
+        }
+        if let Some(zombie_kinds) = &cli.zombie_kinds {
+            self.gameplay.zombie_kinds = zombie_kinds.clone();
+        }
+        if let Some(mode) = cli.auth {
+            self.auth.mode = mode;
+        }
+        if let Some(private_key_file) = &cli.private_key_file {
+            self.auth.private_key_file = private_key_file.clone();
+        }
+        if let Some(simulate) = &cli.simulate {
+            self.network.simulate = simulate.clone();
+        }
+        if cli.log_bandwidth {
+            self.network.log_bandwidth = true;
+        }
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let mut check = |ok: bool, message: &str| {
+            if !ok {
+                errors.push(message.to_string());
+            }
+        };
+
+        let network = &self.network;
+        check(
+            !network.name.trim().is_empty() && network.name.len() <= 64,
+            "network.name must be between 1 and 64 bytes long",
+        );
+        check(
+            network.max_clients > 0,
+            "network.max_clients must be at least 1",
+        );
+        check(
+            network.available_bytes_per_tick >= 1024,
+            "network.available_bytes_per_tick must be at least 1024",
+        );
+        check(
+            network.session_grace_period >= 0.0,
+            "network.session_grace_period must not be negative",
+        );
+        if let Err(error) = network.simulate.validate() {
+            check(false, &format!("network.simulate: {}", error));
+        }
+
+        let simulation = &self.simulation;
+        check(
+            simulation.tick_rate > 0.0 && simulation.tick_rate <= 240.0,
+            "simulation.tick_rate must be between 0 and 240",
+        );
+        check(
+            simulation.update_rate > 0.0 && simulation.update_rate <= 1000.0,
+            "simulation.update_rate must be between 0 and 1000",
+        );
+
+        let waves = &self.gameplay.waves;
+        check(
+            waves.base_zombies > 0,
+            "gameplay.waves.base_zombies must be at least 1",
+        );
+        check(
+            waves.max_alive > 0,
+            "gameplay.waves.max_alive must be at least 1",
+        );
+        check(
+            waves.base_spawn_interval > 0.0 && waves.min_spawn_interval > 0.0,
+            "gameplay.waves spawn intervals must be positive",
+        );
+        check(
+            waves.min_spawn_interval <= waves.base_spawn_interval,
+            "gameplay.waves.min_spawn_interval must not exceed base_spawn_interval",
+        );
+        check(
+            waves.spawn_interval_decay > 0.0 && waves.spawn_interval_decay <= 1.0,
+            "gameplay.waves.spawn_interval_decay must be in (0, 1]",
+        );
+        check(
+            waves.zombies_per_extra_player >= 0.0
+                && waves.stat_growth_per_wave >= 0.0
+                && waves.stat_growth_per_extra_player >= 0.0,
+            "gameplay.waves growth factors must not be negative",
+        );
+        check(
+            waves.intermission >= 0.0,
+            "gameplay.waves.intermission must not be negative",
+        );
+        check(
+            self.gameplay.respawn_delay >= 0.0,
+            "gameplay.respawn_delay must not be negative",
+        );
+        check(
+            (0.0..=HISTORY_SECONDS).contains(&self.gameplay.lag_compensation),
+            &format!(
+                "gameplay.lag_compensation must be between 0 and {}",
+                HISTORY_SECONDS
+            ),
+        );
+
+        let chat = &self.chat;
+        check(
+            chat.max_length > 0 && chat.max_length <= MAX_CHAT_MESSAGE_CHARS,
+            &format!(
+                "chat.max_length must be between 1 and {}",
+                MAX_CHAT_MESSAGE_CHARS
+            ),
+        );
+        check(chat.rate_limit > 0, "chat.rate_limit must be at least 1");
+        check(chat.rate_window > 0.0, "chat.rate_window must be positive");
+        check(
+            chat.blocked_words
+                .iter()
+                .all(|word| !word.trim().is_empty()),
+            "chat.blocked_words must not contain empty words",
+        );
+
+        let auth = &self.auth;
+        if auth.mode == AuthMode::Secure {
+            // Connect tokens tell clients where to connect, so they need a reachable address
+            check(
+                !network.public_address().ip().is_unspecified(),
+                "network.public_address must be set to the address clients connect to in secure mode",
+            );
+        }
+        check(
+            auth.token_expire_seconds > 0,
+            "auth.token_expire_seconds must be at least 1",
+        );
+        check(
+            auth.connection_timeout_seconds > 0,
+            "auth.connection_timeout_seconds must be at least 1",
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use bevy::prelude::*;
+use renet2_netcode::{NetcodeTransportError, ServerSocket};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zombrise_shared::players::player::Player;
+use zombrise_shared::protocol::discovery::{
+    DiscoveryQuery, DiscoveryResponse, ServerInfo, DISCOVERY_PORT,
+};
+use zombrise_shared::protocol::master::{Heartbeat, HEARTBEAT_INTERVAL};
+use zombrise_shared::shared::ProtocolVersion;
+use zombrise_shared::wave::wave::WaveStatus;
+
+use crate::config::{AuthMode, ServerConfig};
+
+/// Answers one socket sends per second at most. Answers are larger than queries, so without
+/// a limit queries with a forged source address would turn the server into an amplifier.
+const MAX_ANSWERS_PER_SECOND: u32 = 20;
+
+/// Answers sent in the current second.
+#[derive(Debug, Default)]
+struct AnswerBudget {
+    second_start: Option<Instant>,
+    answered: u32,
+}
+
+impl AnswerBudget {
+    /// Whether another answer may be sent now.
+    fn take(&mut self) -> bool {
+        let now = Instant::now();
+        if self
+            .second_start
+            .is_none_or(|start| now - start >= Duration::from_secs(1))
+        {
+            self.second_start = Some(now);
+            self.answered = 0;
+        }
+        self.answered += 1;
+        self.answered <= MAX_ANSWERS_PER_SECOND
+    }
+}
+
+/// Socket LAN discovery queries arrive on. Only present when discovery is enabled and the
+/// port could be bound.
+#[derive(Resource)]
+pub struct DiscoverySocket {
+    socket: UdpSocket,
+    answers: AnswerBudget,
+}
+
+/// Heartbeats that keep the server on the master server list. The server browser pings
+/// the same socket, the master server lists the port the heartbeats come from.
+#[derive(Resource)]
+pub struct MasterHeartbeat {
+    socket: UdpSocket,
+    master: SocketAddr,
+    /// Seconds until the next heartbeat.
+    next: f32,
+    answers: AnswerBudget,
+}
+
+/// What the game socket answers `DiscoveryQuery`s with, kept up to date by
+/// `publish_server_info`.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct PublishedServerInfo(Arc<Mutex<Option<ServerInfo>>>);
+
+/// Game socket that also answers `DiscoveryQuery`s sent to the game port. A server of another
+/// version ignores the handshake without saying why, so clients that only know the game
+/// address ask for the version first. Netcode packets never start with the query magic.
+#[derive(Debug)]
+pub struct AnsweringSocket<S> {
+    inner: S,
+    info: PublishedServerInfo,
+    answers: AnswerBudget,
+}
+
+impl<S> AnsweringSocket<S> {
+    pub fn new(inner: S, info: PublishedServerInfo) -> Self {
+        Self {
+            inner,
+            info,
+            answers: AnswerBudget::default(),
+        }
+    }
+}
+
+impl<S: ServerSocket> ServerSocket for AnsweringSocket<S> {
+    fn is_encrypted(&self) -> bool {
+        self.inner.is_encrypted()
+    }
+
+    fn is_reliable(&self) -> bool {
+        self.inner.is_reliable()
+    }
+
+    fn addr(&self) -> io::Result<SocketAddr> {
+        self.inner.addr()
+    }
+
+    fn is_closed(&mut self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn close(&mut self) {
+        self.inner.close();
+    }
+
+    fn connection_denied(&mut self, addr: SocketAddr) {
+        self.inner.connection_denied(addr);
+    }
+
+    fn connection_accepted(&mut self, client_id: u64, addr: SocketAddr) {
+        self.inner.connection_accepted(client_id, addr);
+    }
+
+    fn disconnect(&mut self, addr: SocketAddr) {
+        self.inner.disconnect(addr);
+    }
+
+    fn preupdate(&mut self) {
+        self.inner.preupdate();
+    }
+
+    fn try_recv(&mut self, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        loop {
+            let (len, from) = self.inner.try_recv(buffer)?;
+            let Some(query) = DiscoveryQuery::from_bytes(&buffer[..len]) else {
+                return Ok((len, from));
+            };
+            if !self.answers.take() {
+                continue;
+            }
+            let Some(info) = self.info.0.lock().unwrap().clone() else {
+                continue;
+            };
+
+            let response = DiscoveryResponse {
+                nonce: query.nonce,
+                info,
+            };
+            if let Err(e) = self.inner.send(from, &response.to_bytes()) {
+                eprintln!("Cannot answer version query from {}: {}", from, e);
+            }
+        }
+    }
+
+    fn postupdate(&mut self) {
+        self.inner.postupdate();
+    }
+
+    fn send(&mut self, addr: SocketAddr, packet: &[u8]) -> Result<(), NetcodeTransportError> {
+        self.inner.send(addr, packet)
+    }
+}
+
+pub fn setup_discovery(mut commands: Commands, config: Res<ServerConfig>) {
+    if !config.network.lan_discovery() {
+        return;
+    }
+
+    // Only one server per host can bind the port, the others are still listed by the
+    // master server but not found on the LAN
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket));
+    match socket {
+        Ok(socket) => {
+            println!("Answering LAN discovery on port {}", DISCOVERY_PORT);
+            commands.insert_resource(DiscoverySocket {
+                socket,
+                answers: AnswerBudget::default(),
+            });
+        }
+        // Usually another server on the same machine, the game itself works without it
+        Err(e) => eprintln!(
+            "LAN discovery disabled, cannot bind port {}: {}",
+            DISCOVERY_PORT, e
+        ),
+    }
+}
+
+pub fn setup_master_heartbeat(mut commands: Commands, config: Res<ServerConfig>) {
+    let Some(master) = &config.network.master_server else {
+        return;
+    };
+
+    let heartbeat = master
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())
+        .and_then(|mut addresses| {
+            addresses
+                .next()
+                .ok_or_else(|| "no address found".to_string())
+        })
+        .and_then(|master| {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+                .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+                .map_err(|e| e.to_string())?;
+            Ok(MasterHeartbeat {
+                socket,
+                master,
+                next: 0.0,
+                answers: AnswerBudget::default(),
+            })
+        });
+    match heartbeat {
+        Ok(heartbeat) => {
+            println!("Registering with master server {}", heartbeat.master);
+            commands.insert_resource(heartbeat);
+        }
+        Err(e) => eprintln!("Not listed, cannot reach master server {}: {}", master, e),
+    }
+}
+
+/// What this server currently tells clients about itself, `query_port` is the port of the
+/// socket that answers.
+fn server_info(
+    config: &ServerConfig,
+    protocol_version: &ProtocolVersion,
+    query_port: u16,
+    player_query: &Query<(), With<Player>>,
+    wave_query: &Query<&WaveStatus>,
+) -> ServerInfo {
+    let players = player_query.iter().count();
+    let wave = wave_query.iter().next().map_or(0, |status| status.wave);
+    let token_port =
+        (config.auth.mode == AuthMode::Secure).then(|| config.auth.token_service_address.port());
+    ServerInfo {
+        name: config.network.name.clone(),
+        game_port: config.network.public_address().port(),
+        query_port,
+        token_port,
+        players: players.min(u16::MAX as usize) as u16,
+        max_players: config.network.max_clients.min(u16::MAX as usize) as u16,
+        wave,
+        version: protocol_version.display(),
+        protocol_hash: protocol_version.hash,
+    }
+}
+
+/// Answers the `DiscoveryQuery`s waiting on `socket` that fit into the budget.
+fn answer_queries(
+    socket: &UdpSocket,
+    answers: &mut AnswerBudget,
+    info: impl Fn(u16) -> ServerInfo,
+) {
+    let query_port = socket.local_addr().map_or(0, |address| address.port());
+    let mut buffer = [0; 64];
+    while let Ok((len, from)) = socket.recv_from(&mut buffer) {
+        let Some(query) = DiscoveryQuery::from_bytes(&buffer[..len]) else {
+            continue;
+        };
+        if !answers.take() {
+            continue;
+        }
+
+        let response = DiscoveryResponse {
+            nonce: query.nonce,
+            info: info(query_port),
+        };
+        if let Err(e) = socket.send_to(&response.to_bytes(), from) {
+            eprintln!("Cannot answer discovery query from {}: {}", from, e);
+        }
+    }
+}
+
+pub fn answer_discovery_queries(
+    mut discovery: ResMut<DiscoverySocket>,
+    config: Res<ServerConfig>,
+    protocol_version: Res<ProtocolVersion>,
+    player_query: Query<(), With<Player>>,
+    wave_query: Query<&WaveStatus>,
+) {
+    let discovery = &mut *discovery;
+    answer_queries(&discovery.socket, &mut discovery.answers, |query_port| {
+        server_info(
+            &config,
+            &protocol_version,
+            query_port,
+            &player_query,
+            &wave_query,
+        )
+    });
+}
+
+/// Updates what the game socket answers version queries with.
+pub fn publish_server_info(
+    published: Res<PublishedServerInfo>,
+    config: Res<ServerConfig>,
+    protocol_version: Res<ProtocolVersion>,
+    player_query: Query<(), With<Player>>,
+    wave_query: Query<&WaveStatus>,
+) {
+    let info = server_info(
+        &config,
+        &protocol_version,
+        config.network.public_address().port(),
+        &player_query,
+        &wave_query,
+    );
+    *published.0.lock().unwrap() = Some(info);
+}
+
+pub fn send_master_heartbeats(
+    mut heartbeat: ResMut<MasterHeartbeat>,
+    config: Res<ServerConfig>,
+    protocol_version: Res<ProtocolVersion>,
+    player_query: Query<(), With<Player>>,
+    wave_query: Query<&WaveStatus>,
+    time: Res<Time>,
+) {
+    let info = |query_port| {
+        server_info(
+            &config,
+            &protocol_version,
+            query_port,
+            &player_query,
+            &wave_query,
+        )
+    };
+    // Pings of the server browser
+    let heartbeat = &mut *heartbeat;
+    answer_queries(&heartbeat.socket, &mut heartbeat.answers, info);
+
+    heartbeat.next -= time.delta_secs();
+    if heartbeat.next > 0.0 {
+        return;
+    }
+    heartbeat.next = HEARTBEAT_INTERVAL;
+
+    let query_port = heartbeat
+        .socket
+        .local_addr()
+        .map_or(0, |address| address.port());
+    let message = Heartbeat {
+        info: info(query_port),
+    };
+    if let Err(e) = heartbeat
+        .socket
+        .send_to(&message.to_bytes(), heartbeat.master)
+    {
+        eprintln!("Cannot send heartbeat to {}: {}", heartbeat.master, e);
+    }
+}
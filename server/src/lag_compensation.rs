@@ -0,0 +1,76 @@
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use std::collections::VecDeque;
+use zombrise_shared::players::player::Player;
+use zombrise_shared::shared::ServerTime;
+use zombrise_shared::zombie::zombie::Zombie;
+
+/// Seconds of positions kept, the longest rewind `gameplay.lag_compensation` may ask for.
+pub const HISTORY_SECONDS: f32 = 1.0;
+
+/// Recent positions of a player or zombie by `ServerTime`, oldest first, so attacks can be
+/// judged against where the attacker saw the target.
+#[derive(Component, Default)]
+pub struct PositionHistory(VecDeque<(f64, Vec3)>);
+
+impl PositionHistory {
+    /// Position at `time`, interpolated between the recorded ones and clamped to the
+    /// oldest and newest. `None` before anything was recorded.
+    pub fn position_at(&self, time: f64) -> Option<Vec3> {
+        match self.0.iter().position(|&(recorded, _)| recorded >= time) {
+            None => self.0.back().map(|&(_, position)| position),
+            Some(0) => self.0.front().map(|&(_, position)| position),
+            Some(index) => {
+                let (from_time, from) = self.0[index - 1];
+                let (to_time, to) = self.0[index];
+                let t = (time - from_time) / (to_time - from_time);
+                Some(from.lerp(to, t as f32))
+            }
+        }
+    }
+}
+
+pub fn spawn_server_clock(mut commands: Commands) {
+    commands.spawn((ServerTime::default(), Replicated));
+}
+
+pub fn update_server_time(mut query: Query<&mut ServerTime>, time: Res<Time>) {
+    for mut server_time in &mut query {
+        server_time.0 = time.elapsed_secs_f64();
+    }
+}
+
+pub fn add_position_history(
+    mut commands: Commands,
+    query: Query<Entity, (Or<(Added<Player>, Added<Zombie>)>, Without<PositionHistory>)>,
+) {
+    for entity in &query {
+        commands.entity(entity).insert(PositionHistory::default());
+    }
+}
+
+/// Records where everything is at the time that goes out with this tick's replication.
+pub fn record_position_history(
+    mut query: Query<(&Transform, &mut PositionHistory)>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs_f64();
+    for (transform, mut history) in &mut query {
+        history.0.push_back((now, transform.translation));
+        while history
+            .0
+            .front()
+            .is_some_and(|&(recorded, _)| now - recorded > HISTORY_SECONDS as f64)
+        {
+            history.0.pop_front();
+        }
+    }
+}
+
+/// Server time an attack is judged at: what the attacker saw, but no further back than
+/// `max_rewind` seconds so laggy players can't hit targets that are long gone.
+pub fn rewind_time(view_time: Option<f64>, now: f64, max_rewind: f32) -> f64 {
+    view_time.map_or(now, |view_time| {
+        view_time.clamp(now - max_rewind as f64, now)
+    })
+}
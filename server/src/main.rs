@@ -1,51 +1,153 @@
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 use bevy::{
-    app::ScheduleRunnerPlugin, asset::AssetPlugin, mesh::MeshPlugin, prelude::*,
+    app::ScheduleRunnerPlugin, asset::AssetPlugin, log::LogPlugin, mesh::MeshPlugin, prelude::*,
     scene::ScenePlugin, state::app::StatesPlugin,
 };
 use std::time::Duration;
 
 use avian3d::prelude::*;
 use bevy_replicon::prelude::*;
+use bevy_replicon::shared::backend::connected_client::NetworkId;
 use bevy_replicon_renet2::{
     netcode::{NetcodeServerTransport, ServerAuthentication},
     renet2::{ConnectionConfig, RenetServer, ServerEvent},
     RenetChannelsExt, RepliconRenetPlugins,
 };
-use rand::Rng;
-use renet2_netcode::NativeSocket;
-use std::{
-    net::{SocketAddr, UdpSocket},
-    time::SystemTime,
+use clap::Parser;
+use renet2_netcode::{NativeSocket, NETCODE_KEY_BYTES};
+use std::{collections::HashMap, net::UdpSocket, time::SystemTime};
+use zombrise_shared::players::movement::PLAYER_SPEED;
+use zombrise_shared::players::player::{
+    AttackConfirmed, AttackTarget, DamageFlash, Dead, Health, InputAck, Player, PlayerAttack,
+    PlayerName, PlayerOwner,
+};
+use zombrise_shared::protocol::conditioner::ConditionedSocket;
+use zombrise_shared::replication::transform::{
+    sync_replicated_transforms, NetTransform, StaticTransform,
+};
+use zombrise_shared::shared::{ChatMessage, MapMarker, ProtocolVersion, SharedPlugin, TreeMarker};
+use zombrise_shared::spatial::spatial_hash::{rebuild_spatial_index, SpatialIndex};
+use zombrise_shared::zombie::zombie::{
+    Dying, Zombie, ZombieAiState, ZombieAttackHit, ZombieKind, ZOMBIE_CHASE_RANGE,
+    ZOMBIE_DYING_DURATION,
+};
+
+mod config;
+use config::{AuthMode, Cli, Command, ServerConfig};
+
+mod auth;
+
+mod bandwidth;
+use bandwidth::log_bandwidth;
+
+mod chat;
+use chat::{announce_waves, handle_chat_messages, system_message, ChatLimiter};
+
+mod discovery;
+use discovery::{
+    answer_discovery_queries, publish_server_info, send_master_heartbeats, setup_discovery,
+    setup_master_heartbeat, AnsweringSocket, DiscoverySocket, MasterHeartbeat, PublishedServerInfo,
+};
+
+mod sessions;
+use sessions::{
+    client_user_data, expire_sessions, resume_session, suspend_session, Disconnected,
+    PlayerIdentity, Sessions,
+};
+
+mod respawn;
+use respawn::{handle_respawn_requests, mark_dead_players, tick_respawn_timers};
+
+mod zombie_kinds;
+use zombie_kinds::{AttackStyle, ZombieKinds};
+
+mod lag_compensation;
+use lag_compensation::{
+    add_position_history, record_position_history, rewind_time, spawn_server_clock,
+    update_server_time, PositionHistory,
 };
-use zombrise_shared::players::player::{DamageFlash, Health, Player, PlayerAttack, PlayerOwner};
-use zombrise_shared::shared::{MapMarker, MovePlayer, SharedPlugin, TreeMarker};
-use zombrise_shared::zombie::zombie::{Zombie, ZOMBIE_SPEED};
 
+mod movement;
+use movement::{apply_player_inputs, handle_move_player, PlayerInput};
+
+mod names;
+use names::{sanitize_name, unique_name};
+
+mod navigation;
+use navigation::{track_nav_obstacles, update_nav_grid, NavGrid};
+
+mod spit;
+use spit::{fly_spit, launch_spit};
+
+mod waves;
+use waves::{run_wave_director, spawn_wave_status, sync_wave_status, WaveDirector, ZombieStats};
+
+/// Key that connect tokens are checked against, `None` in unsecure mode.
 #[derive(Resource)]
-struct ZombieSpawnTimer(Timer);
-
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
-enum ZombieAiState {
-    #[default]
-    Idle,
-    Wandering,
-    Chasing,
+struct PrivateKey(Option<[u8; NETCODE_KEY_BYTES]>);
+
+/// Maps each connected client (by renet client id) to the player entity it controls.
+#[derive(Resource, Default)]
+struct PlayerIndex(HashMap<u64, Entity>);
+
+/// Seconds until the player is allowed to attack again.
+#[derive(Component, Default)]
+struct AttackCooldown {
+    remaining: f32,
+}
+
+/// Counts down until a dead zombie's corpse is removed.
+#[derive(Component)]
+struct CorpseTimer(Timer);
+
+/// Timing of a zombie's attack.
+#[derive(Component, Default)]
+struct ZombieAttack {
+    /// Seconds until the current attack lands, `None` when not attacking.
+    windup: Option<f32>,
+    /// Seconds until the zombie can start another attack.
+    cooldown: f32,
 }
 
 #[derive(Component)]
 struct ZombieBehavior {
-    state: ZombieAiState,
     timer: Timer,
     wander_direction: Vec3,
 }
 
 fn main() {
+    let cli = Cli::parse();
+    let config = ServerConfig::load(&cli).unwrap_or_else(|errors| exit_with_errors(&errors));
+
+    match cli.command {
+        Some(Command::TokenService) => {
+            auth::run_token_service(&config, &protocol_version())
+                .unwrap_or_else(|error| exit_with_errors(&[error]));
+            return;
+        }
+        Some(Command::GenerateKey { force }) => {
+            auth::generate_private_key(&config.auth.private_key_file, force)
+                .unwrap_or_else(|error| exit_with_errors(&[error]));
+            return;
+        }
+        None => {}
+    }
+
+    let private_key = (config.auth.mode == AuthMode::Secure).then(|| {
+        auth::load_private_key(&config.auth.private_key_file)
+            .unwrap_or_else(|error| exit_with_errors(&[error]))
+    });
+    let zombie_kinds = ZombieKinds::load(&config.gameplay.zombie_kinds)
+        .unwrap_or_else(|errors| exit_with_errors(&errors));
+
     App::new()
         .add_plugins(
             MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
-                1.0 / 60.0,
+                1.0 / config.simulation.update_rate,
             ))),
         )
+        .add_plugins(LogPlugin::default())
         .add_plugins(AssetPlugin::default())
         .add_plugins(MeshPlugin)
         .add_plugins(ScenePlugin)
@@ -55,72 +157,152 @@ fn main() {
         .add_plugins(RepliconRenetPlugins)
         .add_plugins(SharedPlugin)
         .add_plugins(PhysicsPlugins::default())
-        .insert_resource(Time::<Fixed>::from_hz(60.0))
-        .insert_resource(ZombieSpawnTimer(Timer::from_seconds(
-            20.0,
-            TimerMode::Repeating,
-        )))
-        .add_systems(Startup, setup_server)
+        .insert_resource(Time::<Fixed>::from_hz(config.simulation.tick_rate))
+        .insert_resource(zombie_kinds)
+        .insert_resource(WaveDirector::new(config.gameplay.waves.clone()))
+        .insert_resource(config)
+        .insert_resource(PrivateKey(private_key))
+        .init_resource::<NavGrid>()
+        .init_resource::<SpatialIndex>()
+        .init_resource::<PlayerIndex>()
+        .init_resource::<Sessions>()
+        .init_resource::<ChatLimiter>()
+        .init_resource::<PublishedServerInfo>()
+        .add_systems(
+            Startup,
+            (
+                setup_server,
+                setup_discovery,
+                setup_master_heartbeat,
+                spawn_wave_status,
+                spawn_server_clock,
+            ),
+        )
         .add_systems(
             Update,
             (
                 server_event_system,
-                handle_move_player,
+                handle_move_player.after(server_event_system),
+                update_server_time,
+                add_position_history,
+                record_position_history.before(handle_player_attack),
                 handle_player_attack,
+                handle_respawn_requests,
+                sync_replicated_transforms
+                    .after(handle_respawn_requests)
+                    .after(update_map_size),
                 update_map_size,
-                spawn_zombies,
+                run_wave_director,
+                sync_wave_status.after(run_wave_director),
+                expire_sessions,
+                answer_discovery_queries.run_if(resource_exists::<DiscoverySocket>),
+                send_master_heartbeats.run_if(resource_exists::<MasterHeartbeat>),
+                publish_server_info,
+                handle_chat_messages,
+                announce_waves.after(sync_wave_status),
+                log_bandwidth.run_if(|config: Res<ServerConfig>| config.network.log_bandwidth),
             ),
         )
         .add_systems(
             FixedUpdate,
             (
-                zombie_movement,
-                zombie_collision_damage,
+                apply_player_inputs,
+                rebuild_spatial_index,
+                track_nav_obstacles,
+                update_nav_grid.after(track_nav_obstacles),
+                zombie_movement
+                    .after(update_nav_grid)
+                    .after(rebuild_spatial_index),
+                zombie_attacks.after(zombie_movement),
+                kill_zombies.after(zombie_attacks),
+                fly_spit.after(rebuild_spatial_index),
                 update_damage_flash,
-                remove_dead_players,
+                update_attack_cooldowns,
+                mark_dead_players,
+                tick_respawn_timers,
                 remove_fallen_entities,
+                despawn_zombie_corpses,
             ),
         )
         .run();
 }
 
-fn setup_server(mut commands: Commands, network_channels: Res<RepliconChannels>) {
+/// Protocol version of this build, for the token service which doesn't run the game.
+fn protocol_version() -> ProtocolVersion {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, StatesPlugin, RepliconPlugins, SharedPlugin));
+    app.world().resource::<ProtocolVersion>().clone()
+}
+
+/// Prints configuration errors and stops the server before anything is started.
+fn exit_with_errors(errors: &[String]) -> ! {
+    eprintln!("Invalid server configuration:");
+    for error in errors {
+        eprintln!("  - {}", error);
+    }
+    std::process::exit(1);
+}
+
+fn setup_server(
+    mut commands: Commands,
+    network_channels: Res<RepliconChannels>,
+    config: Res<ServerConfig>,
+    private_key: Res<PrivateKey>,
+    protocol_version: Res<ProtocolVersion>,
+    published_info: Res<PublishedServerInfo>,
+) {
     let server_channels_config = network_channels.server_configs();
     let client_channels_config = network_channels.client_configs();
+    let network = &config.network;
 
     let server = RenetServer::new(ConnectionConfig {
         server_channels_config,
         client_channels_config,
-        available_bytes_per_tick: 16 * 1024,
+        available_bytes_per_tick: network.available_bytes_per_tick,
     });
 
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap();
 
-    let public_addr: SocketAddr = "0.0.0.0:5000".parse().unwrap();
-    let socket = UdpSocket::bind(public_addr).unwrap();
+    let socket = UdpSocket::bind(network.bind_address).unwrap_or_else(|e| {
+        exit_with_errors(&[format!("Cannot bind {}: {}", network.bind_address, e)])
+    });
     let native_socket = NativeSocket::new(socket).unwrap();
+    if network.simulate.is_active() {
+        println!("Simulating a bad network: {}", network.simulate);
+    }
+    let socket = AnsweringSocket::new(
+        ConditionedSocket::new(native_socket, network.simulate.clone()),
+        published_info.clone(),
+    );
+
+    let authentication = match private_key.0 {
+        Some(private_key) => ServerAuthentication::Secure { private_key },
+        None => ServerAuthentication::Unsecure,
+    };
 
-    let socket_addresses = vec![vec![public_addr]];
+    let socket_addresses = vec![vec![network.public_address()]];
     let server_setup_config = bevy_replicon_renet2::netcode::ServerSetupConfig {
         current_time,
-        max_clients: 10,
-        protocol_id: 0,
+        max_clients: network.max_clients,
+        protocol_id: network.netcode_protocol_id(&protocol_version),
         socket_addresses,
-        authentication: ServerAuthentication::Unsecure,
+        authentication,
     };
 
-    let transport = NetcodeServerTransport::new(server_setup_config, native_socket).unwrap();
+    let transport = NetcodeServerTransport::new(server_setup_config, socket).unwrap();
 
     commands.insert_resource(server);
     commands.insert_resource(transport);
 
     // Add ground (flat surface)
+    let ground_transform = Transform::from_xyz(0.0, -0.05, 0.0);
     commands.spawn((
         MapMarker,
         Replicated,
-        Transform::from_xyz(0.0, -0.05, 0.0),
+        ground_transform,
+        StaticTransform(ground_transform),
         RigidBody::Static,
         Collider::cuboid(56.0, 0.1, 56.0), // Flat ground: 56x0.1x56 units
     ));
@@ -136,84 +318,179 @@ fn setup_server(mut commands: Commands, network_channels: Res<RepliconChannels>)
     ];
 
     for position in tree_positions {
+        let transform = Transform::from_translation(position);
         commands.spawn((
             TreeMarker,
             Replicated,
-            Transform::from_translation(position),
+            transform,
+            StaticTransform(transform),
             GlobalTransform::default(),
             RigidBody::Static,
             Collider::cylinder(0.3, 2.0), // Collision cylinder for tree trunk and canopy
         ));
     }
 
-    println!("Server started on {}", public_addr);
+    println!(
+        "Server {} started on {} (public address {}, up to {} clients, {:?} authentication)",
+        protocol_version.display(),
+        network.bind_address,
+        network.public_address(),
+        network.max_clients,
+        config.auth.mode
+    );
 }
 
-fn server_event_system(mut commands: Commands, mut server_events: MessageReader<ServerEvent>) {
+fn server_event_system(
+    mut commands: Commands,
+    mut server_events: MessageReader<ServerEvent>,
+    mut player_index: ResMut<PlayerIndex>,
+    mut sessions: ResMut<Sessions>,
+    transport: Res<NetcodeServerTransport>,
+    session_query: Query<(&PlayerIdentity, Has<Disconnected>)>,
+    identity_query: Query<&PlayerIdentity>,
+    name_query: Query<(Entity, &PlayerName)>,
+    mut chat: MessageWriter<ToClients<ChatMessage>>,
+    config: Res<ServerConfig>,
+) {
+    // Names handed out this frame, their components only show up once the commands ran
+    let mut assigned_names: Vec<(Entity, String)> = Vec::new();
+
     for event in server_events.read() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
                 println!("Client {:?} connected", client_id);
-                // Spawn player for client
-                commands.spawn((
-                    Player,
-                    PlayerOwner(*client_id),
-                    Health::default(),
-                    DamageFlash::default(),
-                    Replicated,
-                    Transform::from_xyz(0.0, 0.5, 0.0),
-                    GlobalTransform::default(),
-                    RigidBody::Dynamic,
-                    Collider::capsule(0.5, 1.0),
-                    LinearVelocity::ZERO,
-                    AngularVelocity::ZERO,
-                    LockedAxes::new().lock_rotation_x().lock_rotation_z(),
-                    LinearDamping(0.5),
-                    AngularDamping(20.0),
-                ));
+                let user_data = client_user_data(&transport, *client_id);
+                let identity = user_data.identity;
+
+                // Give a returning player their old entity back. Only sessions whose client
+                // is gone can be taken over, and only with the secret they were created with.
+                let session = identity.and_then(|identity| sessions.0.get(&identity).copied());
+                let mut identity_in_use = false;
+                if let Some(entity) = session {
+                    if let Ok((session_identity, disconnected)) = session_query.get(entity) {
+                        if !disconnected || session_identity.secret != user_data.secret {
+                            println!(
+                                "Identity of client {:?} is in use, it joins as a new player",
+                                client_id
+                            );
+                            identity_in_use = true;
+                        } else {
+                            let name = player_name(
+                                &user_data.name,
+                                Some(entity),
+                                &name_query,
+                                &assigned_names,
+                            );
+                            println!("{} is back as client {:?}", name, client_id);
+                            chat.write(system_message(format!("{} is back", name)));
+                            resume_session(
+                                &mut commands,
+                                entity,
+                                *client_id,
+                                PlayerName(name.clone()),
+                            );
+                            assigned_names.push((entity, name));
+                            player_index.0.insert(*client_id, entity);
+                            continue;
+                        }
+                    }
+                }
+
+                let name = player_name(&user_data.name, None, &name_query, &assigned_names);
+                println!("{} joined as client {:?}", name, client_id);
+                chat.write(system_message(format!("{} joined", name)));
+                let player = spawn_player(&mut commands, *client_id, PlayerName(name.clone()));
+                assigned_names.push((player, name));
+                // A player whose identity is taken can't be resumed, the session stays with
+                // the client that created it
+                if let Some(identity) = identity.filter(|_| !identity_in_use) {
+                    commands.entity(player).insert(PlayerIdentity {
+                        id: identity,
+                        secret: user_data.secret,
+                    });
+                    sessions.0.insert(identity, player);
+                }
+                player_index.0.insert(*client_id, player);
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
                 println!("Client {:?} disconnected: {:?}", client_id, reason);
+                let Some(entity) = player_index.0.remove(client_id) else {
+                    continue;
+                };
+                if let Ok((_, name)) = name_query.get(entity) {
+                    println!("{} left", name.0);
+                    chat.write(system_message(format!("{} left", name.0)));
+                }
+
+                // Keep the player around for a while in case the client comes back
+                if identity_query.contains(entity) && config.network.session_grace_period > 0.0 {
+                    suspend_session(&mut commands, entity, config.network.session_grace_period);
+                } else {
+                    if let Ok(identity) = identity_query.get(entity) {
+                        sessions.0.remove(&identity.id);
+                    }
+                    commands.entity(entity).try_despawn();
+                }
             }
         }
     }
 }
 
-fn handle_move_player(
-    mut events: MessageReader<FromClient<MovePlayer>>,
-    mut query: Query<(&PlayerOwner, &mut LinearVelocity, &mut Transform)>,
-) {
-    let speed = 5.0;
-    for FromClient {
-        message: event,
-        client_id: _,
-    } in events.read()
-    {
-        for (_owner, mut velocity, mut transform) in &mut query {
-            // if owner.0 != *client_id {
-            //     continue;
-            // }
-
-            let yaw_rotation = Quat::from_rotation_y(event.camera_yaw);
-            let rotated_direction = yaw_rotation * event.direction;
+/// Sanitized name for a connecting client that no other player has, including suspended
+/// players who may come back. `entity` is the client's own player when resuming a session.
+fn player_name(
+    requested: &str,
+    entity: Option<Entity>,
+    name_query: &Query<(Entity, &PlayerName)>,
+    assigned_names: &[(Entity, String)],
+) -> String {
+    let taken: Vec<&str> = name_query
+        .iter()
+        .map(|(other, name)| (other, name.0.as_str()))
+        .chain(
+            assigned_names
+                .iter()
+                .map(|(other, name)| (*other, name.as_str())),
+        )
+        .filter(|(other, _)| Some(*other) != entity)
+        .map(|(_, name)| name)
+        .collect();
+    unique_name(sanitize_name(requested), &taken)
+}
 
-            velocity.x = rotated_direction.x * speed;
-            velocity.z = rotated_direction.z * speed; // Rotate player to face movement direction (only in XZ plane)
-            let horizontal_direction = Vec3::new(rotated_direction.x, 0.0, rotated_direction.z);
-            if horizontal_direction.length() > 0.01 {
-                let target_rotation =
-                    Quat::from_rotation_arc(Vec3::NEG_Z, horizontal_direction.normalize());
-                transform.rotation = target_rotation;
-            }
+fn spawn_player(commands: &mut Commands, client_id: u64, name: PlayerName) -> Entity {
+    let transform = Transform::from_xyz(0.0, 0.5, 0.0);
+    commands
+        .spawn((
+            Player,
+            PlayerOwner(client_id),
+            name,
+            Health::default(),
+            DamageFlash::default(),
+            AttackCooldown::default(),
+            PlayerInput::default(),
+            InputAck::default(),
+            Replicated,
+            transform,
+            NetTransform::from_transform(&transform),
+            GlobalTransform::default(),
+            (
+                RigidBody::Dynamic,
+                Collider::capsule(0.5, 1.0),
+                LinearVelocity::ZERO,
+                AngularVelocity::ZERO,
+                LockedAxes::new().lock_rotation_x().lock_rotation_z(),
+                LinearDamping(0.5),
+                AngularDamping(20.0),
+            ),
+        ))
+        .id()
+}
 
-            if event.direction.y > 0.0 {
-                // Check if on ground, for simplicity, assume if y velocity is small
-                if velocity.y.abs() < 0.1 {
-                    velocity.y = 5.0; // jump velocity
-                }
-            }
-        }
-    }
+/// Resolves the renet client id of the client that sent a replicon message.
+fn sender_network_id(client_id: ClientId, network_ids: &Query<&NetworkId>) -> Option<u64> {
+    let client_entity = client_id.entity()?;
+    network_ids.get(client_entity).ok().map(|id| id.get())
 }
 
 fn update_map_size(
@@ -229,107 +506,91 @@ fn update_map_size(
     }
 }
 
-fn spawn_zombies(
-    mut commands: Commands,
-    time: Res<Time>,
-    mut timer: ResMut<ZombieSpawnTimer>,
-    zombie_query: Query<&Zombie>,
-) {
-    if timer.0.tick(time.delta()).just_finished() {
-        let zombie_count = zombie_query.iter().count();
-        if zombie_count >= 30 {
-            return;
-        }
-
-        let mut rng = rand::rng();
-        let x = rng.random_range(-20.0..20.0);
-        let z = rng.random_range(-20.0..20.0);
-
-        commands.spawn((
-            Zombie,
-            Replicated,
-            Transform::from_xyz(x, 0.5, z),
-            GlobalTransform::default(),
-            RigidBody::Dynamic,
-            Collider::capsule(0.5, 1.0),
-            LinearVelocity::ZERO,
-            AngularVelocity::ZERO,
-            LockedAxes::new().lock_rotation_x().lock_rotation_z(),
-            LinearDamping(0.5),
-            AngularDamping(20.0),
-            ZombieBehavior {
-                state: ZombieAiState::Idle,
-                timer: Timer::from_seconds(rng.random_range(1.0..3.0), TimerMode::Once),
-                wander_direction: Vec3::ZERO,
-            },
-        ));
-        println!("Zombie spawned at {}, {}", x, z);
-    }
-}
-
 fn zombie_movement(
     mut zombie_query: Query<
-        (&mut LinearVelocity, &mut Transform, &mut ZombieBehavior),
-        (With<Zombie>, Without<Player>),
+        (
+            &mut LinearVelocity,
+            &mut Transform,
+            &mut ZombieBehavior,
+            &mut ZombieAiState,
+            &ZombieStats,
+        ),
+        (With<Zombie>, Without<Player>, Without<Dying>),
     >,
-    player_query: Query<&Transform, (With<Player>, Without<Zombie>)>,
+    spatial_index: Res<SpatialIndex>,
+    nav_grid: Res<NavGrid>,
     time: Res<Time>,
 ) {
-    let speed = ZOMBIE_SPEED;
-    let chase_range = 10.0;
-
-    for (mut lin_vel, mut zombie_transform, mut behavior) in &mut zombie_query {
-        let mut nearest_player_pos: Option<Vec3> = None;
-        let mut min_dist = f32::MAX;
-
-        for player_transform in &player_query {
-            let dist = zombie_transform
-                .translation
-                .distance(player_transform.translation);
-            if dist < min_dist {
-                min_dist = dist;
-                nearest_player_pos = Some(player_transform.translation);
-            }
+    // Close to the player the straight line is always free
+    const DIRECT_CHASE_RANGE: f32 = 2.0;
+
+    for (mut lin_vel, mut zombie_transform, mut behavior, mut ai_state, stats) in &mut zombie_query
+    {
+        let speed = stats.speed;
+
+        // The wind-up plays out in place until `zombie_attacks` resolves the hit, so the
+        // attacking clip clients play and the damage always go together
+        if *ai_state == ZombieAiState::Attacking {
+            lin_vel.x = 0.0;
+            lin_vel.z = 0.0;
+            continue;
         }
 
-        // Check if we should chase
-        if let Some(player_pos) = nearest_player_pos {
-            if min_dist < chase_range {
-                behavior.state = ZombieAiState::Chasing;
+        // Check if we should attack or chase
+        if let Some((_, player_pos, min_dist)) = spatial_index
+            .players
+            .nearest(zombie_transform.translation, ZOMBIE_CHASE_RANGE)
+        {
+            let mut direction = (player_pos - zombie_transform.translation).normalize_or_zero();
+
+            if min_dist < stats.attack_range {
+                // Stand still while attacking, `zombie_attacks` switches to Attacking for
+                // the wind-up of every attack
+                ai_state.set_if_neq(ZombieAiState::InReach);
+                lin_vel.x = 0.0;
+                lin_vel.z = 0.0;
+            } else {
+                ai_state.set_if_neq(ZombieAiState::Chasing);
 
-                // Chase logic
-                let direction = (player_pos - zombie_transform.translation).normalize_or_zero();
+                // Walk around obstacles along the flow field
+                if min_dist > DIRECT_CHASE_RANGE {
+                    if let Some(path_direction) =
+                        nav_grid.direction_to_player(zombie_transform.translation)
+                    {
+                        direction = path_direction;
+                    }
+                }
                 lin_vel.x = direction.x * speed;
                 lin_vel.z = direction.z * speed;
+            }
 
-                // Rotate to face player
-                let horizontal_direction = Vec3::new(direction.x, 0.0, direction.z);
-                if horizontal_direction.length() > 0.01 {
-                    let target_rotation =
-                        Quat::from_rotation_arc(Vec3::NEG_Z, horizontal_direction.normalize());
-                    zombie_transform.rotation = target_rotation;
-                }
-                continue;
+            // Rotate to face player
+            let horizontal_direction = Vec3::new(direction.x, 0.0, direction.z);
+            if horizontal_direction.length() > 0.01 {
+                let target_rotation =
+                    Quat::from_rotation_arc(Vec3::NEG_Z, horizontal_direction.normalize());
+                zombie_transform.rotation = target_rotation;
             }
+            continue;
         }
 
         // If we were chasing but lost the player, go back to idle
-        if behavior.state == ZombieAiState::Chasing {
-            behavior.state = ZombieAiState::Idle;
+        if matches!(*ai_state, ZombieAiState::Chasing | ZombieAiState::InReach) {
+            *ai_state = ZombieAiState::Idle;
             behavior.timer = Timer::from_seconds(1.0, TimerMode::Once);
         }
 
         // Handle Idle and Wandering states
         behavior.timer.tick(time.delta());
 
-        match behavior.state {
+        match *ai_state {
             ZombieAiState::Idle => {
                 lin_vel.x = 0.0;
                 lin_vel.z = 0.0;
 
                 if behavior.timer.is_finished() {
                     // Switch to Wandering
-                    behavior.state = ZombieAiState::Wandering;
+                    *ai_state = ZombieAiState::Wandering;
                     behavior.timer =
                         Timer::from_seconds(rand::random::<f32>() * 2.0 + 2.0, TimerMode::Once); // Wander for 2-4 seconds
 
@@ -360,95 +621,351 @@ fn zombie_movement(
 
                 if behavior.timer.is_finished() {
                     // Switch to Idle
-                    behavior.state = ZombieAiState::Idle;
+                    *ai_state = ZombieAiState::Idle;
                     behavior.timer =
                         Timer::from_seconds(rand::random::<f32>() * 2.0 + 1.0, TimerMode::Once);
                     // Idle for 1-3 seconds
                 }
             }
-            _ => {} // Chasing is handled above
+            _ => {} // Chasing and Attacking are handled above
         }
     }
 }
 
-fn zombie_collision_damage(
-    zombie_query: Query<&Transform, With<Zombie>>,
-    mut player_query: Query<(&Transform, &mut Health, &mut DamageFlash), With<Player>>,
+fn zombie_attacks(
+    mut commands: Commands,
+    mut zombie_query: Query<
+        (
+            &Transform,
+            &ZombieKind,
+            &mut ZombieAiState,
+            &ZombieStats,
+            &mut ZombieAttack,
+            &mut Health,
+        ),
+        (With<Zombie>, Without<Player>, Without<Dying>),
+    >,
+    mut player_query: Query<
+        (
+            &Transform,
+            &PlayerOwner,
+            &PlayerName,
+            &mut Health,
+            &mut DamageFlash,
+        ),
+        (With<Player>, Without<Dead>),
+    >,
+    spatial_index: Res<SpatialIndex>,
+    zombie_kinds: Res<ZombieKinds>,
+    mut hits: MessageWriter<ToClients<ZombieAttackHit>>,
     time: Res<Time>,
 ) {
-    const DAMAGE_PER_SECOND: f32 = 10.0;
-    const COLLISION_DISTANCE: f32 = 1.5;
+    for (zombie_transform, kind, mut ai_state, stats, mut attack, mut zombie_health) in
+        &mut zombie_query
+    {
+        attack.cooldown = (attack.cooldown - time.delta_secs()).max(0.0);
 
-    for zombie_transform in &zombie_query {
-        for (player_transform, mut health, mut damage_flash) in &mut player_query {
-            let distance = zombie_transform
-                .translation
-                .distance(player_transform.translation);
+        let Some(windup) = attack.windup else {
+            if *ai_state == ZombieAiState::InReach && attack.cooldown <= 0.0 {
+                // Clients start the attacking clip when this arrives, so the blow lands
+                // in the clip when it lands here
+                attack.windup = Some(kind.attack_windup());
+                *ai_state = ZombieAiState::Attacking;
+            }
+            continue;
+        };
 
-            if distance < COLLISION_DISTANCE && health.current > 0.0 {
-                let damage = DAMAGE_PER_SECOND * time.delta_secs();
-                health.current = (health.current - damage).max(0.0);
-                damage_flash.timer = 0.3; // Flash for 0.3 seconds
+        let windup = windup - time.delta_secs();
+        if windup > 0.0 {
+            attack.windup = Some(windup);
+            continue;
+        }
+
+        attack.windup = None;
+        attack.cooldown = stats.attack_cooldown;
+        if *ai_state == ZombieAiState::Attacking {
+            *ai_state = ZombieAiState::InReach;
+        }
 
-                if health.current <= 0.0 {
-                    println!("Player died!");
+        match zombie_kinds.get(*kind).attack {
+            AttackStyle::Melee => {}
+            AttackStyle::Ranged => {
+                // The spit hits whoever it touches first, see `fly_spit`
+                if let Some((_, target, _)) = spatial_index
+                    .players
+                    .nearest(zombie_transform.translation, stats.attack_range)
+                {
+                    launch_spit(
+                        &mut commands,
+                        zombie_transform.translation,
+                        target,
+                        stats.attack_range,
+                        stats.damage,
+                        *kind,
+                    );
                 }
+                continue;
+            }
+            AttackStyle::Explode { .. } => {
+                // Exploders sacrifice themselves, the blast is dealt by `kill_zombies`
+                zombie_health.current = 0.0;
+                continue;
+            }
+        }
+
+        // Players can still be hit while stepping back during the wind-up
+        let reach = stats.attack_range + 0.5;
+
+        // The blow lands on the nearest player in reach
+        let Some((target, _, _)) = spatial_index
+            .players
+            .nearest(zombie_transform.translation, reach)
+        else {
+            continue;
+        };
+
+        if let Ok((player_transform, owner, name, mut health, mut damage_flash)) =
+            player_query.get_mut(target)
+        {
+            health.current = (health.current - stats.damage).max(0.0);
+            damage_flash.timer = 0.3; // Flash for 0.3 seconds
+            hits.write(ToClients {
+                mode: SendMode::Broadcast,
+                message: ZombieAttackHit {
+                    target: owner.0,
+                    position: player_transform.translation,
+                    damage: stats.damage,
+                },
+            });
+
+            if health.current <= 0.0 {
+                println!("{} was killed by a {:?} zombie", name.0, kind);
             }
         }
     }
 }
 
+/// Turns zombies without health into corpses and sets off exploders.
+fn kill_zombies(
+    mut commands: Commands,
+    mut zombie_query: Query<
+        (
+            Entity,
+            &Transform,
+            &ZombieKind,
+            &mut Health,
+            &mut LinearVelocity,
+        ),
+        (With<Zombie>, Without<Player>, Without<Dying>),
+    >,
+    mut player_query: Query<
+        (&Transform, &PlayerOwner, &mut Health, &mut DamageFlash),
+        (With<Player>, Without<Dead>),
+    >,
+    spatial_index: Res<SpatialIndex>,
+    zombie_kinds: Res<ZombieKinds>,
+    mut hits: MessageWriter<ToClients<ZombieAttackHit>>,
+) {
+    let mut explosions = Vec::new();
+
+    for (entity, transform, kind, health, mut velocity) in &mut zombie_query {
+        if health.current > 0.0 {
+            continue;
+        }
+
+        // Freeze the corpse in place and let it play the dying animation
+        velocity.0 = Vec3::ZERO;
+        commands
+            .entity(entity)
+            .insert((
+                Dying,
+                CorpseTimer(Timer::from_seconds(ZOMBIE_DYING_DURATION, TimerMode::Once)),
+                RigidBody::Kinematic,
+            ))
+            .remove::<Collider>();
+        println!("{:?} zombie died", kind);
+
+        if let AttackStyle::Explode { radius, damage } = zombie_kinds.get(*kind).attack {
+            explosions.push((entity, transform.translation, radius, damage));
+        }
+    }
+
+    for (source, center, radius, damage) in explosions {
+        println!("Zombie exploded at {}", center);
+
+        for (entity, _, _) in spatial_index.players.within_radius(center, radius) {
+            let Ok((player_transform, owner, mut health, mut damage_flash)) =
+                player_query.get_mut(entity)
+            else {
+                continue;
+            };
+            health.current = (health.current - damage).max(0.0);
+            damage_flash.timer = 0.3;
+            hits.write(ToClients {
+                mode: SendMode::Broadcast,
+                message: ZombieAttackHit {
+                    target: owner.0,
+                    position: player_transform.translation,
+                    damage,
+                },
+            });
+        }
+
+        // Nearby zombies are caught in the blast too and may chain next tick
+        for (entity, _, _) in spatial_index.zombies.within_radius(center, radius) {
+            if entity == source {
+                continue;
+            }
+            if let Ok((_, _, _, mut health, _)) = zombie_query.get_mut(entity) {
+                health.current = (health.current - damage).max(0.0);
+            }
+        }
+    }
+}
+
+const ATTACK_RANGE: f32 = 2.0;
+/// Half of the opening angle of the cone in front of the attacker that can be hit.
+const ATTACK_HALF_ANGLE: f32 = std::f32::consts::FRAC_PI_3;
+const ATTACK_COOLDOWN: f32 = 0.5;
+
+/// Checks whether `target` is in range and inside the forward-facing attack cone.
+fn in_attack_cone(attacker: &Transform, target: Vec3) -> bool {
+    let to_target = target - attacker.translation;
+    if to_target.length() >= ATTACK_RANGE {
+        return false;
+    }
+
+    let horizontal = Vec3::new(to_target.x, 0.0, to_target.z);
+    if horizontal.length() < 0.01 {
+        // Overlapping targets are always hit
+        return true;
+    }
+
+    let forward = attacker.rotation * Vec3::NEG_Z;
+    let forward = Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
+    forward.dot(horizontal.normalize()) >= ATTACK_HALF_ANGLE.cos()
+}
+
 fn handle_player_attack(
     mut events: MessageReader<FromClient<PlayerAttack>>,
+    network_ids: Query<&NetworkId>,
+    player_index: Res<PlayerIndex>,
     mut player_query: Query<
         (
-            Entity,
             &PlayerOwner,
+            &PlayerName,
             &Transform,
+            Option<&PositionHistory>,
             &mut Health,
             &mut DamageFlash,
+            &mut AttackCooldown,
         ),
         With<Player>,
     >,
-    mut zombie_query: Query<(Entity, &Transform), With<Zombie>>,
-    mut commands: Commands,
+    mut zombie_query: Query<
+        (&Transform, Option<&PositionHistory>, &mut Health),
+        (With<Zombie>, Without<Player>, Without<Dying>),
+    >,
+    spatial_index: Res<SpatialIndex>,
+    mut confirmations: MessageWriter<ToClients<AttackConfirmed>>,
+    config: Res<ServerConfig>,
+    time: Res<Time>,
 ) {
-    const ATTACK_RANGE: f32 = 2.0;
     const PLAYER_DAMAGE: f32 = 10.0;
+    // The index is rebuilt on the fixed tick, leave room for movement since then
+    const INDEX_SLACK: f32 = 0.5;
 
-    for FromClient { .. } in events.read() {
-        let mut attacker_pos: Option<Vec3> = None;
-        let mut attacker_entity: Option<Entity> = None;
+    let now = time.elapsed_secs_f64();
 
-        // Find the attacking player (there should only be one per event)
-        for (entity, _, transform, _, _) in &player_query {
-            attacker_pos = Some(transform.translation);
-            attacker_entity = Some(entity);
-            break;
-        }
+    for FromClient { client_id, message } in events.read() {
+        let Some(owner) = sender_network_id(*client_id, &network_ids) else {
+            continue;
+        };
+        let Some(&attacker_entity) = player_index.0.get(&owner) else {
+            continue;
+        };
 
-        if let Some(attacker_pos) = attacker_pos {
-            // Attack Zombies
-            for (zombie_entity, zombie_transform) in &mut zombie_query {
-                let distance = attacker_pos.distance(zombie_transform.translation);
+        // Resolve the attack from the sender's own player
+        let (attacker_name, attacker_transform) = {
+            let Ok((_, name, transform, _, health, _, mut cooldown)) =
+                player_query.get_mut(attacker_entity)
+            else {
+                continue;
+            };
+            if health.current <= 0.0 || cooldown.remaining > 0.0 {
+                continue;
+            }
+            cooldown.remaining = ATTACK_COOLDOWN;
+            (name.0.clone(), *transform)
+        };
 
-                if distance < ATTACK_RANGE {
-                    commands.entity(zombie_entity).despawn();
-                    println!("Player attacked zombie at distance {}", distance);
-                }
+        // Targets are tested where the attacker saw them, the index has them where they are now
+        let rewind_to = rewind_time(message.view_time, now, config.gameplay.lag_compensation);
+        let rewind_slack = (now - rewind_to) as f32 * PLAYER_SPEED;
+        let search_radius = ATTACK_RANGE + INDEX_SLACK + rewind_slack;
+        let rewound = |transform: &Transform, history: Option<&PositionHistory>| {
+            history
+                .and_then(|history| history.position_at(rewind_to))
+                .unwrap_or(transform.translation)
+        };
+
+        // Attack Zombies
+        for (entity, _, _) in spatial_index
+            .zombies
+            .within_radius(attacker_transform.translation, search_radius)
+        {
+            let Ok((zombie_transform, history, mut health)) = zombie_query.get_mut(entity) else {
+                continue;
+            };
+            let position = rewound(zombie_transform, history);
+            if health.current > 0.0 && in_attack_cone(&attacker_transform, position) {
+                health.current = (health.current - PLAYER_DAMAGE).max(0.0);
+                confirmations.write(ToClients {
+                    mode: SendMode::Broadcast,
+                    message: AttackConfirmed {
+                        attacker: owner,
+                        target: AttackTarget::Zombie,
+                        position,
+                    },
+                });
+                println!("{} hit a zombie", attacker_name);
             }
+        }
 
-            // Attack other Players
-            for (entity, _, transform, mut health, mut damage_flash) in &mut player_query {
-                if Some(entity) != attacker_entity {
-                    let distance = attacker_pos.distance(transform.translation);
+        // Attack other Players
+        for (entity, _, _) in spatial_index
+            .players
+            .within_radius(attacker_transform.translation, search_radius)
+        {
+            let Ok((
+                target_owner,
+                target_name,
+                transform,
+                history,
+                mut health,
+                mut damage_flash,
+                _,
+            )) = player_query.get_mut(entity)
+            else {
+                continue;
+            };
+            if target_owner.0 == owner || health.current <= 0.0 {
+                continue;
+            }
 
-                    if distance < ATTACK_RANGE {
-                        health.current = (health.current - PLAYER_DAMAGE).max(0.0);
-                        damage_flash.timer = 0.3;
-                        println!("Player attacked another player at distance {}", distance);
-                    }
-                }
+            let position = rewound(transform, history);
+            if in_attack_cone(&attacker_transform, position) {
+                health.current = (health.current - PLAYER_DAMAGE).max(0.0);
+                damage_flash.timer = 0.3;
+                confirmations.write(ToClients {
+                    mode: SendMode::Broadcast,
+                    message: AttackConfirmed {
+                        attacker: owner,
+                        target: AttackTarget::Player(target_owner.0),
+                        position,
+                    },
+                });
+                println!("{} hit {}", attacker_name, target_name.0);
             }
         }
     }
@@ -465,34 +982,38 @@ fn update_damage_flash(mut query: Query<&mut DamageFlash>, time: Res<Time>) {
     }
 }
 
-fn remove_dead_players(
+fn despawn_zombie_corpses(
     mut commands: Commands,
-    player_query: Query<(Entity, &Health, &PlayerOwner), With<Player>>,
-    mut server: ResMut<RenetServer>,
+    mut corpse_query: Query<(Entity, &mut CorpseTimer)>,
+    time: Res<Time>,
 ) {
-    for (entity, health, owner) in &player_query {
-        if health.current <= 0.0 {
-            println!("Removing dead player (Client ID: {:?})", owner.0);
+    for (entity, mut timer) in &mut corpse_query {
+        if timer.0.tick(time.delta()).is_finished() {
             commands.entity(entity).despawn();
-            server.disconnect(owner.0);
+        }
+    }
+}
+
+fn update_attack_cooldowns(mut query: Query<&mut AttackCooldown>, time: Res<Time>) {
+    for mut cooldown in &mut query {
+        if cooldown.remaining > 0.0 {
+            cooldown.remaining = (cooldown.remaining - time.delta_secs()).max(0.0);
         }
     }
 }
 
 fn remove_fallen_entities(
     mut commands: Commands,
-    player_query: Query<(Entity, &Transform, &PlayerOwner), With<Player>>,
+    mut player_query: Query<(&Transform, &PlayerName, &mut Health), (With<Player>, Without<Dead>)>,
     zombie_query: Query<(Entity, &Transform), With<Zombie>>,
-    mut server: ResMut<RenetServer>,
 ) {
     const FALL_DEATH_Y: f32 = -10.0;
 
-    // Remove fallen players
-    for (entity, transform, owner) in &player_query {
+    // Kill fallen players, they respawn like any other dead player
+    for (transform, name, mut health) in &mut player_query {
         if transform.translation.y < FALL_DEATH_Y {
-            println!("Player fell to death (Client ID: {:?})", owner.0);
-            commands.entity(entity).despawn();
-            server.disconnect(owner.0);
+            println!("{} fell to death", name.0);
+            health.current = 0.0;
         }
     }
 
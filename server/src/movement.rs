@@ -0,0 +1,172 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon::shared::backend::connected_client::NetworkId;
+use zombrise_shared::players::movement::{player_movement, PLAYER_JUMP_VELOCITY};
+use zombrise_shared::players::player::{Health, InputAck, MovePlayer, Player};
+
+use crate::{sender_network_id, PlayerIndex};
+
+/// Latest movement input of a player, applied on every physics step until the next one arrives.
+#[derive(Component, Default)]
+pub struct PlayerInput(Option<MovePlayer>);
+
+/// Stores the newest input of every client that still owns a player. Inputs travel
+/// unreliably, so ones older than what the player already has are dropped.
+pub fn handle_move_player(
+    mut events: MessageReader<FromClient<MovePlayer>>,
+    network_ids: Query<&NetworkId>,
+    player_index: Res<PlayerIndex>,
+    mut query: Query<&mut PlayerInput, With<Player>>,
+) {
+    for FromClient { client_id, message } in events.read() {
+        let Some(owner) = sender_network_id(*client_id, &network_ids) else {
+            continue;
+        };
+        let Some(mut input) = player_index
+            .0
+            .get(&owner)
+            .and_then(|&entity| query.get_mut(entity).ok())
+        else {
+            continue;
+        };
+        if input
+            .0
+            .as_ref()
+            .is_none_or(|latest| message.sequence > latest.sequence)
+        {
+            input.0 = Some(message.clone());
+        }
+    }
+}
+
+/// Sets the velocity of players from their input. Runs before the physics step, so the
+/// `NetTransform` replicated with `InputAck` already contains the acknowledged input.
+pub fn apply_player_inputs(
+    mut query: Query<
+        (
+            &PlayerInput,
+            &Health,
+            &mut LinearVelocity,
+            &mut Transform,
+            &mut InputAck,
+        ),
+        With<Player>,
+    >,
+) {
+    for (input, health, mut velocity, mut transform, mut ack) in &mut query {
+        let Some(input) = &input.0 else {
+            continue;
+        };
+        // Acknowledged even when dead, the client doesn't predict dead players
+        ack.set_if_neq(InputAck(input.sequence));
+        if health.current <= 0.0 {
+            continue;
+        }
+
+        let movement = player_movement(input);
+        velocity.x = movement.velocity.x;
+        velocity.z = movement.velocity.z;
+        if let Some(rotation) = movement.rotation {
+            transform.rotation = rotation;
+        }
+
+        // Check if on ground, for simplicity, assume if y velocity is small
+        if movement.jump && velocity.y.abs() < 0.1 {
+            velocity.y = PLAYER_JUMP_VELOCITY;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_replicon::shared::backend::connected_client::NetworkIdMap;
+    use zombrise_shared::players::movement::PLAYER_SPEED;
+
+    fn spawn_player(app: &mut App, client_id: u64, health: f32) -> Entity {
+        let player = app
+            .world_mut()
+            .spawn((
+                Player,
+                PlayerInput::default(),
+                Health {
+                    current: health,
+                    max: 100.0,
+                },
+                LinearVelocity::default(),
+                Transform::default(),
+                InputAck::default(),
+            ))
+            .id();
+        app.world_mut()
+            .resource_mut::<PlayerIndex>()
+            .0
+            .insert(client_id, player);
+        player
+    }
+
+    fn spawn_client(app: &mut App, client_id: u64) -> ClientId {
+        let entity = app.world_mut().spawn(NetworkId::new(client_id)).id();
+        ClientId::Client(entity)
+    }
+
+    fn send_move(app: &mut App, client_id: ClientId, direction: Vec3) {
+        app.world_mut().write_message(FromClient {
+            client_id,
+            message: MovePlayer {
+                sequence: 1,
+                direction,
+                camera_yaw: 0.0,
+            },
+        });
+    }
+
+    fn velocity(app: &App, player: Entity) -> Vec3 {
+        app.world().get::<LinearVelocity>(player).unwrap().0
+    }
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_message::<FromClient<MovePlayer>>()
+            .init_resource::<PlayerIndex>()
+            .init_resource::<NetworkIdMap>()
+            .add_systems(Update, (handle_move_player, apply_player_inputs).chain());
+        app
+    }
+
+    #[test]
+    fn input_only_moves_the_senders_player() {
+        let mut app = test_app();
+        let first = spawn_player(&mut app, 1, 100.0);
+        let second = spawn_player(&mut app, 2, 100.0);
+        let first_client = spawn_client(&mut app, 1);
+        spawn_client(&mut app, 2);
+
+        send_move(&mut app, first_client, Vec3::X);
+        app.update();
+
+        assert!(velocity(&app, first).abs_diff_eq(Vec3::X * PLAYER_SPEED, 1e-5));
+        assert_eq!(velocity(&app, second), Vec3::ZERO);
+        assert_eq!(app.world().get::<InputAck>(first).unwrap().0, 1);
+        assert_eq!(app.world().get::<InputAck>(second).unwrap().0, 0);
+    }
+
+    #[test]
+    fn input_without_a_living_player_is_dropped() {
+        let mut app = test_app();
+        let alive = spawn_player(&mut app, 1, 100.0);
+        let dead = spawn_player(&mut app, 2, 0.0);
+        let dead_client = spawn_client(&mut app, 2);
+        let unknown_client = spawn_client(&mut app, 3);
+
+        send_move(&mut app, dead_client, Vec3::X);
+        send_move(&mut app, unknown_client, Vec3::X);
+        app.update();
+
+        assert_eq!(velocity(&app, alive), Vec3::ZERO);
+        assert_eq!(velocity(&app, dead), Vec3::ZERO);
+        assert!(app.world().get::<PlayerInput>(alive).unwrap().0.is_none());
+    }
+}
@@ -0,0 +1,124 @@
+use zombrise_shared::players::player::MAX_PLAYER_NAME_CHARS;
+
+/// Name of players that didn't send a usable one.
+const DEFAULT_NAME: &str = "Player";
+
+/// Whether `c` takes no visible space, like format characters (Unicode category Cf, e.g.
+/// zero width spaces and bidi overrides), variation selectors and fillers. Names made of
+/// them look empty or like another player's name.
+pub fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        // Format characters
+        '\u{00AD}'
+            | '\u{0600}'..='\u{0605}'
+            | '\u{061C}'
+            | '\u{06DD}'
+            | '\u{070F}'
+            | '\u{0890}'..='\u{0891}'
+            | '\u{08E2}'
+            | '\u{180E}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{206F}'
+            | '\u{FEFF}'
+            | '\u{FFF9}'..='\u{FFFB}'
+            | '\u{110BD}'
+            | '\u{110CD}'
+            | '\u{13430}'..='\u{1343F}'
+            | '\u{1BCA0}'..='\u{1BCA3}'
+            | '\u{1D173}'..='\u{1D17A}'
+            | '\u{E0001}'
+            | '\u{E0020}'..='\u{E007F}'
+            // Invisible marks and fillers of other categories
+            | '\u{034F}'
+            | '\u{115F}'..='\u{1160}'
+            | '\u{17B4}'..='\u{17B5}'
+            | '\u{180B}'..='\u{180D}'
+            | '\u{180F}'
+            | '\u{2800}'
+            | '\u{3164}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FFA0}'
+            | '\u{E0100}'..='\u{E01EF}'
+    )
+}
+
+/// Drops control and invisible characters, collapses whitespace and cuts the name to
+/// `MAX_PLAYER_NAME_CHARS`. Empty names become `DEFAULT_NAME`.
+pub fn sanitize_name(requested: &str) -> String {
+    let cleaned: String = requested
+        .chars()
+        .filter(|c| !c.is_control() && !is_invisible(*c))
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let name: String = cleaned.chars().take(MAX_PLAYER_NAME_CHARS).collect();
+    let name = name.trim_end();
+
+    if name.is_empty() {
+        DEFAULT_NAME.to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Appends a number to `name` until it differs from every name in `taken`,
+/// ignoring case so players can't impersonate each other with `BOB` and `bob`.
+pub fn unique_name(name: String, taken: &[&str]) -> String {
+    let is_taken = |candidate: &str| {
+        taken
+            .iter()
+            .any(|other| other.to_lowercase() == candidate.to_lowercase())
+    };
+    if !is_taken(&name) {
+        return name;
+    }
+
+    (2..)
+        .map(|number| {
+            let suffix = format!(" {}", number);
+            // Make room for the suffix so the name stays within the limit
+            let base: String = name
+                .chars()
+                .take(MAX_PLAYER_NAME_CHARS.saturating_sub(suffix.chars().count()))
+                .collect();
+            format!("{}{}", base.trim_end(), suffix)
+        })
+        .find(|candidate| !is_taken(candidate))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invisible_characters_are_dropped() {
+        assert_eq!(sanitize_name("Bo\u{200B}b"), "Bob");
+        assert_eq!(sanitize_name("\u{202E}boB"), "boB");
+        assert_eq!(sanitize_name("A\u{FEFF}l\u{2060}ice\u{E0041}"), "Alice");
+        assert_eq!(sanitize_name("\u{3164}\u{200D}\u{2800}"), DEFAULT_NAME);
+    }
+
+    #[test]
+    fn invisible_characters_cannot_copy_a_name() {
+        let name = sanitize_name("Bob\u{200C}");
+        assert_eq!(unique_name(name, &["bob"]), "Bob 2");
+    }
+
+    #[test]
+    fn whitespace_and_control_characters_are_cleaned() {
+        assert_eq!(sanitize_name("  Zombie\t\n  Slayer "), "Zombie Slayer");
+        assert_eq!(sanitize_name("\u{7}"), DEFAULT_NAME);
+        assert_eq!(sanitize_name("Ünïcödé 名前"), "Ünïcödé 名前");
+    }
+
+    #[test]
+    fn long_names_are_cut() {
+        let name = sanitize_name(&"x".repeat(MAX_PLAYER_NAME_CHARS + 5));
+        assert_eq!(name.chars().count(), MAX_PLAYER_NAME_CHARS);
+    }
+}
@@ -0,0 +1,408 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use zombrise_shared::players::player::{Dead, Player};
+use zombrise_shared::shared::MapMarker;
+
+/// The navigation grid reaches this far past the edge of the ground.
+const NAV_MARGIN: f32 = 2.0;
+/// Half the side length of the grid before the ground's size is known.
+const NAV_DEFAULT_EXTENT: f32 = 30.0;
+const NAV_CELL_SIZE: f32 = 0.5;
+/// Obstacles are inflated by this much so zombies don't scrape along them.
+const NAV_AGENT_RADIUS: f32 = 0.5;
+/// Seconds between flow field updates while obstacles stay the same.
+const NAV_REFRESH_INTERVAL: f32 = 0.25;
+
+/// Grid over the play area with a flow field leading towards the closest living player.
+///
+/// Blocked cells come from the AABBs of all static colliders except the ground. The flow
+/// field is a single Dijkstra pass from every player cell, so each zombie only has to look
+/// at its own cell regardless of how many zombies there are.
+#[derive(Resource)]
+pub struct NavGrid {
+    /// Half the side length of the square area covered by the grid, follows the ground's size.
+    extent: f32,
+    size: usize,
+    blocked: Vec<bool>,
+    /// Path cost from each cell to the nearest player, `f32::INFINITY` if unreachable.
+    distance: Vec<f32>,
+    obstacles: HashSet<Entity>,
+    obstacles_dirty: bool,
+    refresh_timer: Timer,
+}
+
+impl Default for NavGrid {
+    fn default() -> Self {
+        let mut grid = Self {
+            extent: 0.0,
+            size: 0,
+            blocked: Vec::new(),
+            distance: Vec::new(),
+            obstacles: HashSet::new(),
+            obstacles_dirty: true,
+            refresh_timer: Timer::from_seconds(NAV_REFRESH_INTERVAL, TimerMode::Repeating),
+        };
+        grid.resize(NAV_DEFAULT_EXTENT);
+        grid
+    }
+}
+
+impl NavGrid {
+    /// Resizes the grid to cover `extent` in every direction, rounded up to whole cells.
+    /// Obstacles have to be rebuilt afterwards.
+    fn resize(&mut self, extent: f32) {
+        let size = (extent * 2.0 / NAV_CELL_SIZE).ceil() as usize;
+        self.extent = size as f32 * NAV_CELL_SIZE / 2.0;
+        self.size = size;
+        self.blocked = vec![false; size * size];
+        self.distance = vec![f32::INFINITY; size * size];
+        self.obstacles_dirty = true;
+    }
+
+    fn cell_at(&self, position: Vec3) -> Option<(usize, usize)> {
+        let x = ((position.x + self.extent) / NAV_CELL_SIZE).floor();
+        let z = ((position.z + self.extent) / NAV_CELL_SIZE).floor();
+        if x < 0.0 || z < 0.0 || x >= self.size as f32 || z >= self.size as f32 {
+            return None;
+        }
+        Some((x as usize, z as usize))
+    }
+
+    fn cell_center(&self, x: usize, z: usize) -> Vec3 {
+        Vec3::new(
+            (x as f32 + 0.5) * NAV_CELL_SIZE - self.extent,
+            0.0,
+            (z as f32 + 0.5) * NAV_CELL_SIZE - self.extent,
+        )
+    }
+
+    fn index(&self, x: usize, z: usize) -> usize {
+        z * self.size + x
+    }
+
+    fn neighbours(&self, x: usize, z: usize) -> impl Iterator<Item = (usize, usize, f32)> + '_ {
+        neighbours(&self.blocked, self.size, x, z)
+    }
+
+    /// Range of cell indices along one axis whose centers lie within `min..=max`.
+    fn cell_range(&self, min: f32, max: f32) -> std::ops::Range<usize> {
+        let first = ((min + self.extent) / NAV_CELL_SIZE - 0.5).ceil().max(0.0);
+        let last = ((max + self.extent) / NAV_CELL_SIZE - 0.5).floor() + 1.0;
+        let last = last.clamp(0.0, self.size as f32);
+        first as usize..(last as usize).max(first as usize)
+    }
+
+    fn rebuild_obstacles(&mut self, obstacles: &[ColliderAabb]) {
+        self.blocked.fill(false);
+
+        for aabb in obstacles {
+            let min = aabb.min - Vec3::splat(NAV_AGENT_RADIUS);
+            let max = aabb.max + Vec3::splat(NAV_AGENT_RADIUS);
+
+            let columns = self.cell_range(min.x, max.x);
+            for z in self.cell_range(min.z, max.z) {
+                for x in columns.clone() {
+                    let index = self.index(x, z);
+                    self.blocked[index] = true;
+                }
+            }
+        }
+    }
+
+    fn rebuild_flow_field(&mut self, goals: impl Iterator<Item = Vec3>) {
+        self.distance.fill(f32::INFINITY);
+        let mut open = BinaryHeap::new();
+
+        for goal in goals {
+            if let Some((x, z)) = self.cell_at(goal) {
+                let index = self.index(x, z);
+                self.distance[index] = 0.0;
+                open.push(OpenCell { cost: 0.0, x, z });
+            }
+        }
+
+        while let Some(OpenCell { cost, x, z }) = open.pop() {
+            if cost > self.distance[self.index(x, z)] {
+                continue;
+            }
+
+            // Borrows only `blocked`, so `distance` can be updated while walking the neighbours
+            for (nx, nz, step) in neighbours(&self.blocked, self.size, x, z) {
+                let next_cost = cost + step;
+                let index = self.index(nx, nz);
+                if next_cost < self.distance[index] {
+                    self.distance[index] = next_cost;
+                    open.push(OpenCell {
+                        cost: next_cost,
+                        x: nx,
+                        z: nz,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Horizontal direction to walk from `position` to get closer to a player, or `None`
+    /// when the position is off the grid or no player can be reached from it.
+    pub fn direction_to_player(&self, position: Vec3) -> Option<Vec3> {
+        let (x, z) = self.cell_at(position)?;
+        // Zombies pushed into a blocked cell have an infinite cost and step to any reachable neighbour
+        let current = self.distance[self.index(x, z)];
+
+        let (best_x, best_z, _) = self
+            .neighbours(x, z)
+            .map(|(nx, nz, _)| (nx, nz, self.distance[self.index(nx, nz)]))
+            .filter(|(_, _, distance)| *distance < current)
+            .min_by(|a, b| a.2.total_cmp(&b.2))?;
+
+        let target = self.cell_center(best_x, best_z);
+        Some(Vec3::new(target.x - position.x, 0.0, target.z - position.z).normalize_or_zero())
+    }
+}
+
+/// Walkable neighbours of a cell with the cost to step onto them.
+/// Diagonal steps are only allowed when they don't cut a blocked corner.
+fn neighbours(
+    blocked: &[bool],
+    size: usize,
+    x: usize,
+    z: usize,
+) -> impl Iterator<Item = (usize, usize, f32)> + '_ {
+    const OFFSETS: [(i32, i32); 8] = [
+        (1, 0),
+        (-1, 0),
+        (0, 1),
+        (0, -1),
+        (1, 1),
+        (1, -1),
+        (-1, 1),
+        (-1, -1),
+    ];
+
+    OFFSETS.iter().filter_map(move |&(dx, dz)| {
+        let nx = x as i32 + dx;
+        let nz = z as i32 + dz;
+        if nx < 0 || nz < 0 || nx >= size as i32 || nz >= size as i32 {
+            return None;
+        }
+        let (nx, nz) = (nx as usize, nz as usize);
+        if blocked[nz * size + nx] {
+            return None;
+        }
+        if dx != 0 && dz != 0 {
+            if blocked[z * size + nx] || blocked[nz * size + x] {
+                return None;
+            }
+            return Some((nx, nz, std::f32::consts::SQRT_2));
+        }
+        Some((nx, nz, 1.0))
+    })
+}
+
+/// Min-heap entry for the Dijkstra pass.
+struct OpenCell {
+    cost: f32,
+    x: usize,
+    z: usize,
+}
+
+impl PartialEq for OpenCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for OpenCell {}
+
+impl PartialOrd for OpenCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so the cheapest cell is popped first
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+/// Half the side length of the square around the origin that covers the ground, which
+/// grows with the player count.
+pub fn map_half_size(map: &ColliderAabb) -> f32 {
+    map.min
+        .x
+        .abs()
+        .max(map.max.x.abs())
+        .max(map.min.z.abs())
+        .max(map.max.z.abs())
+}
+
+/// Flags the grid for a rebuild when static colliders are added, moved or removed.
+pub fn track_nav_obstacles(
+    mut nav_grid: ResMut<NavGrid>,
+    changed_query: Query<(Entity, &RigidBody), (Changed<ColliderAabb>, Without<MapMarker>)>,
+    mut removed: RemovedComponents<Collider>,
+) {
+    for (entity, rigid_body) in &changed_query {
+        if rigid_body.is_static() {
+            nav_grid.obstacles_dirty = true;
+            nav_grid.obstacles.insert(entity);
+        }
+    }
+
+    for entity in removed.read() {
+        if nav_grid.obstacles.remove(&entity) {
+            nav_grid.obstacles_dirty = true;
+        }
+    }
+}
+
+pub fn update_nav_grid(
+    mut nav_grid: ResMut<NavGrid>,
+    obstacle_query: Query<(&RigidBody, &ColliderAabb), Without<MapMarker>>,
+    map_query: Query<&ColliderAabb, With<MapMarker>>,
+    player_query: Query<&Transform, (With<Player>, Without<Dead>)>,
+    time: Res<Time>,
+) {
+    // The ground grows with the player count, so the grid has to follow it
+    if let Ok(map) = map_query.single() {
+        let extent = map_half_size(map) + NAV_MARGIN;
+        if extent > nav_grid.extent || extent < nav_grid.extent - NAV_CELL_SIZE {
+            nav_grid.resize(extent);
+            info!(
+                "Navigation grid resized to {:.1} units",
+                nav_grid.extent * 2.0
+            );
+        }
+    }
+
+    let obstacles_dirty = nav_grid.obstacles_dirty;
+    if obstacles_dirty {
+        let obstacles: Vec<ColliderAabb> = obstacle_query
+            .iter()
+            .filter(|(rigid_body, _)| rigid_body.is_static())
+            .map(|(_, aabb)| *aabb)
+            .collect();
+        nav_grid.rebuild_obstacles(&obstacles);
+        nav_grid.obstacles_dirty = false;
+        debug!("Navigation grid rebuilt with {} obstacles", obstacles.len());
+    }
+
+    if nav_grid.refresh_timer.tick(time.delta()).just_finished() || obstacles_dirty {
+        nav_grid.rebuild_flow_field(player_query.iter().map(|t| t.translation));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(grid: &mut NavGrid, x: usize, z: usize) {
+        let index = grid.index(x, z);
+        grid.blocked[index] = true;
+    }
+
+    fn is_blocked(grid: &NavGrid, position: Vec3) -> bool {
+        let (x, z) = grid.cell_at(position).unwrap();
+        grid.blocked[grid.index(x, z)]
+    }
+
+    #[test]
+    fn cell_range_covers_centers_inside_the_bounds() {
+        let grid = NavGrid::default();
+        let last = grid.size;
+
+        assert_eq!(grid.cell_range(-30.0, -29.0), 0..2);
+        assert_eq!(grid.cell_range(29.0, 40.0), last - 2..last);
+        assert_eq!(grid.cell_range(-40.0, 40.0), 0..last);
+        // Bounds exactly on a cell center include it
+        assert_eq!(grid.cell_range(0.25, 0.25), 60..61);
+        // Between two centers
+        assert!(grid.cell_range(0.0, 0.2).is_empty());
+        // Off the grid
+        assert!(grid.cell_range(-50.0, -40.0).is_empty());
+        assert!(grid.cell_range(40.0, 50.0).is_empty());
+    }
+
+    #[test]
+    fn diagonal_steps_do_not_cut_blocked_corners() {
+        let mut grid = NavGrid::default();
+        block(&mut grid, 11, 10);
+
+        let steps: Vec<_> = grid.neighbours(10, 10).collect();
+        let reaches = |x, z| steps.iter().any(|&(nx, nz, _)| (nx, nz) == (x, z));
+        assert!(!reaches(11, 10));
+        assert!(!reaches(11, 11));
+        assert!(!reaches(11, 9));
+        assert!(reaches(9, 11));
+        assert_eq!(steps.len(), 5);
+        assert!(steps
+            .iter()
+            .any(|&(nx, nz, cost)| (nx, nz) == (9, 9) && cost == std::f32::consts::SQRT_2));
+    }
+
+    #[test]
+    fn unreachable_cells_have_no_direction() {
+        let mut grid = NavGrid::default();
+        let inside = grid.cell_center(50, 50);
+
+        grid.rebuild_flow_field(std::iter::empty());
+        assert_eq!(grid.direction_to_player(inside), None);
+
+        for (x, z) in [(49, 49), (50, 49), (51, 49), (49, 50), (51, 50)]
+            .into_iter()
+            .chain([(49, 51), (50, 51), (51, 51)])
+        {
+            block(&mut grid, x, z);
+        }
+        grid.rebuild_flow_field(std::iter::once(Vec3::new(10.0, 0.0, 10.0)));
+        assert_eq!(grid.direction_to_player(inside), None);
+        assert!(grid.direction_to_player(grid.cell_center(60, 60)).is_some());
+        // Off the grid
+        assert_eq!(grid.direction_to_player(Vec3::new(100.0, 0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn leads_around_a_wall() {
+        let mut grid = NavGrid::default();
+        let wall =
+            ColliderAabb::from_min_max(Vec3::new(-5.0, 0.0, -0.25), Vec3::new(5.0, 2.0, 0.25));
+        grid.rebuild_obstacles(&[wall]);
+        let player = Vec3::new(0.0, 0.0, 5.0);
+        grid.rebuild_flow_field(std::iter::once(player));
+
+        let goal = grid.cell_at(player);
+        let mut position = Vec3::new(0.0, 0.0, -5.0);
+        for _ in 0..1000 {
+            if grid.cell_at(position) == goal {
+                break;
+            }
+            let direction = grid.direction_to_player(position).unwrap();
+            position += direction * 0.1;
+            assert!(
+                !is_blocked(&grid, position),
+                "walked into the wall at {}",
+                position
+            );
+        }
+        assert_eq!(grid.cell_at(position), goal);
+    }
+
+    #[test]
+    fn resize_follows_the_extent() {
+        let mut grid = NavGrid {
+            obstacles_dirty: false,
+            ..Default::default()
+        };
+        grid.resize(40.2);
+
+        assert_eq!(grid.extent, 40.25);
+        assert_eq!(grid.size, 161);
+        assert_eq!(grid.blocked.len(), 161 * 161);
+        assert!(grid.obstacles_dirty);
+        assert_eq!(grid.cell_at(Vec3::new(-40.2, 0.0, 40.2)), Some((0, 160)));
+    }
+}
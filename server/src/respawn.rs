@@ -0,0 +1,131 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon::shared::backend::connected_client::NetworkId;
+use rand::Rng;
+use zombrise_shared::chat::chat::ChatMessage;
+use zombrise_shared::players::player::{Dead, Health, Player, PlayerName, RequestRespawn};
+use zombrise_shared::shared::MapMarker;
+use zombrise_shared::zombie::zombie::Zombie;
+
+use crate::chat::system_message;
+use crate::config::ServerConfig;
+use crate::navigation::map_half_size;
+use crate::{sender_network_id, PlayerIndex};
+
+/// Respawn points keep this far from the edge of the ground.
+const SPAWN_EDGE_MARGIN: f32 = 8.0;
+/// Half the side length of the respawn area while the ground's size is unknown.
+const DEFAULT_SPAWN_EXTENT: f32 = 20.0;
+
+/// Puts players whose health dropped to zero into the `Dead` state.
+pub fn mark_dead_players(
+    mut commands: Commands,
+    mut player_query: Query<
+        (Entity, &Health, &PlayerName, &mut LinearVelocity),
+        (With<Player>, Without<Dead>),
+    >,
+    mut chat: MessageWriter<ToClients<ChatMessage>>,
+    config: Res<ServerConfig>,
+) {
+    for (entity, health, name, mut velocity) in &mut player_query {
+        if health.current <= 0.0 {
+            println!("{} died", name.0);
+            chat.write(system_message(format!("{} died", name.0)));
+            velocity.0 = Vec3::ZERO;
+            commands.entity(entity).insert(Dead {
+                respawn_in: config.gameplay.respawn_delay,
+            });
+        }
+    }
+}
+
+pub fn tick_respawn_timers(mut query: Query<&mut Dead>, time: Res<Time>) {
+    for mut dead in &mut query {
+        if dead.respawn_in > 0.0 {
+            dead.respawn_in = (dead.respawn_in - time.delta_secs()).max(0.0);
+        }
+    }
+}
+
+pub fn handle_respawn_requests(
+    mut commands: Commands,
+    mut events: MessageReader<FromClient<RequestRespawn>>,
+    network_ids: Query<&NetworkId>,
+    player_index: Res<PlayerIndex>,
+    mut player_query: Query<
+        (
+            &Dead,
+            &PlayerName,
+            &mut Health,
+            &mut Transform,
+            &mut LinearVelocity,
+            &mut AngularVelocity,
+        ),
+        With<Player>,
+    >,
+    zombie_query: Query<&Transform, (With<Zombie>, Without<Player>)>,
+    map_query: Query<&ColliderAabb, With<MapMarker>>,
+) {
+    // The ground grows with the player count, so respawn points spread out with it
+    let spawn_extent = map_query.single().map_or(DEFAULT_SPAWN_EXTENT, |map| {
+        (map_half_size(map) - SPAWN_EDGE_MARGIN).max(1.0)
+    });
+
+    for FromClient { client_id, .. } in events.read() {
+        let Some(owner) = sender_network_id(*client_id, &network_ids) else {
+            continue;
+        };
+        let Some(&entity) = player_index.0.get(&owner) else {
+            continue;
+        };
+        let Ok((dead, name, mut health, mut transform, mut linear, mut angular)) =
+            player_query.get_mut(entity)
+        else {
+            continue;
+        };
+        if dead.respawn_in > 0.0 {
+            continue;
+        }
+
+        let zombie_positions: Vec<Vec3> = zombie_query.iter().map(|t| t.translation).collect();
+        let spawn_point = find_safe_spawn_point(&zombie_positions, spawn_extent);
+
+        health.current = health.max;
+        *transform = Transform::from_translation(spawn_point);
+        linear.0 = Vec3::ZERO;
+        angular.0 = Vec3::ZERO;
+        commands.entity(entity).remove::<Dead>();
+
+        println!("{} respawned at {}", name.0, spawn_point);
+    }
+}
+
+/// Samples a few random points within `extent` of the center and picks the one furthest
+/// from any zombie.
+fn find_safe_spawn_point(zombie_positions: &[Vec3], extent: f32) -> Vec3 {
+    const CANDIDATES: usize = 16;
+
+    let mut rng = rand::rng();
+    let mut best_point = Vec3::new(0.0, 0.5, 0.0);
+    let mut best_distance = f32::MIN;
+
+    for _ in 0..CANDIDATES {
+        let candidate = Vec3::new(
+            rng.random_range(-extent..extent),
+            0.5,
+            rng.random_range(-extent..extent),
+        );
+        let distance = zombie_positions
+            .iter()
+            .map(|zombie| zombie.distance(candidate))
+            .fold(f32::MAX, f32::min);
+
+        if distance > best_distance {
+            best_distance = distance;
+            best_point = candidate;
+        }
+    }
+
+    best_point
+}
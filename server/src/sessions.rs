@@ -0,0 +1,85 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon_renet2::netcode::NetcodeServerTransport;
+use std::collections::HashMap;
+use zombrise_shared::players::player::{InputAck, Player, PlayerName, PlayerOwner};
+use zombrise_shared::protocol::protocol::ConnectUserData;
+
+use crate::movement::PlayerInput;
+
+/// Persistent identity of the install that controls a player, and the secret it proved
+/// the identity with when the session was created.
+#[derive(Component, Clone, Copy)]
+pub struct PlayerIdentity {
+    pub id: u64,
+    pub secret: u128,
+}
+
+/// Player whose client went away. It stays out of the game until the same identity
+/// reconnects or the grace period runs out.
+#[derive(Component)]
+pub struct Disconnected {
+    pub remaining: f32,
+}
+
+/// Player entities by identity, both connected ones and those in their grace period.
+#[derive(Resource, Default)]
+pub struct Sessions(pub HashMap<u64, Entity>);
+
+/// What the client sent in its netcode user data when connecting.
+pub fn client_user_data(transport: &NetcodeServerTransport, client_id: u64) -> ConnectUserData {
+    transport
+        .user_data(client_id)
+        .map(|user_data| ConnectUserData::from_bytes(&user_data))
+        .unwrap_or_default()
+}
+
+/// Takes a player out of the game while keeping its position, health and respawn timer.
+pub fn suspend_session(commands: &mut Commands, entity: Entity, grace_period: f32) {
+    commands
+        .entity(entity)
+        .insert((
+            Disconnected {
+                remaining: grace_period,
+            },
+            LinearVelocity::ZERO,
+            RigidBodyDisabled,
+            ColliderDisabled,
+        ))
+        // Not a player anymore for zombies and waves, and despawned on the clients
+        .remove::<(Player, Replicated)>();
+}
+
+/// Hands a suspended player back to the reconnected client, under the name it asked for now.
+/// Input sequence numbers start over with the new connection.
+pub fn resume_session(commands: &mut Commands, entity: Entity, client_id: u64, name: PlayerName) {
+    commands
+        .entity(entity)
+        .remove::<(Disconnected, RigidBodyDisabled, ColliderDisabled)>()
+        .insert((
+            Player,
+            Replicated,
+            PlayerOwner(client_id),
+            name,
+            PlayerInput::default(),
+            InputAck::default(),
+        ));
+}
+
+/// Despawns players whose client didn't come back within the grace period.
+pub fn expire_sessions(
+    mut commands: Commands,
+    mut sessions: ResMut<Sessions>,
+    mut query: Query<(Entity, &PlayerIdentity, &mut Disconnected)>,
+    time: Res<Time>,
+) {
+    for (entity, identity, mut disconnected) in &mut query {
+        disconnected.remaining -= time.delta_secs();
+        if disconnected.remaining <= 0.0 {
+            println!("Session of player {} expired", identity.id);
+            sessions.0.remove(&identity.id);
+            commands.entity(entity).despawn();
+        }
+    }
+}
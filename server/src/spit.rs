@@ -0,0 +1,106 @@
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use zombrise_shared::players::player::{
+    DamageFlash, Dead, Health, Player, PlayerName, PlayerOwner,
+};
+use zombrise_shared::replication::transform::NetTransform;
+use zombrise_shared::spatial::spatial_hash::SpatialIndex;
+use zombrise_shared::zombie::zombie::{Spit, ZombieAttackHit, ZombieKind, SPIT_RADIUS, SPIT_SPEED};
+
+/// Spits fly this much further than the attack range, so players at the edge still get hit.
+const SPIT_EXTRA_RANGE: f32 = 1.0;
+
+/// Server side of a flying spit.
+#[derive(Component)]
+pub struct SpitFlight {
+    velocity: Vec3,
+    /// Seconds until the spit has flown past its range and is removed.
+    remaining: f32,
+    damage: f32,
+    /// Kind of the zombie that spat, for the kill message.
+    kind: ZombieKind,
+}
+
+/// Spits from `from` towards where `target` is now, players can still step out of the way.
+pub fn launch_spit(
+    commands: &mut Commands,
+    from: Vec3,
+    target: Vec3,
+    range: f32,
+    damage: f32,
+    kind: ZombieKind,
+) {
+    let direction = (target - from).normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return;
+    }
+
+    let transform = Transform::from_translation(from).looking_to(direction, Vec3::Y);
+    commands.spawn((
+        Spit,
+        Replicated,
+        transform,
+        NetTransform::from_transform(&transform),
+        SpitFlight {
+            velocity: direction * SPIT_SPEED,
+            remaining: (range + SPIT_EXTRA_RANGE) / SPIT_SPEED,
+            damage,
+            kind,
+        },
+    ));
+}
+
+/// Moves spits and lets them hit the first player they touch.
+pub fn fly_spit(
+    mut commands: Commands,
+    mut spit_query: Query<(Entity, &mut Transform, &mut SpitFlight)>,
+    mut player_query: Query<
+        (
+            &Transform,
+            &PlayerOwner,
+            &PlayerName,
+            &mut Health,
+            &mut DamageFlash,
+        ),
+        (With<Player>, Without<Dead>, Without<SpitFlight>),
+    >,
+    spatial_index: Res<SpatialIndex>,
+    mut hits: MessageWriter<ToClients<ZombieAttackHit>>,
+    time: Res<Time>,
+) {
+    for (entity, mut transform, mut flight) in &mut spit_query {
+        transform.translation += flight.velocity * time.delta_secs();
+        flight.remaining -= time.delta_secs();
+
+        let Some((target, _, _)) = spatial_index
+            .players
+            .nearest(transform.translation, SPIT_RADIUS)
+        else {
+            if flight.remaining <= 0.0 {
+                commands.entity(entity).despawn();
+            }
+            continue;
+        };
+        commands.entity(entity).despawn();
+
+        let Ok((player_transform, owner, name, mut health, mut damage_flash)) =
+            player_query.get_mut(target)
+        else {
+            continue;
+        };
+        health.current = (health.current - flight.damage).max(0.0);
+        damage_flash.timer = 0.3;
+        hits.write(ToClients {
+            mode: SendMode::Broadcast,
+            message: ZombieAttackHit {
+                target: owner.0,
+                position: player_transform.translation,
+                damage: flight.damage,
+            },
+        });
+
+        if health.current <= 0.0 {
+            println!("{} was killed by a {:?} zombie", name.0, flight.kind);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+use zombrise_shared::players::player::{Health, Player};
+use zombrise_shared::replication::transform::NetTransform;
+use zombrise_shared::wave::wave::WaveStatus;
+use zombrise_shared::zombie::zombie::{Dying, Zombie, ZombieAiState, ZombieKind, ZombieSpeed};
+
+use crate::zombie_kinds::{ZombieKindStats, ZombieKinds};
+use crate::{ZombieAttack, ZombieBehavior};
+
+/// Tuning values for the horde mode.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct WaveSettings {
+    /// Zombies in the first wave for a single player.
+    pub base_zombies: u32,
+    /// Extra zombies added with every wave.
+    pub zombies_per_wave: u32,
+    /// Extra share of zombies for every connected player beyond the first.
+    pub zombies_per_extra_player: f32,
+    /// Seconds between two spawns in the first wave.
+    pub base_spawn_interval: f32,
+    /// Factor applied to the spawn interval with every wave.
+    pub spawn_interval_decay: f32,
+    pub min_spawn_interval: f32,
+    /// Growth of zombie stats with every wave, e.g. 0.1 = +10% per wave.
+    pub stat_growth_per_wave: f32,
+    /// Growth of zombie stats for every connected player beyond the first.
+    pub stat_growth_per_extra_player: f32,
+    /// Seconds of calm between two waves.
+    pub intermission: f32,
+    /// Maximum number of zombies alive at the same time.
+    pub max_alive: usize,
+}
+
+impl Default for WaveSettings {
+    fn default() -> Self {
+        Self {
+            base_zombies: 5,
+            zombies_per_wave: 3,
+            zombies_per_extra_player: 0.5,
+            base_spawn_interval: 3.0,
+            spawn_interval_decay: 0.9,
+            min_spawn_interval: 0.5,
+            stat_growth_per_wave: 0.1,
+            stat_growth_per_extra_player: 0.15,
+            intermission: 15.0,
+            max_alive: 30,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum WavePhase {
+    Intermission,
+    InProgress,
+}
+
+/// Drives the rounds of the horde mode: spawns zombies during a wave and
+/// counts down the intermission between waves.
+#[derive(Resource)]
+pub struct WaveDirector {
+    pub settings: WaveSettings,
+    phase: WavePhase,
+    wave: u32,
+    /// Zombies of the current wave that still have to be spawned.
+    to_spawn: u32,
+    spawn_timer: Timer,
+    intermission_timer: Timer,
+}
+
+impl WaveDirector {
+    pub fn new(settings: WaveSettings) -> Self {
+        Self {
+            phase: WavePhase::Intermission,
+            wave: 0,
+            to_spawn: 0,
+            spawn_timer: Timer::from_seconds(settings.base_spawn_interval, TimerMode::Repeating),
+            intermission_timer: Timer::from_seconds(settings.intermission, TimerMode::Once),
+            settings,
+        }
+    }
+
+    fn zombie_count(&self, wave: u32, player_count: usize) -> u32 {
+        let base = self.settings.base_zombies + self.settings.zombies_per_wave * (wave - 1);
+        let extra_players = player_count.saturating_sub(1) as f32;
+        (base as f32 * (1.0 + self.settings.zombies_per_extra_player * extra_players)).round()
+            as u32
+    }
+
+    fn spawn_interval(&self, wave: u32) -> f32 {
+        (self.settings.base_spawn_interval
+            * self.settings.spawn_interval_decay.powi(wave as i32 - 1))
+        .max(self.settings.min_spawn_interval)
+    }
+
+    fn stat_multiplier(&self, wave: u32, player_count: usize) -> f32 {
+        let extra_players = player_count.saturating_sub(1) as f32;
+        (1.0 + self.settings.stat_growth_per_wave * (wave - 1) as f32)
+            * (1.0 + self.settings.stat_growth_per_extra_player * extra_players)
+    }
+
+    fn start_wave(&mut self, player_count: usize) {
+        self.wave += 1;
+        self.phase = WavePhase::InProgress;
+        self.to_spawn = self.zombie_count(self.wave, player_count);
+        self.spawn_timer =
+            Timer::from_seconds(self.spawn_interval(self.wave), TimerMode::Repeating);
+        println!("Wave {} started with {} zombies", self.wave, self.to_spawn);
+    }
+
+    fn start_intermission(&mut self) {
+        self.phase = WavePhase::Intermission;
+        self.intermission_timer = Timer::from_seconds(self.settings.intermission, TimerMode::Once);
+        println!("Wave {} cleared", self.wave);
+    }
+}
+
+/// Stats of a single zombie, its kind's base stats scaled by the wave it was spawned in.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ZombieStats {
+    pub speed: f32,
+    /// Damage dealt by a single attack.
+    pub damage: f32,
+    pub max_health: f32,
+    pub attack_range: f32,
+    pub attack_cooldown: f32,
+}
+
+impl ZombieStats {
+    pub fn scaled(base: &ZombieKindStats, multiplier: f32) -> Self {
+        Self {
+            speed: base.speed * multiplier,
+            damage: base.damage * multiplier,
+            max_health: base.health * multiplier,
+            attack_range: base.attack_range,
+            attack_cooldown: base.attack_cooldown,
+        }
+    }
+}
+
+pub fn spawn_wave_status(mut commands: Commands) {
+    commands.spawn((WaveStatus::default(), Replicated));
+}
+
+pub fn run_wave_director(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut director: ResMut<WaveDirector>,
+    zombie_kinds: Res<ZombieKinds>,
+    player_query: Query<(), With<Player>>,
+    zombie_query: Query<(), (With<Zombie>, Without<Dying>)>,
+) {
+    let player_count = player_query.iter().count();
+    if player_count == 0 {
+        // Nobody to fight, hold the director until someone joins
+        return;
+    }
+
+    match director.phase {
+        WavePhase::Intermission => {
+            if director.intermission_timer.tick(time.delta()).is_finished() {
+                director.start_wave(player_count);
+            }
+        }
+        WavePhase::InProgress => {
+            let alive = zombie_query.iter().count();
+            if director.to_spawn == 0 && alive == 0 {
+                director.start_intermission();
+                return;
+            }
+
+            if director.spawn_timer.tick(time.delta()).just_finished()
+                && director.to_spawn > 0
+                && alive < director.settings.max_alive
+            {
+                let mut rng = rand::rng();
+                let position = Vec3::new(
+                    rng.random_range(-20.0..20.0),
+                    0.5,
+                    rng.random_range(-20.0..20.0),
+                );
+                let kind = zombie_kinds.pick(director.wave);
+                let multiplier = director.stat_multiplier(director.wave, player_count);
+                let stats = ZombieStats::scaled(zombie_kinds.get(kind), multiplier);
+                spawn_zombie(&mut commands, position, kind, stats);
+                director.to_spawn -= 1;
+            }
+        }
+    }
+}
+
+/// Copies the director state into the replicated `WaveStatus`.
+pub fn sync_wave_status(
+    director: Res<WaveDirector>,
+    zombie_query: Query<(), (With<Zombie>, Without<Dying>)>,
+    mut status_query: Query<&mut WaveStatus>,
+) {
+    let Ok(mut status) = status_query.single_mut() else {
+        return;
+    };
+
+    let new_status = match director.phase {
+        WavePhase::Intermission => WaveStatus {
+            wave: director.wave,
+            zombies_remaining: 0,
+            intermission: director.intermission_timer.remaining_secs().ceil(),
+        },
+        WavePhase::InProgress => WaveStatus {
+            wave: director.wave,
+            zombies_remaining: director.to_spawn + zombie_query.iter().count() as u32,
+            intermission: 0.0,
+        },
+    };
+
+    // Only touch the component on change to avoid replicating it every tick
+    if *status != new_status {
+        *status = new_status;
+    }
+}
+
+fn spawn_zombie(commands: &mut Commands, position: Vec3, kind: ZombieKind, stats: ZombieStats) {
+    let mut rng = rand::rng();
+    let transform = Transform::from_translation(position);
+
+    commands.spawn((
+        Zombie,
+        kind,
+        Replicated,
+        Health {
+            current: stats.max_health,
+            max: stats.max_health,
+        },
+        transform,
+        NetTransform::from_transform(&transform),
+        GlobalTransform::default(),
+        (
+            RigidBody::Dynamic,
+            Collider::capsule(0.5, 1.0),
+            LinearVelocity::ZERO,
+            AngularVelocity::ZERO,
+            LockedAxes::new().lock_rotation_x().lock_rotation_z(),
+            LinearDamping(0.5),
+            AngularDamping(20.0),
+        ),
+        ZombieAiState::Idle,
+        ZombieSpeed(stats.speed),
+        ZombieAttack::default(),
+        ZombieBehavior {
+            timer: Timer::from_seconds(rng.random_range(1.0..3.0), TimerMode::Once),
+            wander_direction: Vec3::ZERO,
+        },
+        stats,
+    ));
+    println!(
+        "{:?} zombie spawned at {}, {}",
+        kind, position.x, position.z
+    );
+}
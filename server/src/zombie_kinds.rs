@@ -0,0 +1,202 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use zombrise_shared::zombie::zombie::ZombieKind;
+
+/// Built-in stats, used when no stats file is configured and the default one doesn't exist.
+const DEFAULT_ZOMBIE_KINDS: &str = include_str!("../data/zombie_kinds.ron");
+/// Stats file looked up in the working directory when none is configured.
+pub const DEFAULT_ZOMBIE_KINDS_FILE: &str = "zombie_kinds.ron";
+
+/// How a zombie kind deals its damage.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum AttackStyle {
+    /// Hits the nearest player in reach.
+    Melee,
+    /// Stops at its attack range and spits at the nearest player in reach. The spit flies
+    /// slowly enough to dodge and hits the first player it touches.
+    Ranged,
+    /// Blows itself up next to a player, damaging everything within `radius` when it dies.
+    Explode { radius: f32, damage: f32 },
+}
+
+/// Base stats of a zombie kind before wave multipliers are applied.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ZombieKindStats {
+    pub speed: f32,
+    pub health: f32,
+    pub damage: f32,
+    pub attack_range: f32,
+    pub attack_cooldown: f32,
+    pub spawn_weight: u32,
+    pub min_wave: u32,
+    pub attack: AttackStyle,
+}
+
+#[derive(Resource, Deserialize, Clone, Debug)]
+pub struct ZombieKinds {
+    kinds: HashMap<ZombieKind, ZombieKindStats>,
+}
+
+impl ZombieKinds {
+    /// Loads the stats from `path`. Only a missing default file falls back to the built-in
+    /// stats, a configured file that can't be read is an error.
+    pub fn load(path: &Path) -> Result<Self, Vec<String>> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                println!("Loading zombie kinds from {}", path.display());
+                Self::parse(&contents).map_err(|errors| {
+                    errors
+                        .into_iter()
+                        .map(|e| format!("Invalid {}: {}", path.display(), e))
+                        .collect()
+                })
+            }
+            Err(e)
+                if e.kind() == std::io::ErrorKind::NotFound
+                    && path == Path::new(DEFAULT_ZOMBIE_KINDS_FILE) =>
+            {
+                Ok(Self::parse(DEFAULT_ZOMBIE_KINDS).expect("Built-in zombie kinds are valid"))
+            }
+            Err(e) => Err(vec![format!("Cannot read {}: {}", path.display(), e)]),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, Vec<String>> {
+        let kinds: Self = ron::from_str(contents).map_err(|e| vec![e.to_string()])?;
+        kinds.validate()?;
+        Ok(kinds)
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if !self.kinds.contains_key(&ZombieKind::Walker) {
+            errors.push("the Walker kind is required".to_string());
+        }
+
+        // Sorted so the errors come out in the same order every time
+        let mut kinds: Vec<_> = self.kinds.iter().collect();
+        kinds.sort_by_key(|(kind, _)| format!("{:?}", kind));
+        for (kind, stats) in kinds {
+            let mut check = |ok: bool, message: &str| {
+                if !ok {
+                    errors.push(format!("{:?}: {}", kind, message));
+                }
+            };
+            check(stats.speed > 0.0, "speed must be positive");
+            check(stats.health > 0.0, "health must be positive");
+            check(stats.attack_range > 0.0, "attack_range must be positive");
+            check(
+                stats.attack_cooldown > 0.0,
+                "attack_cooldown must be positive",
+            );
+            check(stats.spawn_weight > 0, "spawn_weight must be at least 1");
+            match stats.attack {
+                // Exploders deal their damage with the blast
+                AttackStyle::Explode { radius, damage } => {
+                    check(radius > 0.0, "explosion radius must be positive");
+                    check(damage > 0.0, "explosion damage must be positive");
+                }
+                AttackStyle::Melee | AttackStyle::Ranged => {
+                    check(stats.damage > 0.0, "damage must be positive");
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn get(&self, kind: ZombieKind) -> &ZombieKindStats {
+        self.kinds
+            .get(&kind)
+            .unwrap_or_else(|| &self.kinds[&ZombieKind::Walker])
+    }
+
+    /// Picks a random kind allowed in `wave`, weighted by `spawn_weight`.
+    pub fn pick(&self, wave: u32) -> ZombieKind {
+        let candidates: Vec<(ZombieKind, u32)> = self
+            .kinds
+            .iter()
+            .filter(|(_, stats)| stats.min_wave <= wave && stats.spawn_weight > 0)
+            .map(|(kind, stats)| (*kind, stats.spawn_weight))
+            .collect();
+
+        let total: u32 = candidates.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return ZombieKind::Walker;
+        }
+
+        let mut roll = rand::rng().random_range(0..total);
+        for (kind, weight) in candidates {
+            if roll < weight {
+                return kind;
+            }
+            roll -= weight;
+        }
+        ZombieKind::Walker
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_kinds_are_valid() {
+        assert!(ZombieKinds::parse(DEFAULT_ZOMBIE_KINDS).is_ok());
+    }
+
+    #[test]
+    fn missing_default_file_uses_built_in_kinds() {
+        // Tests run in the crate directory, which has no zombie_kinds.ron
+        assert!(ZombieKinds::load(Path::new(DEFAULT_ZOMBIE_KINDS_FILE)).is_ok());
+    }
+
+    #[test]
+    fn missing_configured_file_is_an_error() {
+        let errors = ZombieKinds::load(Path::new("does_not_exist/zombie_kinds.ron")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("Cannot read does_not_exist/zombie_kinds.ron"));
+    }
+
+    #[test]
+    fn invalid_stats_are_reported() {
+        let errors = ZombieKinds::parse(
+            "(kinds: {
+                Walker: (speed: 0.0, health: 30.0, damage: -1.0, attack_range: 1.5,
+                    attack_cooldown: 1.0, spawn_weight: 0, min_wave: 1, attack: Melee),
+                Exploder: (speed: 0.8, health: 20.0, damage: 0.0, attack_range: 1.5,
+                    attack_cooldown: 1.0, spawn_weight: 3, min_wave: 4,
+                    attack: Explode(radius: 0.0, damage: 40.0)),
+            })",
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "Exploder: explosion radius must be positive",
+                "Walker: speed must be positive",
+                "Walker: spawn_weight must be at least 1",
+                "Walker: damage must be positive",
+            ]
+        );
+    }
+
+    #[test]
+    fn walker_is_required() {
+        let errors = ZombieKinds::parse(
+            "(kinds: {
+                Runner: (speed: 1.6, health: 15.0, damage: 8.0, attack_range: 1.3,
+                    attack_cooldown: 0.6, spawn_weight: 5, min_wave: 2, attack: Melee),
+            })",
+        )
+        .unwrap_err();
+        assert_eq!(errors, vec!["the Walker kind is required"]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Compares the per-tick proximity queries of the server with and without the spatial hash.
+//!
+//! Run with `cargo bench -p zombrise_shared --bench spatial_hash`.
+
+use bevy::prelude::*;
+use rand::Rng;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use zombrise_shared::spatial::spatial_hash::{SpatialHash, SPATIAL_CELL_SIZE};
+
+const PLAYERS: usize = 10;
+const CHASE_RANGE: f32 = 10.0;
+const ATTACK_RANGE: f32 = 2.0;
+const ITERATIONS: u32 = 200;
+const MAP_EXTENT: f32 = 60.0;
+
+fn random_positions(count: usize) -> Vec<(Entity, Vec3)> {
+    let mut rng = rand::rng();
+    (0..count)
+        .map(|i| {
+            let entity = Entity::from_raw_u32(i as u32 + 1).unwrap();
+            let position = Vec3::new(
+                rng.random_range(-MAP_EXTENT..MAP_EXTENT),
+                0.5,
+                rng.random_range(-MAP_EXTENT..MAP_EXTENT),
+            );
+            (entity, position)
+        })
+        .collect()
+}
+
+/// One tick of the old nested loops: nearest player per zombie plus attack range checks.
+fn brute_force_tick(zombies: &[(Entity, Vec3)], players: &[(Entity, Vec3)]) -> usize {
+    let mut found = 0;
+    for (_, zombie) in zombies {
+        let nearest = players
+            .iter()
+            .map(|(_, player)| zombie.distance(*player))
+            .filter(|distance| *distance < CHASE_RANGE)
+            .min_by(|a, b| a.total_cmp(b));
+        found += nearest.is_some() as usize;
+    }
+    for (_, player) in players {
+        found += zombies
+            .iter()
+            .filter(|(_, zombie)| zombie.distance(*player) < ATTACK_RANGE)
+            .count();
+    }
+    found
+}
+
+/// The same tick answered from rebuilt spatial hashes.
+fn spatial_hash_tick(
+    zombies: &[(Entity, Vec3)],
+    players: &[(Entity, Vec3)],
+    zombie_hash: &mut SpatialHash,
+    player_hash: &mut SpatialHash,
+) -> usize {
+    zombie_hash.clear();
+    for (entity, position) in zombies {
+        zombie_hash.insert(*entity, *position);
+    }
+    player_hash.clear();
+    for (entity, position) in players {
+        player_hash.insert(*entity, *position);
+    }
+
+    let mut found = 0;
+    for (_, zombie) in zombies {
+        found += player_hash.nearest(*zombie, CHASE_RANGE).is_some() as usize;
+    }
+    for (_, player) in players {
+        found += zombie_hash.within_radius(*player, ATTACK_RANGE).count();
+    }
+    found
+}
+
+fn time(mut tick: impl FnMut() -> usize) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(tick());
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>8} {:>14} {:>14}",
+        "zombies", "players", "nested loops", "spatial hash"
+    );
+
+    for zombie_count in [30, 100, 300, 1000, 3000] {
+        let zombies = random_positions(zombie_count);
+        let players = random_positions(PLAYERS);
+        let mut zombie_hash = SpatialHash::new(SPATIAL_CELL_SIZE);
+        let mut player_hash = SpatialHash::new(SPATIAL_CELL_SIZE);
+
+        assert_eq!(
+            brute_force_tick(&zombies, &players),
+            spatial_hash_tick(&zombies, &players, &mut zombie_hash, &mut player_hash),
+        );
+
+        let brute_force = time(|| brute_force_tick(&zombies, &players));
+        let spatial_hash = time(|| {
+            spatial_hash_tick(&zombies, &players, &mut zombie_hash, &mut player_hash)
+        });
+
+        println!(
+            "{:>8} {:>8} {:>14?} {:>14?}",
+            zombie_count, PLAYERS, brute_force, spatial_hash
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Measures the replication traffic replicon sends to a client for a horde of moving zombies,
+//! with the full `Transform` replicated like before and with `NetTransform` now. A server and
+//! a client app are connected through replicon's test backend and every replication message
+//! the server hands to the backend is counted, with entity ids, component ids and message
+//! headers. Only the renet packet headers around them are left out.
+//!
+//! Run with `cargo bench -p zombrise_shared --bench transform_bandwidth`.
+
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use bevy_replicon::prelude::*;
+use bevy_replicon::test_app::{ServerTestAppExt, TestClientEntity};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::TAU;
+use zombrise_shared::replication::transform::{sync_replicated_transforms, NetTransform};
+use zombrise_shared::shared::{SharedPlugin, Zombie};
+
+const HORDE_SIZES: [usize; 3] = [10, 30, 100];
+const TICK_RATE: f32 = 60.0;
+const SECONDS: f32 = 30.0;
+const MAP_EXTENT: f32 = 25.0;
+
+/// Walking zombie, roughly like the zombie AI: chasing, wandering or standing still.
+struct SimulatedZombie {
+    position: Vec3,
+    heading: f32,
+    speed: f32,
+    /// Seconds until it picks what to do next.
+    next_decision: f32,
+}
+
+impl SimulatedZombie {
+    fn step(&mut self, rng: &mut StdRng, delta: f32) {
+        self.next_decision -= delta;
+        if self.next_decision <= 0.0 {
+            self.speed = match rng.random::<f32>() {
+                r if r < 0.5 => 3.0,
+                r if r < 0.8 => 1.5,
+                _ => 0.0,
+            };
+            self.heading += rng.random_range(-0.75..0.75);
+            self.next_decision = rng.random_range(0.5..2.5);
+        }
+        if self.speed == 0.0 {
+            return;
+        }
+        // Chasers steer a little every tick
+        if self.speed > 2.0 {
+            self.heading += rng.random_range(-0.025..0.025);
+        }
+        self.position +=
+            Vec3::new(self.heading.sin(), 0.0, self.heading.cos()) * self.speed * delta;
+        // Physics leaves a little noise on the height of moving bodies
+        self.position.y = 0.9 + rng.random_range(-0.001..0.001);
+    }
+
+    fn transform(&self) -> Transform {
+        Transform::from_translation(self.position)
+            .with_rotation(Quat::from_rotation_y(self.heading))
+    }
+}
+
+/// How transforms are replicated.
+#[derive(Clone, Copy)]
+enum Replication {
+    /// The whole `Transform`, as before.
+    FullTransform,
+    /// `NetTransform` through `SharedPlugin`.
+    NetTransform,
+}
+
+fn connected_apps(replication: Replication) -> (App, App) {
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            // One replication tick per update
+            RepliconPlugins.set(ServerPlugin::new(PostUpdate)),
+        ));
+        match replication {
+            Replication::FullTransform => {
+                app.replicate::<Zombie>().replicate::<Transform>();
+            }
+            Replication::NetTransform => {
+                app.add_plugins(SharedPlugin);
+            }
+        }
+        app.finish();
+    }
+    server_app.add_systems(Update, sync_replicated_transforms);
+    server_app.connect_client(&mut client_app);
+    (server_app, client_app)
+}
+
+/// Passes messages between the apps like `ServerTestAppExt::exchange_with_client` and
+/// returns the bytes sent to the client.
+fn exchange(server_app: &mut App, client_app: &mut App) -> usize {
+    let client_entity = **client_app.world().resource::<TestClientEntity>();
+    let mut client_messages = client_app.world_mut().resource_mut::<ClientMessages>();
+    let mut server_messages = server_app.world_mut().resource_mut::<ServerMessages>();
+    for (channel_id, message) in client_messages.drain_sent() {
+        server_messages.insert_received(client_entity, channel_id, message);
+    }
+
+    let mut bytes = 0;
+    for (client, channel_id, message) in server_messages.drain_sent() {
+        if client == client_entity {
+            bytes += message.len();
+            client_messages.insert_received(channel_id, message);
+        }
+    }
+    bytes
+}
+
+/// Average bytes per tick sent to the client once the zombies have been spawned on it.
+fn measure(replication: Replication, zombie_count: usize) -> f32 {
+    let (mut server_app, mut client_app) = connected_apps(replication);
+
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut zombies: Vec<(Entity, SimulatedZombie)> = (0..zombie_count)
+        .map(|_| {
+            let zombie = SimulatedZombie {
+                position: Vec3::new(
+                    rng.random_range(-MAP_EXTENT..MAP_EXTENT),
+                    0.9,
+                    rng.random_range(-MAP_EXTENT..MAP_EXTENT),
+                ),
+                heading: rng.random_range(0.0..TAU),
+                speed: 0.0,
+                next_decision: 0.0,
+            };
+            let transform = zombie.transform();
+            let mut entity = server_app
+                .world_mut()
+                .spawn((Replicated, Zombie, transform));
+            if let Replication::NetTransform = replication {
+                entity.insert(NetTransform::from_transform(&transform));
+            }
+            (entity.id(), zombie)
+        })
+        .collect();
+
+    // The spawns are the same for both and not counted
+    server_app.update();
+    exchange(&mut server_app, &mut client_app);
+    client_app.update();
+
+    let ticks = (SECONDS * TICK_RATE) as usize;
+    let delta = 1.0 / TICK_RATE;
+    let mut bytes = 0;
+    for _ in 0..ticks {
+        for (entity, zombie) in &mut zombies {
+            zombie.step(&mut rng, delta);
+            *server_app
+                .world_mut()
+                .get_mut::<Transform>(*entity)
+                .unwrap() = zombie.transform();
+        }
+        server_app.update();
+        bytes += exchange(&mut server_app, &mut client_app);
+        client_app.update();
+    }
+
+    let replicated = client_app
+        .world_mut()
+        .query_filtered::<(), With<Zombie>>()
+        .iter(client_app.world())
+        .count();
+    assert_eq!(replicated, zombie_count, "client should see every zombie");
+
+    bytes as f32 / ticks as f32
+}
+
+fn main() {
+    println!(
+        "Replication bytes per tick sent to one client, {} Hz for {} s:",
+        TICK_RATE, SECONDS
+    );
+    println!(
+        "{:>8} {:>12} {:>14} {:>14} {:>14}",
+        "zombies", "Transform", "NetTransform", "Transform/s", "NetTransform/s"
+    );
+    for zombie_count in HORDE_SIZES {
+        let before = measure(Replication::FullTransform, zombie_count);
+        let after = measure(Replication::NetTransform, zombie_count);
+        println!(
+            "{:>8} {:>12.0} {:>14.0} {:>14.0} {:>14.0}",
+            zombie_count,
+            before,
+            after,
+            before * TICK_RATE,
+            after * TICK_RATE
+        );
+    }
+}
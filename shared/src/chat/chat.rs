@@ -0,0 +1,27 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Longest chat message in characters, servers may allow less.
+pub const MAX_CHAT_MESSAGE_CHARS: usize = 200;
+
+/// Sent by a client to say something to everyone on the server.
+#[derive(Event, Message, Serialize, Deserialize, Clone, Debug)]
+pub struct SendChatMessage {
+    pub text: String,
+}
+
+/// Who a chat line comes from.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ChatSender {
+    /// A player, by their `PlayerName`.
+    Player(String),
+    /// The server itself, e.g. for joins, deaths and waves.
+    System,
+}
+
+/// Chat line broadcast by the server, already filtered.
+#[derive(Event, Message, Serialize, Deserialize, Clone, Debug)]
+pub struct ChatMessage {
+    pub sender: ChatSender,
+    pub text: String,
+}
@@ -1,3 +1,14 @@
+#![allow(
+    clippy::module_inception,
+    clippy::too_many_arguments,
+    clippy::type_complexity
+)]
+
+pub mod chat;
 pub mod players;
+pub mod protocol;
+pub mod replication;
 pub mod shared;
+pub mod spatial;
+pub mod wave;
 pub mod zombie;
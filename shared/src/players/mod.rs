@@ -1 +1,2 @@
+pub mod movement;
 pub mod player;
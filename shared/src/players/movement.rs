@@ -0,0 +1,41 @@
+use bevy::math::{Quat, Vec3};
+
+use super::player::MovePlayer;
+
+/// Walking speed of players in units per second.
+pub const PLAYER_SPEED: f32 = 5.0;
+/// Upward velocity of a jump.
+pub const PLAYER_JUMP_VELOCITY: f32 = 5.0;
+/// Movement inputs the client sends per second, the server's default tick rate.
+pub const INPUT_RATE: f64 = 60.0;
+
+/// What a `MovePlayer` input does to a player. The server applies it to the physics body,
+/// the client uses it to predict its own player until the server's state arrives.
+pub struct PlayerMovement {
+    /// Horizontal velocity, `y` is always zero.
+    pub velocity: Vec3,
+    /// Facing along the movement, `None` when standing still.
+    pub rotation: Option<Quat>,
+    pub jump: bool,
+}
+
+pub fn player_movement(input: &MovePlayer) -> PlayerMovement {
+    // Clients send unit directions, longer ones would make the player faster
+    let (direction, camera_yaw) = if input.direction.is_finite() && input.camera_yaw.is_finite() {
+        (input.direction.clamp_length_max(1.0), input.camera_yaw)
+    } else {
+        (Vec3::ZERO, 0.0)
+    };
+    let rotated_direction = Quat::from_rotation_y(camera_yaw) * direction;
+    let horizontal_direction = Vec3::new(rotated_direction.x, 0.0, rotated_direction.z);
+
+    // Rotate player to face movement direction (only in XZ plane)
+    let rotation = (horizontal_direction.length() > 0.01)
+        .then(|| Quat::from_rotation_arc(Vec3::NEG_Z, horizontal_direction.normalize()));
+
+    PlayerMovement {
+        velocity: horizontal_direction * PLAYER_SPEED,
+        rotation,
+        jump: direction.y > 0.0,
+    }
+}
@@ -38,17 +38,65 @@ pub struct DamageFlash {
 #[derive(Component, Serialize, Deserialize, Reflect)]
 pub struct PlayerOwner(pub ClientId);
 
+/// Longest display name in characters, the server cuts longer ones.
+pub const MAX_PLAYER_NAME_CHARS: usize = 16;
+
+/// Display name of a player, sanitized and unique among the players on the server.
+#[derive(Component, Serialize, Deserialize, Reflect, Clone, Debug, PartialEq, Eq)]
+pub struct PlayerName(pub String);
+
+/// Marks a player that has died and is waiting to respawn.
+#[derive(Component, Serialize, Deserialize, Reflect, Clone)]
+pub struct Dead {
+    /// Seconds until the player may request a respawn.
+    pub respawn_in: f32,
+}
+
 #[derive(Component)]
 pub struct MainCamera;
 
-#[derive(Event, Message, Serialize, Deserialize)]
+/// Movement input of one fixed tick, sent every tick while playing, also when standing still.
+#[derive(Event, Message, Serialize, Deserialize, Clone, Debug)]
 pub struct MovePlayer {
+    /// Counts up with every input, so the server can drop late ones and acknowledge the rest.
+    pub sequence: u32,
     pub direction: Vec3,
     pub camera_yaw: f32,
 }
 
+/// `sequence` of the last `MovePlayer` the server applied to this player. It is replicated
+/// together with the `NetTransform` the input resulted in, so the owner knows which of its
+/// predicted inputs are already part of that position.
+#[derive(
+    Component, Serialize, Deserialize, Reflect, Clone, Copy, Default, Debug, PartialEq, Eq,
+)]
+pub struct InputAck(pub u32);
+
+#[derive(Event, Message, Serialize, Deserialize)]
+pub struct PlayerAttack {
+    /// `ViewTime` when the attack was made, the server judges hits against where targets
+    /// were at that moment.
+    pub view_time: Option<f64>,
+}
+
+/// Asks the server to respawn the sender's dead player.
 #[derive(Event, Message, Serialize, Deserialize)]
-pub struct PlayerAttack;
+pub struct RequestRespawn;
+
+/// What a confirmed attack landed on.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttackTarget {
+    Zombie,
+    Player(ClientId),
+}
+
+/// Sent by the server for every hit it accepted from a `PlayerAttack`.
+#[derive(Event, Message, Serialize, Deserialize, Clone, Debug)]
+pub struct AttackConfirmed {
+    pub attacker: ClientId,
+    pub target: AttackTarget,
+    pub position: Vec3,
+}
 
 #[derive(Event, Message, Serialize, Deserialize)]
 pub struct DamagePlayer {
@@ -56,12 +104,15 @@ pub struct DamagePlayer {
     pub amount: f32,
 }
 
+/// Samples the movement keys once per fixed tick, the rate the server simulates at, and
+/// sends them. Standing still is sent as well, the server keeps the last input until the
+/// next one.
 #[cfg(feature = "client")]
-pub fn handle_input(
+pub fn send_move_input(
     keyboard_input: Res<ButtonInput<KeyCode>>,
     mut move_events: bevy::prelude::MessageWriter<MovePlayer>,
-    mut attack_events: bevy::prelude::MessageWriter<PlayerAttack>,
     camera_rotation: Option<Res<CameraRotation>>,
+    mut sequence: bevy::prelude::ResMut<InputSequence>,
 ) {
     let mut direction = Vec3::ZERO;
 
@@ -78,20 +129,43 @@ pub fn handle_input(
         direction.x += 1.0;
     }
 
-    if direction.length() > 0.0 {
-        direction = direction.normalize();
-        let camera_yaw = camera_rotation.map(|r| r.yaw).unwrap_or(0.0);
-        move_events.write(MovePlayer {
-            direction,
-            camera_yaw,
+    let camera_yaw = camera_rotation.map(|r| r.yaw).unwrap_or(0.0);
+    move_events.write(MovePlayer {
+        sequence: sequence.advance(),
+        direction: direction.normalize_or_zero(),
+        camera_yaw,
+    });
+}
+
+#[cfg(feature = "client")]
+pub fn handle_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut attack_events: bevy::prelude::MessageWriter<PlayerAttack>,
+    view_time: Res<ViewTime>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        attack_events.write(PlayerAttack {
+            view_time: view_time.0,
         });
     }
+}
 
-    if keyboard_input.just_pressed(KeyCode::Space) {
-        attack_events.write(PlayerAttack);
+/// Sequence number of the last `MovePlayer` this client sent.
+#[derive(bevy::prelude::Resource, Default)]
+pub struct InputSequence(pub u32);
+
+impl InputSequence {
+    pub fn advance(&mut self) -> u32 {
+        self.0 += 1;
+        self.0
     }
 }
 
+/// `ServerTime` of what the client shows of other players and zombies, which lags behind
+/// the server by the latency and the interpolation delay. `None` until the time arrived.
+#[derive(bevy::prelude::Resource, Default)]
+pub struct ViewTime(pub Option<f64>);
+
 #[derive(bevy::prelude::Resource)]
 pub struct CameraRotation {
     pub yaw: f32,
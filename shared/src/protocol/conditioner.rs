@@ -0,0 +1,526 @@
+//! Bad network connections simulated on top of a real socket, to reproduce what players on
+//! poor connections see while testing on localhost.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use renet2_netcode::{ClientSocket, NetcodeTransportError, ServerSocket};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Extra delay of reordered packets, enough to arrive after the ones sent after them.
+const REORDER_DELAY: Duration = Duration::from_millis(50);
+/// Larger than any netcode packet.
+const MAX_PACKET_BYTES: usize = 2048;
+
+/// What to do to packets, in both directions. The default leaves them alone.
+///
+/// Parsed from `latency=100,jitter=20,loss=0.05,duplicate=0.01,reorder=0.02,seed=7`,
+/// where every key is optional.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConditions {
+    /// Milliseconds every packet is held back.
+    pub latency_ms: u32,
+    /// Up to this many milliseconds added on top, picked for every packet.
+    pub jitter_ms: u32,
+    /// Share of packets dropped, from 0 to 1.
+    pub loss: f32,
+    /// Share of packets delivered twice.
+    pub duplicate: f32,
+    /// Share of packets delivered late, after packets sent after them.
+    pub reorder: f32,
+    /// Makes the dropped, duplicated and delayed packets the same on every run, for tests.
+    pub seed: Option<u64>,
+}
+
+impl NetworkConditions {
+    pub fn is_active(&self) -> bool {
+        self.latency_ms > 0
+            || self.jitter_ms > 0
+            || self.loss > 0.0
+            || self.duplicate > 0.0
+            || self.reorder > 0.0
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, share) in [
+            ("loss", self.loss),
+            ("duplicate", self.duplicate),
+            ("reorder", self.reorder),
+        ] {
+            if !(0.0..=1.0).contains(&share) {
+                return Err(format!("{} must be between 0 and 1", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for NetworkConditions {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut conditions = Self::default();
+        for setting in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got `{}`", setting))?;
+            let value = value.trim();
+            let invalid = || format!("invalid value for {}: `{}`", key.trim(), value);
+            match key.trim() {
+                "latency" => conditions.latency_ms = value.parse().map_err(|_| invalid())?,
+                "jitter" => conditions.jitter_ms = value.parse().map_err(|_| invalid())?,
+                "loss" => conditions.loss = value.parse().map_err(|_| invalid())?,
+                "duplicate" => conditions.duplicate = value.parse().map_err(|_| invalid())?,
+                "reorder" => conditions.reorder = value.parse().map_err(|_| invalid())?,
+                "seed" => conditions.seed = Some(value.parse().map_err(|_| invalid())?),
+                key => return Err(format!("unknown setting `{}`", key)),
+            }
+        }
+        conditions.validate()?;
+        Ok(conditions)
+    }
+}
+
+impl fmt::Display for NetworkConditions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ms latency, {} ms jitter, {}% loss, {}% duplicated, {}% reordered",
+            self.latency_ms,
+            self.jitter_ms,
+            self.loss * 100.0,
+            self.duplicate * 100.0,
+            self.reorder * 100.0
+        )
+    }
+}
+
+/// Packet waiting for its delay to pass. Ordered by due time, then by arrival.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct DelayedPacket {
+    due: Instant,
+    order: u64,
+    addr: SocketAddr,
+    bytes: Vec<u8>,
+}
+
+/// Socket that applies `NetworkConditions` to everything sent and received through the
+/// socket it wraps. Works for both the server and the client transport, and is meant for
+/// unreliable sockets like `NativeSocket`.
+#[derive(Debug)]
+pub struct ConditionedSocket<S> {
+    inner: S,
+    conditions: NetworkConditions,
+    rng: StdRng,
+    incoming: BinaryHeap<Reverse<DelayedPacket>>,
+    outgoing: BinaryHeap<Reverse<DelayedPacket>>,
+    /// Counts packets so ones due at the same moment keep their order.
+    next_order: u64,
+    receive_buffer: Vec<u8>,
+}
+
+impl<S> ConditionedSocket<S> {
+    pub fn new(inner: S, conditions: NetworkConditions) -> Self {
+        let rng = match conditions.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        Self {
+            inner,
+            conditions,
+            rng,
+            incoming: BinaryHeap::new(),
+            outgoing: BinaryHeap::new(),
+            next_order: 0,
+            receive_buffer: vec![0; MAX_PACKET_BYTES],
+        }
+    }
+
+    /// Copies of the packet with the delays they get, none if it is lost.
+    fn schedule(&mut self, addr: SocketAddr, bytes: Vec<u8>) -> Vec<DelayedPacket> {
+        if self.rng.random::<f32>() < self.conditions.loss {
+            return Vec::new();
+        }
+        let copies = if self.rng.random::<f32>() < self.conditions.duplicate {
+            2
+        } else {
+            1
+        };
+
+        let now = Instant::now();
+        (0..copies)
+            .map(|_| {
+                let jitter = self.rng.random_range(0..=self.conditions.jitter_ms);
+                let mut delay = Duration::from_millis((self.conditions.latency_ms + jitter) as u64);
+                if self.rng.random::<f32>() < self.conditions.reorder {
+                    delay += REORDER_DELAY;
+                }
+                self.next_order += 1;
+                DelayedPacket {
+                    due: now + delay,
+                    order: self.next_order,
+                    addr,
+                    bytes: bytes.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Takes everything the real socket has and hands out the first packet whose delay passed.
+    fn receive(
+        &mut self,
+        buffer: &mut [u8],
+        mut recv: impl FnMut(&mut S, &mut [u8]) -> io::Result<(usize, SocketAddr)>,
+    ) -> io::Result<(usize, SocketAddr)> {
+        if !self.conditions.is_active() {
+            return recv(&mut self.inner, buffer);
+        }
+
+        loop {
+            match recv(&mut self.inner, &mut self.receive_buffer) {
+                Ok((len, addr)) => {
+                    let bytes = self.receive_buffer[..len].to_vec();
+                    for packet in self.schedule(addr, bytes) {
+                        self.incoming.push(Reverse(packet));
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let due = self
+            .incoming
+            .peek()
+            .is_some_and(|Reverse(packet)| packet.due <= Instant::now());
+        if !due {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let Reverse(packet) = self.incoming.pop().unwrap();
+        let len = packet.bytes.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&packet.bytes[..len]);
+        Ok((len, packet.addr))
+    }
+
+    fn send_later<E>(
+        &mut self,
+        addr: SocketAddr,
+        packet: &[u8],
+        mut send: impl FnMut(&mut S, SocketAddr, &[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if !self.conditions.is_active() {
+            return send(&mut self.inner, addr, packet);
+        }
+        for packet in self.schedule(addr, packet.to_vec()) {
+            self.outgoing.push(Reverse(packet));
+        }
+        Ok(())
+    }
+
+    /// Sends the packets whose delay passed.
+    fn flush<E>(&mut self, mut send: impl FnMut(&mut S, SocketAddr, &[u8]) -> Result<(), E>) {
+        let now = Instant::now();
+        while self
+            .outgoing
+            .peek()
+            .is_some_and(|Reverse(packet)| packet.due <= now)
+        {
+            let Reverse(packet) = self.outgoing.pop().unwrap();
+            // A failed send is just one more lost packet
+            let _ = send(&mut self.inner, packet.addr, &packet.bytes);
+        }
+    }
+}
+
+impl<S: ServerSocket> ServerSocket for ConditionedSocket<S> {
+    fn is_encrypted(&self) -> bool {
+        self.inner.is_encrypted()
+    }
+
+    fn is_reliable(&self) -> bool {
+        self.inner.is_reliable()
+    }
+
+    fn addr(&self) -> io::Result<SocketAddr> {
+        self.inner.addr()
+    }
+
+    fn is_closed(&mut self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn close(&mut self) {
+        self.inner.close();
+    }
+
+    fn connection_denied(&mut self, addr: SocketAddr) {
+        self.inner.connection_denied(addr);
+    }
+
+    fn connection_accepted(&mut self, client_id: u64, addr: SocketAddr) {
+        self.inner.connection_accepted(client_id, addr);
+    }
+
+    fn disconnect(&mut self, addr: SocketAddr) {
+        self.inner.disconnect(addr);
+    }
+
+    fn preupdate(&mut self) {
+        self.inner.preupdate();
+    }
+
+    fn try_recv(&mut self, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.receive(buffer, |inner, buffer| inner.try_recv(buffer))
+    }
+
+    fn postupdate(&mut self) {
+        self.flush(|inner, addr, packet| inner.send(addr, packet));
+        self.inner.postupdate();
+    }
+
+    fn send(&mut self, addr: SocketAddr, packet: &[u8]) -> Result<(), NetcodeTransportError> {
+        self.send_later(addr, packet, |inner, addr, packet| inner.send(addr, packet))
+    }
+}
+
+impl<S: ClientSocket> ClientSocket for ConditionedSocket<S> {
+    fn is_encrypted(&self) -> bool {
+        self.inner.is_encrypted()
+    }
+
+    fn is_reliable(&self) -> bool {
+        self.inner.is_reliable()
+    }
+
+    fn addr(&self) -> io::Result<SocketAddr> {
+        self.inner.addr()
+    }
+
+    fn is_closed(&mut self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn close(&mut self) {
+        self.inner.close();
+    }
+
+    fn preupdate(&mut self) {
+        self.inner.preupdate();
+    }
+
+    fn try_recv(&mut self, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.receive(buffer, |inner, buffer| inner.try_recv(buffer))
+    }
+
+    fn postupdate(&mut self) {
+        self.flush(|inner, addr, packet| inner.send(addr, packet));
+        self.inner.postupdate();
+    }
+
+    fn send(&mut self, addr: SocketAddr, packet: &[u8]) -> Result<(), NetcodeTransportError> {
+        self.send_later(addr, packet, |inner, addr, packet| inner.send(addr, packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    const PACKETS: u32 = 1000;
+
+    /// Stands in for a real socket: hands out queued packets and records what was sent.
+    #[derive(Default)]
+    struct FakeSocket {
+        inbox: VecDeque<(Vec<u8>, SocketAddr)>,
+        sent: Vec<Vec<u8>>,
+    }
+
+    impl FakeSocket {
+        fn try_recv(&mut self, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+            let (bytes, addr) = self
+                .inbox
+                .pop_front()
+                .ok_or(io::Error::from(io::ErrorKind::WouldBlock))?;
+            buffer[..bytes.len()].copy_from_slice(&bytes);
+            Ok((bytes.len(), addr))
+        }
+
+        fn send(&mut self, _addr: SocketAddr, packet: &[u8]) -> io::Result<()> {
+            self.sent.push(packet.to_vec());
+            Ok(())
+        }
+    }
+
+    fn peer() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 5000))
+    }
+
+    fn conditioned(conditions: &str) -> ConditionedSocket<FakeSocket> {
+        ConditionedSocket::new(FakeSocket::default(), conditions.parse().unwrap())
+    }
+
+    /// Sends `PACKETS` numbered packets and flushes the ones that are due.
+    fn send_numbered(socket: &mut ConditionedSocket<FakeSocket>) {
+        for number in 0..PACKETS {
+            socket
+                .send_later(peer(), &number.to_le_bytes(), FakeSocket::send)
+                .unwrap();
+        }
+        socket.flush(FakeSocket::send);
+    }
+
+    fn numbers(sent: &[Vec<u8>]) -> Vec<u32> {
+        sent.iter()
+            .map(|bytes| u32::from_le_bytes(bytes[..4].try_into().unwrap()))
+            .collect()
+    }
+
+    fn receive(socket: &mut ConditionedSocket<FakeSocket>) -> Option<Vec<u8>> {
+        let mut buffer = [0; MAX_PACKET_BYTES];
+        socket
+            .receive(&mut buffer, FakeSocket::try_recv)
+            .ok()
+            .map(|(len, _)| buffer[..len].to_vec())
+    }
+
+    #[test]
+    fn loss_drops_about_that_share() {
+        let mut socket = conditioned("loss=0.3,seed=1");
+        send_numbered(&mut socket);
+
+        let delivered = socket.inner.sent.len() as u32;
+        assert!((600..800).contains(&delivered), "{} delivered", delivered);
+        // Whatever gets through keeps its order
+        let numbers = numbers(&socket.inner.sent);
+        assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn duplicate_delivers_copies() {
+        let mut socket = conditioned("duplicate=0.2,seed=2");
+        send_numbered(&mut socket);
+
+        let delivered = socket.inner.sent.len() as u32;
+        assert!(
+            (PACKETS + 100..PACKETS + 300).contains(&delivered),
+            "{} delivered",
+            delivered
+        );
+        let numbers = numbers(&socket.inner.sent);
+        let copies = numbers.windows(2).filter(|pair| pair[0] == pair[1]).count();
+        assert_eq!(copies as u32, delivered - PACKETS);
+    }
+
+    #[test]
+    fn reorder_delivers_some_packets_after_later_ones() {
+        let mut socket = conditioned("reorder=0.1,seed=3");
+        send_numbered(&mut socket);
+
+        let on_time = socket.inner.sent.len() as u32;
+        assert!((850..950).contains(&on_time), "{} on time", on_time);
+
+        std::thread::sleep(REORDER_DELAY + Duration::from_millis(20));
+        socket.flush(FakeSocket::send);
+        let numbers = numbers(&socket.inner.sent);
+        assert_eq!(numbers.len() as u32, PACKETS);
+        let late = numbers.windows(2).filter(|pair| pair[0] > pair[1]).count();
+        assert!(late > 0);
+
+        let mut sorted = numbers.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..PACKETS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn same_seed_drops_the_same_packets() {
+        let mut first = conditioned("loss=0.5,duplicate=0.1,seed=4");
+        let mut second = conditioned("loss=0.5,duplicate=0.1,seed=4");
+        send_numbered(&mut first);
+        send_numbered(&mut second);
+        assert_eq!(first.inner.sent, second.inner.sent);
+    }
+
+    #[test]
+    fn delayed_packets_are_held_until_due() {
+        let mut socket = conditioned("latency=80,seed=5");
+        socket.inner.inbox.push_back((b"incoming".to_vec(), peer()));
+        socket
+            .send_later(peer(), b"outgoing", FakeSocket::send)
+            .unwrap();
+
+        socket.flush(FakeSocket::send);
+        assert!(socket.inner.sent.is_empty());
+        assert_eq!(receive(&mut socket), None);
+        // Taken from the real socket, but held back
+        assert!(socket.inner.inbox.is_empty());
+
+        std::thread::sleep(Duration::from_millis(100));
+        socket.flush(FakeSocket::send);
+        assert_eq!(socket.inner.sent, vec![b"outgoing".to_vec()]);
+        assert_eq!(receive(&mut socket), Some(b"incoming".to_vec()));
+        assert_eq!(receive(&mut socket), None);
+    }
+
+    #[test]
+    fn inactive_conditions_pass_packets_through() {
+        let mut socket = conditioned("");
+        socket.inner.inbox.push_back((b"incoming".to_vec(), peer()));
+        socket
+            .send_later(peer(), b"outgoing", FakeSocket::send)
+            .unwrap();
+        assert_eq!(socket.inner.sent, vec![b"outgoing".to_vec()]);
+        assert_eq!(receive(&mut socket), Some(b"incoming".to_vec()));
+    }
+
+    #[test]
+    fn parses_every_setting() {
+        let conditions: NetworkConditions =
+            "latency=100, jitter=20,loss=0.05,duplicate=0.01,reorder=0.02,seed=7"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            conditions,
+            NetworkConditions {
+                latency_ms: 100,
+                jitter_ms: 20,
+                loss: 0.05,
+                duplicate: 0.01,
+                reorder: 0.02,
+                seed: Some(7),
+            }
+        );
+        assert_eq!("".parse::<NetworkConditions>(), Ok(Default::default()));
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        assert_eq!(
+            "latency".parse::<NetworkConditions>(),
+            Err("expected key=value, got `latency`".to_string())
+        );
+        assert_eq!(
+            "latency=fast".parse::<NetworkConditions>(),
+            Err("invalid value for latency: `fast`".to_string())
+        );
+        assert_eq!(
+            "latency=-5".parse::<NetworkConditions>(),
+            Err("invalid value for latency: `-5`".to_string())
+        );
+        assert_eq!(
+            "bandwidth=10".parse::<NetworkConditions>(),
+            Err("unknown setting `bandwidth`".to_string())
+        );
+        assert_eq!(
+            "loss=1.5".parse::<NetworkConditions>(),
+            Err("loss must be between 0 and 1".to_string())
+        );
+    }
+}
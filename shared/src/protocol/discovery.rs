@@ -0,0 +1,131 @@
+use std::net::SocketAddr;
+
+use crate::protocol::wire::{write_string, Reader};
+
+/// UDP port game servers listen on for LAN discovery queries.
+pub const DISCOVERY_PORT: u16 = 5050;
+
+const QUERY_MAGIC: [u8; 4] = *b"ZRDQ";
+const RESPONSE_MAGIC: [u8; 4] = *b"ZRDR";
+
+/// Broadcast by clients looking for servers on the local network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiscoveryQuery {
+    /// Echoed in the response so the client can tell which query it answers and time it.
+    pub nonce: u64,
+}
+
+impl DiscoveryQuery {
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = QUERY_MAGIC.to_vec();
+        bytes.extend_from_slice(&self.nonce.to_le_bytes());
+        bytes
+    }
+
+    /// `None` for packets that aren't discovery queries.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(bytes, QUERY_MAGIC)?;
+        Some(Self {
+            nonce: reader.u64()?,
+        })
+    }
+}
+
+/// What a server tells about itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerInfo {
+    pub name: String,
+    /// Port of the game server, on the address the answer came from.
+    pub game_port: u16,
+    /// UDP port the server answers `DiscoveryQuery`s on when asked directly. Only one server
+    /// per host gets `DISCOVERY_PORT`, so the server browser pings this one.
+    pub query_port: u16,
+    /// Port of the token service on the game server's host in secure mode, `None` when
+    /// clients connect to the game port directly.
+    pub token_port: Option<u16>,
+    pub players: u16,
+    pub max_players: u16,
+    /// Current wave, zero before the first wave started.
+    pub wave: u32,
+    /// Readable version, see `ProtocolVersion::display`.
+    pub version: String,
+    /// `ProtocolVersion::hash` of the server, clients can only join with the same one.
+    pub protocol_hash: u64,
+}
+
+impl ServerInfo {
+    pub(crate) fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.game_port.to_le_bytes());
+        bytes.extend_from_slice(&self.query_port.to_le_bytes());
+        // Zero is no port, so it stands for no token service
+        bytes.extend_from_slice(&self.token_port.unwrap_or(0).to_le_bytes());
+        bytes.extend_from_slice(&self.players.to_le_bytes());
+        bytes.extend_from_slice(&self.max_players.to_le_bytes());
+        bytes.extend_from_slice(&self.wave.to_le_bytes());
+        bytes.extend_from_slice(&self.protocol_hash.to_le_bytes());
+        write_string(bytes, &self.name);
+        write_string(bytes, &self.version);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Option<Self> {
+        let game_port = reader.u16()?;
+        let query_port = reader.u16()?;
+        let token_port = Some(reader.u16()?).filter(|port| *port != 0);
+        let players = reader.u16()?;
+        let max_players = reader.u16()?;
+        let wave = reader.u32()?;
+        let protocol_hash = reader.u64()?;
+        let name = reader.string()?;
+        let version = reader.string()?;
+        Some(Self {
+            name,
+            game_port,
+            query_port,
+            token_port,
+            players,
+            max_players,
+            wave,
+            version,
+            protocol_hash,
+        })
+    }
+
+    /// Address clients join through and whether it is a token service, for a server whose
+    /// game runs on `game_address`.
+    pub fn join_address(&self, game_address: SocketAddr) -> (SocketAddr, bool) {
+        match self.token_port {
+            Some(token_port) => (SocketAddr::new(game_address.ip(), token_port), true),
+            None => (game_address, false),
+        }
+    }
+
+    /// Whether no more players can join.
+    pub fn is_full(&self) -> bool {
+        self.players >= self.max_players
+    }
+}
+
+/// Sent back by servers to the address a query came from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveryResponse {
+    pub nonce: u64,
+    pub info: ServerInfo,
+}
+
+impl DiscoveryResponse {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = RESPONSE_MAGIC.to_vec();
+        bytes.extend_from_slice(&self.nonce.to_le_bytes());
+        self.info.write(&mut bytes);
+        bytes
+    }
+
+    /// `None` for packets that aren't discovery responses.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(bytes, RESPONSE_MAGIC)?;
+        Some(Self {
+            nonce: reader.u64()?,
+            info: ServerInfo::read(&mut reader)?,
+        })
+    }
+}
@@ -0,0 +1,213 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::protocol::discovery::ServerInfo;
+use crate::protocol::wire::Reader;
+
+/// Port the master server receives heartbeats (UDP) and list requests (TCP) on.
+pub const MASTER_PORT: u16 = 5060;
+/// Seconds between two heartbeats of a game server.
+pub const HEARTBEAT_INTERVAL: f32 = 10.0;
+
+const HEARTBEAT_MAGIC: [u8; 4] = *b"ZRMH";
+const LIST_REQUEST_MAGIC: [u8; 4] = *b"ZRMQ";
+const LIST_MAGIC: [u8; 4] = *b"ZRML";
+
+/// Sent by game servers over UDP to get listed. Servers that stop sending them drop off
+/// the list, so there is no separate unregister message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heartbeat {
+    pub info: ServerInfo,
+}
+
+impl Heartbeat {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = HEARTBEAT_MAGIC.to_vec();
+        self.info.write(&mut bytes);
+        bytes
+    }
+
+    /// `None` for packets that aren't heartbeats.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(bytes, HEARTBEAT_MAGIC)?;
+        Some(Self {
+            info: ServerInfo::read(&mut reader)?,
+        })
+    }
+}
+
+/// Sent by clients over TCP, answered with a `ServerList`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ServerListRequest;
+
+impl ServerListRequest {
+    pub fn to_bytes(self) -> Vec<u8> {
+        LIST_REQUEST_MAGIC.to_vec()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Reader::new(bytes, LIST_REQUEST_MAGIC).map(|_| Self)
+    }
+}
+
+/// Game server on the master server list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListedServer {
+    /// Game address, the address the heartbeats came from with the announced game port.
+    pub address: SocketAddr,
+    pub info: ServerInfo,
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct ServerList {
+    pub servers: Vec<ListedServer>,
+}
+
+impl ServerList {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = LIST_MAGIC.to_vec();
+        let count = self.servers.len().min(u16::MAX as usize);
+        bytes.extend_from_slice(&(count as u16).to_le_bytes());
+        for server in &self.servers[..count] {
+            match server.address.ip() {
+                IpAddr::V4(ip) => {
+                    bytes.push(4);
+                    bytes.extend_from_slice(&ip.octets());
+                }
+                IpAddr::V6(ip) => {
+                    bytes.push(6);
+                    bytes.extend_from_slice(&ip.octets());
+                }
+            }
+            bytes.extend_from_slice(&server.address.port().to_le_bytes());
+            server.info.write(&mut bytes);
+        }
+        bytes
+    }
+
+    /// `None` for data that isn't a complete server list.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(bytes, LIST_MAGIC)?;
+        let count = reader.u16()?;
+        let mut servers = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let ip = match reader.u8()? {
+                4 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(reader.take(4)?).ok()?)),
+                6 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(reader.take(16)?).ok()?)),
+                _ => return None,
+            };
+            let port = reader.u16()?;
+            servers.push(ListedServer {
+                address: SocketAddr::new(ip, port),
+                info: ServerInfo::read(&mut reader)?,
+            });
+        }
+        Some(Self { servers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(name: &str) -> ServerInfo {
+        ServerInfo {
+            name: name.to_string(),
+            game_port: 5000,
+            query_port: 40123,
+            token_port: Some(5001),
+            players: 3,
+            max_players: 10,
+            wave: 7,
+            version: "0.1.0 (1a2b3c4d)".to_string(),
+            protocol_hash: 0x1a2b_3c4d_5e6f_7081,
+        }
+    }
+
+    #[test]
+    fn heartbeat_round_trip() {
+        let heartbeat = Heartbeat {
+            info: info("Zombrise server"),
+        };
+        assert_eq!(
+            Heartbeat::from_bytes(&heartbeat.to_bytes()),
+            Some(heartbeat)
+        );
+    }
+
+    #[test]
+    fn heartbeat_without_token_service_round_trip() {
+        let heartbeat = Heartbeat {
+            info: ServerInfo {
+                token_port: None,
+                ..info("Open server")
+            },
+        };
+        assert_eq!(
+            Heartbeat::from_bytes(&heartbeat.to_bytes()),
+            Some(heartbeat)
+        );
+    }
+
+    #[test]
+    fn server_list_round_trip() {
+        let list = ServerList {
+            servers: vec![
+                ListedServer {
+                    address: "203.0.113.7:5000".parse().unwrap(),
+                    info: info("First"),
+                },
+                ListedServer {
+                    address: "[2001:db8::1]:5002".parse().unwrap(),
+                    info: info("Second"),
+                },
+            ],
+        };
+        assert_eq!(ServerList::from_bytes(&list.to_bytes()), Some(list));
+        assert_eq!(
+            ServerList::from_bytes(&ServerList::default().to_bytes()),
+            Some(ServerList::default())
+        );
+    }
+
+    #[test]
+    fn truncated_messages_are_rejected() {
+        let heartbeat = Heartbeat {
+            info: info("Zombrise server"),
+        }
+        .to_bytes();
+        for len in 0..heartbeat.len() {
+            assert_eq!(Heartbeat::from_bytes(&heartbeat[..len]), None);
+        }
+
+        let list = ServerList {
+            servers: vec![ListedServer {
+                address: "203.0.113.7:5000".parse().unwrap(),
+                info: info("First"),
+            }],
+        }
+        .to_bytes();
+        for len in 0..list.len() {
+            assert_eq!(ServerList::from_bytes(&list[..len]), None);
+        }
+    }
+
+    #[test]
+    fn other_messages_are_rejected() {
+        let heartbeat = Heartbeat {
+            info: info("Zombrise server"),
+        };
+        assert_eq!(ServerList::from_bytes(&heartbeat.to_bytes()), None);
+        assert_eq!(Heartbeat::from_bytes(&ServerListRequest.to_bytes()), None);
+        assert_eq!(
+            ServerListRequest::from_bytes(&ServerListRequest.to_bytes()),
+            Some(ServerListRequest)
+        );
+
+        // Unknown address family
+        let mut list = LIST_MAGIC.to_vec();
+        list.extend_from_slice(&1u16.to_le_bytes());
+        list.push(5);
+        list.extend_from_slice(&[0; 32]);
+        assert_eq!(ServerList::from_bytes(&list), None);
+    }
+}
@@ -0,0 +1,5 @@
+pub mod conditioner;
+pub mod discovery;
+pub mod master;
+pub mod protocol;
+mod wire;
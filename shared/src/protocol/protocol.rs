@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+use bevy_replicon_renet2::netcode::NETCODE_USER_DATA_BYTES;
+
+/// Version of the shared crate, the readable part of the protocol version.
+pub const SHARED_CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Registers replicated components and messages like `app.replicate` and
+/// `app.add_*_message`, and returns a description of every registration in order.
+/// The descriptions feed the protocol hash, so builds that register different types
+/// or channels end up with different protocol versions.
+#[macro_export]
+macro_rules! register_protocol {
+    (
+        $app:expr,
+        replicate: [$($component:ty),* $(,)?],
+        client_messages: [$($client_message:ty => $client_channel:expr),* $(,)?],
+        server_messages: [$($server_message:ty => $server_channel:expr),* $(,)?] $(,)?
+    ) => {{
+        let mut registrations: Vec<String> = Vec::new();
+        $(
+            $app.replicate::<$component>();
+            registrations.push(format!("replicate {}", std::any::type_name::<$component>()));
+        )*
+        $(
+            $app.add_client_message::<$client_message>($client_channel);
+            registrations.push(format!(
+                "client message {} on {}",
+                std::any::type_name::<$client_message>(),
+                stringify!($client_channel)
+            ));
+        )*
+        $(
+            $app.add_server_message::<$server_message>($server_channel);
+            registrations.push(format!(
+                "server message {} on {}",
+                std::any::type_name::<$server_message>(),
+                stringify!($server_channel)
+            ));
+        )*
+        registrations
+    }};
+}
+
+/// Protocol version of this build, both ends must have the same one to play together.
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolVersion {
+    /// Hash of the crate version and all protocol registrations. Used as the netcode
+    /// protocol id, so the handshake already turns away clients of another version.
+    pub hash: u64,
+}
+
+impl ProtocolVersion {
+    pub fn new(registrations: &[String]) -> Self {
+        // FNV-1a, stable across runs and platforms unlike `DefaultHasher`
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let parts =
+            std::iter::once(SHARED_CRATE_VERSION).chain(registrations.iter().map(String::as_str));
+        for part in parts {
+            for byte in part.bytes().chain(std::iter::once(b'\n')) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        Self { hash }
+    }
+
+    /// Human readable version, e.g. `0.1.0 (3fa2c1d0)`.
+    pub fn display(&self) -> String {
+        format!("{} ({:08x})", SHARED_CRATE_VERSION, self.hash as u32)
+    }
+}
+
+/// First byte of a token service answer when a connect token follows.
+pub const TOKEN_ISSUED: u8 = 0;
+/// First byte of a token service answer refusing a client of another protocol version,
+/// followed by the server's readable version.
+pub const TOKEN_VERSION_MISMATCH: u8 = 1;
+
+/// What a client tells the server in the netcode user data when connecting.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectUserData {
+    /// Persistent identity of the player's install, used to resume sessions.
+    pub identity: Option<u64>,
+    /// `ProtocolVersion::hash` of the client, 0 for builds that didn't send one.
+    pub protocol_hash: u64,
+    /// Random secret stored next to the identity. A session is only handed back to a client
+    /// that sends the same secret as when it was created, knowing the identity isn't enough.
+    pub secret: u128,
+    /// Display name the player asked for, the server sanitizes it.
+    pub name: String,
+}
+
+impl ConnectUserData {
+    /// Room for the name in the user data, enough for `MAX_PLAYER_NAME_CHARS` of any script.
+    pub const MAX_NAME_BYTES: usize = 64;
+
+    /// The name as sent, cut at a character boundary to fit `MAX_NAME_BYTES`.
+    pub fn name_bytes(&self) -> &[u8] {
+        let mut end = self.name.len().min(Self::MAX_NAME_BYTES);
+        while !self.name.is_char_boundary(end) {
+            end -= 1;
+        }
+        &self.name.as_bytes()[..end]
+    }
+
+    pub fn to_bytes(&self) -> [u8; NETCODE_USER_DATA_BYTES] {
+        let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+        user_data[..8].copy_from_slice(&self.identity.unwrap_or(0).to_le_bytes());
+        user_data[8..16].copy_from_slice(&self.protocol_hash.to_le_bytes());
+        user_data[16..32].copy_from_slice(&self.secret.to_le_bytes());
+        let name = self.name_bytes();
+        user_data[32] = name.len() as u8;
+        user_data[33..33 + name.len()].copy_from_slice(name);
+        user_data
+    }
+
+    pub fn from_bytes(user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> Self {
+        let identity = u64::from_le_bytes(user_data[..8].try_into().unwrap());
+        let protocol_hash = u64::from_le_bytes(user_data[8..16].try_into().unwrap());
+        let secret = u128::from_le_bytes(user_data[16..32].try_into().unwrap());
+        let name_len = (user_data[32] as usize).min(Self::MAX_NAME_BYTES);
+        Self {
+            identity: (identity != 0).then_some(identity),
+            protocol_hash,
+            secret,
+            name: String::from_utf8_lossy(&user_data[33..33 + name_len]).into_owned(),
+        }
+    }
+}
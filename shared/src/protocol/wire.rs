@@ -0,0 +1,118 @@
+//! Little helpers for the hand written binary formats of the discovery and master server
+//! protocols, which are spoken outside of replicon.
+
+/// Writes a length prefixed string, cut to 255 bytes.
+pub(crate) fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    let value = &value.as_bytes()[..value.len().min(u8::MAX as usize)];
+    bytes.push(value.len() as u8);
+    bytes.extend_from_slice(value);
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// `None` if the bytes don't start with `magic`.
+    pub(crate) fn new(bytes: &'a [u8], magic: [u8; 4]) -> Option<Self> {
+        let mut reader = Self { bytes };
+        (reader.take(4)? == magic).then_some(reader)
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Some(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    pub(crate) fn string(&mut self) -> Option<String> {
+        let len = self.u8()? as usize;
+        // Lossy because cutting long names can split a character
+        Some(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAGIC: [u8; 4] = *b"TEST";
+
+    #[test]
+    fn reads_what_was_written() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(7);
+        bytes.extend_from_slice(&300u16.to_le_bytes());
+        bytes.extend_from_slice(&70_000u32.to_le_bytes());
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        write_string(&mut bytes, "zombie");
+
+        let mut reader = Reader::new(&bytes, MAGIC).unwrap();
+        assert_eq!(reader.u8(), Some(7));
+        assert_eq!(reader.u16(), Some(300));
+        assert_eq!(reader.u32(), Some(70_000));
+        assert_eq!(reader.u64(), Some(u64::MAX));
+        assert_eq!(reader.string().as_deref(), Some("zombie"));
+        assert_eq!(reader.u8(), None);
+    }
+
+    #[test]
+    fn wrong_or_short_magic_is_rejected() {
+        assert!(Reader::new(b"TES", MAGIC).is_none());
+        assert!(Reader::new(b"", MAGIC).is_none());
+        assert!(Reader::new(b"NOPE and more", MAGIC).is_none());
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        let mut reader = Reader::new(b"TEST\x01", MAGIC).unwrap();
+        assert_eq!(reader.u16(), None);
+        // A failed read doesn't consume anything
+        assert_eq!(reader.u8(), Some(1));
+
+        let mut reader = Reader::new(b"TEST\x01\x02\x03", MAGIC).unwrap();
+        assert_eq!(reader.u32(), None);
+        let mut reader = Reader::new(b"TEST\x01\x02\x03\x04\x05\x06\x07", MAGIC).unwrap();
+        assert_eq!(reader.u64(), None);
+        assert_eq!(reader.take(8), None);
+    }
+
+    #[test]
+    fn string_longer_than_the_input_is_rejected() {
+        let mut reader = Reader::new(b"TEST\x0ahello", MAGIC).unwrap();
+        assert_eq!(reader.string(), None);
+    }
+
+    #[test]
+    fn garbage_strings_are_read_lossily() {
+        let mut reader = Reader::new(b"TEST\x03\xff\xfea", MAGIC).unwrap();
+        assert_eq!(reader.string().as_deref(), Some("\u{fffd}\u{fffd}a"));
+    }
+
+    #[test]
+    fn long_strings_are_cut() {
+        let mut bytes = MAGIC.to_vec();
+        write_string(&mut bytes, &"x".repeat(300));
+        let mut reader = Reader::new(&bytes, MAGIC).unwrap();
+        assert_eq!(reader.string(), Some("x".repeat(255)));
+    }
+}
@@ -0,0 +1,83 @@
+//! Compact replication of transforms. The replicated state lives in its own components
+//! instead of a replication rule for `Transform`: a rule deserializes into `Transform`
+//! itself, which prediction and interpolation own on the client, so every update would
+//! overwrite what they show. Replicon sends each client only the components changed since
+//! the last tick it acknowledged, and `sync_replicated_transforms` only changes them when
+//! the rounded value does, so zombies standing still cost nothing.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+/// Steps per unit of `NetTransform` positions, one per centimeter.
+const POSITION_STEPS: f32 = 100.0;
+/// Steps of a full turn in `NetTransform::yaw`, small enough for two varint bytes.
+const YAW_STEPS: u32 = 4096;
+
+/// Replicated transform of upright characters, players and zombies: the position to the
+/// centimeter and the rotation around the vertical axis, without scale. Replication
+/// encodes integers as varints, so inside the map this takes at most 8 bytes instead of
+/// the 40 of a full `Transform`.
+#[derive(
+    Component, Serialize, Deserialize, Reflect, Clone, Copy, Default, Debug, PartialEq, Eq,
+)]
+#[require(Transform)]
+pub struct NetTransform {
+    x: i32,
+    y: i32,
+    z: i32,
+    yaw: u16,
+}
+
+impl NetTransform {
+    pub fn from_transform(transform: &Transform) -> Self {
+        let quantize = |value: f32| (value * POSITION_STEPS).round() as i32;
+        let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        let turns = (yaw / TAU).rem_euclid(1.0);
+        Self {
+            x: quantize(transform.translation.x),
+            y: quantize(transform.translation.y),
+            z: quantize(transform.translation.z),
+            yaw: ((turns * YAW_STEPS as f32).round() as u32 % YAW_STEPS) as u16,
+        }
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        Vec3::new(self.x as f32, self.y as f32, self.z as f32) / POSITION_STEPS
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.yaw as f32 / YAW_STEPS as f32 * TAU)
+    }
+}
+
+/// Replicated transform of entities that stay where they are, like trees and the map.
+/// Replication only sends changes, so it goes out once per client unless the entity is
+/// moved or scaled, which the map is when players join or leave.
+#[derive(Component, Serialize, Deserialize, Reflect, Clone, Copy, Default, Debug, PartialEq)]
+#[require(Transform)]
+pub struct StaticTransform(pub Transform);
+
+/// Copies the server's transforms into the replicated components. Values that round to
+/// what was sent last aren't sent again.
+pub fn sync_replicated_transforms(
+    mut character_query: Query<(&Transform, &mut NetTransform), Changed<Transform>>,
+    mut static_query: Query<(&Transform, &mut StaticTransform), Changed<Transform>>,
+) {
+    for (transform, mut net_transform) in &mut character_query {
+        net_transform.set_if_neq(NetTransform::from_transform(transform));
+    }
+    for (transform, mut static_transform) in &mut static_query {
+        static_transform.set_if_neq(StaticTransform(*transform));
+    }
+}
+
+/// Places static entities on the client. Characters are placed by prediction and
+/// interpolation instead.
+pub fn apply_static_transforms(
+    mut query: Query<(&StaticTransform, &mut Transform), Changed<StaticTransform>>,
+) {
+    for (static_transform, mut transform) in &mut query {
+        *transform = static_transform.0;
+    }
+}
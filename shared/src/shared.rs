@@ -1,9 +1,17 @@
+pub use crate::chat::chat::{ChatMessage, ChatSender, SendChatMessage};
 pub use crate::players::player::{
-    DamageFlash, DamagePlayer, Health, MovePlayer, Player, PlayerAttack, PlayerOwner,
+    AttackConfirmed, AttackTarget, DamageFlash, DamagePlayer, Dead, Health, InputAck, MovePlayer,
+    Player, PlayerAttack, PlayerName, PlayerOwner, RequestRespawn,
+};
+pub use crate::protocol::protocol::ProtocolVersion;
+use crate::register_protocol;
+pub use crate::replication::transform::{NetTransform, StaticTransform};
+pub use crate::wave::wave::WaveStatus;
+pub use crate::zombie::zombie::{
+    Dying, Spit, Zombie, ZombieAiState, ZombieAttackHit, ZombieKind, ZombieSpeed,
 };
-pub use crate::zombie::zombie::Zombie;
 use bevy::prelude::*;
-use bevy_replicon::prelude::{*, Channel};
+use bevy_replicon::prelude::{Channel, *};
 use serde::{Deserialize, Serialize};
 
 #[derive(Component, Serialize, Deserialize, Clone, Debug, Reflect, Default)]
@@ -14,19 +22,51 @@ pub struct MapMarker;
 #[reflect(Component)]
 pub struct TreeMarker;
 
+/// Seconds since the server started, replicated on a single entity every tick so clients
+/// can tell which moment of the server they are showing.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, Reflect, Default)]
+#[reflect(Component)]
+pub struct ServerTime(pub f64);
+
 pub struct SharedPlugin;
 
 impl Plugin for SharedPlugin {
     fn build(&self, app: &mut App) {
-        app.replicate::<Player>();
-        app.replicate::<PlayerOwner>();
-        app.replicate::<Health>();
-        app.replicate::<DamageFlash>();
-        app.replicate::<Zombie>();
-        app.replicate::<Transform>();
-        app.replicate::<MapMarker>();
-        app.replicate::<TreeMarker>();
-        app.add_client_message::<MovePlayer>(Channel::Unreliable);
-        app.add_client_message::<PlayerAttack>(Channel::Unreliable);
+        let registrations = register_protocol!(
+            app,
+            replicate: [
+                Player,
+                PlayerOwner,
+                PlayerName,
+                InputAck,
+                Health,
+                Dead,
+                DamageFlash,
+                Zombie,
+                ZombieKind,
+                Dying,
+                ZombieAiState,
+                ZombieSpeed,
+                Spit,
+                NetTransform,
+                StaticTransform,
+                MapMarker,
+                TreeMarker,
+                WaveStatus,
+                ServerTime,
+            ],
+            client_messages: [
+                MovePlayer => Channel::Unreliable,
+                PlayerAttack => Channel::Unreliable,
+                RequestRespawn => Channel::Ordered,
+                SendChatMessage => Channel::Ordered,
+            ],
+            server_messages: [
+                AttackConfirmed => Channel::Unordered,
+                ZombieAttackHit => Channel::Unordered,
+                ChatMessage => Channel::Ordered,
+            ],
+        );
+        app.insert_resource(ProtocolVersion::new(&registrations));
     }
 }
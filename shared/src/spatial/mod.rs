@@ -0,0 +1 @@
+pub mod spatial_hash;
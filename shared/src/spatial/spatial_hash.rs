@@ -0,0 +1,222 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::players::player::{Dead, Player};
+use crate::zombie::zombie::{Dying, Zombie};
+
+/// Side length of a spatial hash cell. Sized for the frequent short range queries (attack
+/// reach, spit hits), which touch at most 3x3 cells; the chase range lookup
+/// (`ZOMBIE_CHASE_RANGE`) scans about 6x6 cells once per zombie and tick.
+pub const SPATIAL_CELL_SIZE: f32 = 4.0;
+
+/// Buckets entities by their position on the XZ plane for fast proximity queries.
+#[derive(Debug, Clone)]
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<(Entity, Vec3)>>,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, position: Vec3) -> (i32, i32) {
+        (
+            (position.x / self.cell_size).floor() as i32,
+            (position.z / self.cell_size).floor() as i32,
+        )
+    }
+
+    /// Empties all cells while keeping their allocations for the next rebuild.
+    pub fn clear(&mut self) {
+        for entries in self.cells.values_mut() {
+            entries.clear();
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vec3) {
+        let cell = self.cell(position);
+        self.cells.entry(cell).or_default().push((entity, position));
+    }
+
+    /// Entities within `radius` of `center`, with their positions and distances.
+    pub fn within_radius(
+        &self,
+        center: Vec3,
+        radius: f32,
+    ) -> impl Iterator<Item = (Entity, Vec3, f32)> + '_ {
+        let (min_x, min_z) = self.cell(center - Vec3::splat(radius));
+        let (max_x, max_z) = self.cell(center + Vec3::splat(radius));
+
+        (min_z..=max_z)
+            .flat_map(move |z| (min_x..=max_x).map(move |x| (x, z)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .map(move |(entity, position)| (*entity, *position, position.distance(center)))
+            .filter(move |(_, _, distance)| *distance < radius)
+    }
+
+    /// The closest entity within `radius` of `center`.
+    pub fn nearest(&self, center: Vec3, radius: f32) -> Option<(Entity, Vec3, f32)> {
+        self.within_radius(center, radius)
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+    }
+}
+
+/// Spatial hashes of living players and zombies, rebuilt every fixed tick.
+#[derive(Resource, Debug, Clone)]
+pub struct SpatialIndex {
+    pub players: SpatialHash,
+    pub zombies: SpatialHash,
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        Self {
+            players: SpatialHash::new(SPATIAL_CELL_SIZE),
+            zombies: SpatialHash::new(SPATIAL_CELL_SIZE),
+        }
+    }
+}
+
+type LivingPlayer = (With<Player>, Without<Dead>);
+type LivingZombie = (With<Zombie>, Without<Dying>);
+
+pub fn rebuild_spatial_index(
+    mut index: ResMut<SpatialIndex>,
+    player_query: Query<(Entity, &Transform), LivingPlayer>,
+    zombie_query: Query<(Entity, &Transform), LivingZombie>,
+) {
+    index.players.clear();
+    for (entity, transform) in &player_query {
+        index.players.insert(entity, transform.translation);
+    }
+
+    index.zombies.clear();
+    for (entity, transform) in &zombie_query {
+        index.zombies.insert(entity, transform.translation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn entity(index: usize) -> Entity {
+        Entity::from_raw_u32(index as u32 + 1).unwrap()
+    }
+
+    /// Random positions around the origin plus some exactly on cell boundaries.
+    fn positions() -> Vec<(Entity, Vec3)> {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut positions: Vec<Vec3> = (0..300)
+            .map(|_| {
+                Vec3::new(
+                    rng.random_range(-20.0..20.0),
+                    rng.random_range(0.0..2.0),
+                    rng.random_range(-20.0..20.0),
+                )
+            })
+            .collect();
+        for x in [-8.0, -4.0, 0.0, 4.0, 8.0] {
+            for z in [-8.0, -4.0, 0.0, 4.0, 8.0] {
+                positions.push(Vec3::new(x, 0.0, z));
+            }
+        }
+        positions
+            .into_iter()
+            .enumerate()
+            .map(|(i, position)| (entity(i), position))
+            .collect()
+    }
+
+    fn hash(positions: &[(Entity, Vec3)]) -> SpatialHash {
+        let mut hash = SpatialHash::new(SPATIAL_CELL_SIZE);
+        for (entity, position) in positions {
+            hash.insert(*entity, *position);
+        }
+        hash
+    }
+
+    fn queries() -> Vec<(Vec3, f32)> {
+        let mut queries = vec![
+            (Vec3::ZERO, 4.0),
+            (Vec3::new(-4.0, 0.0, -4.0), 0.5),
+            (Vec3::new(-4.0, 0.0, 4.0), 4.0),
+            (Vec3::new(-0.001, 0.0, -0.001), 2.0),
+            (Vec3::new(-13.7, 1.0, 9.2), 10.0),
+            (Vec3::new(19.0, 0.0, -19.0), 3.0),
+        ];
+        let mut rng = StdRng::seed_from_u64(5);
+        queries.extend((0..50).map(|_| {
+            let center = Vec3::new(
+                rng.random_range(-25.0..25.0),
+                0.5,
+                rng.random_range(-25.0..25.0),
+            );
+            (center, rng.random_range(0.1..12.0))
+        }));
+        queries
+    }
+
+    #[test]
+    fn within_radius_matches_brute_force() {
+        let positions = positions();
+        let hash = hash(&positions);
+
+        for (center, radius) in queries() {
+            let mut found: Vec<Entity> = hash
+                .within_radius(center, radius)
+                .map(|(entity, _, _)| entity)
+                .collect();
+            let mut expected: Vec<Entity> = positions
+                .iter()
+                .filter(|(_, position)| position.distance(center) < radius)
+                .map(|(entity, _)| *entity)
+                .collect();
+            found.sort();
+            expected.sort();
+            assert_eq!(found, expected, "center {} radius {}", center, radius);
+        }
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let positions = positions();
+        let hash = hash(&positions);
+
+        for (center, radius) in queries() {
+            let found = hash
+                .nearest(center, radius)
+                .map(|(_, _, distance)| distance);
+            let expected = positions
+                .iter()
+                .map(|(_, position)| position.distance(center))
+                .filter(|distance| *distance < radius)
+                .min_by(|a, b| a.total_cmp(b));
+            assert_eq!(found, expected, "center {} radius {}", center, radius);
+        }
+    }
+
+    #[test]
+    fn finds_entities_across_cell_boundaries() {
+        let hash = hash(&[
+            (entity(0), Vec3::new(-0.1, 0.0, -0.1)),
+            (entity(1), Vec3::new(4.0, 0.0, 0.0)),
+            (entity(2), Vec3::new(-4.0, 0.0, 0.0)),
+        ]);
+
+        let (nearest, _, _) = hash.nearest(Vec3::new(0.1, 0.0, 0.1), 1.0).unwrap();
+        assert_eq!(nearest, entity(0));
+        // Exactly at the radius is out of range
+        assert_eq!(hash.within_radius(Vec3::ZERO, 4.0).count(), 1);
+        assert_eq!(hash.within_radius(Vec3::ZERO, 4.01).count(), 3);
+        assert!(hash.nearest(Vec3::new(-2.0, 0.0, 3.0), 0.5).is_none());
+    }
+}
@@ -0,0 +1,14 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Progress of the horde mode, replicated on a single entity spawned by the server.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, Reflect, Default, PartialEq)]
+#[reflect(Component)]
+pub struct WaveStatus {
+    /// Current wave number, zero before the first wave started.
+    pub wave: u32,
+    /// Zombies that still have to be spawned or killed in the current wave.
+    pub zombies_remaining: u32,
+    /// Seconds until the next wave starts, zero while a wave is running.
+    pub intermission: f32,
+}
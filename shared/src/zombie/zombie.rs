@@ -1,19 +1,114 @@
-#[cfg(feature = "client")]
-use bevy::animation::{AnimationEvent, AnimationEventTrigger};
 use bevy::prelude::*;
+use bevy_replicon_renet2::renet2::ClientId;
 use serde::{Deserialize, Serialize};
 
 #[derive(Component, Serialize, Deserialize, Clone, Debug, Reflect, Default)]
 #[reflect(Component)]
 pub struct Zombie;
 
+/// Archetype of a zombie. The server loads the stats for every kind from a data file,
+/// the client picks model and animation settings per kind.
+#[derive(
+    Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Reflect, Default,
+)]
+#[reflect(Component)]
+pub enum ZombieKind {
+    #[default]
+    Walker,
+    /// Fast but fragile.
+    Runner,
+    /// Slow, armored and hits hard.
+    Tank,
+    /// Keeps its distance and spits at players.
+    Spitter,
+    /// Blows up when it dies, damaging everything around it.
+    Exploder,
+}
+
+impl ZombieKind {
+    /// Playback speed of the attacking clip. Shared with the server, which times the hits
+    /// from it.
+    pub fn attack_animation_speed(self) -> f32 {
+        match self {
+            ZombieKind::Runner => 1.5,
+            ZombieKind::Tank => 0.8,
+            _ => ZOMBIE_ATTACK_ANIMATION_SPEED,
+        }
+    }
+
+    /// Real seconds between starting an attack and the hit landing.
+    pub fn attack_windup(self) -> f32 {
+        ZOMBIE_ATTACK_HIT_TIME / self.attack_animation_speed()
+    }
+}
+
+/// Movement speed of a zombie in units per second after wave multipliers, replicated so
+/// clients can match the walking animation to it.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Reflect, Default)]
+#[reflect(Component)]
+pub struct ZombieSpeed(pub f32);
+
+/// Spit of a ranged zombie, flying straight at `SPIT_SPEED` until it hits a player or
+/// has flown past the zombie's attack range.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, Reflect, Default)]
+#[reflect(Component)]
+pub struct Spit;
+
+/// Units per second a spit flies, slow enough to step out of its way from afar.
+pub const SPIT_SPEED: f32 = 8.0;
+/// Distance from a spit's center at which it hits a player.
+pub const SPIT_RADIUS: f32 = 0.6;
+
+/// What the server-side zombie AI is doing, replicated so clients can animate it.
+#[derive(
+    Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Reflect, Default,
+)]
+#[reflect(Component)]
+pub enum ZombieAiState {
+    #[default]
+    Idle,
+    Wandering,
+    Chasing,
+    /// Next to a player, waiting for the attack cooldown.
+    InReach,
+    /// Winding up an attack, entered once per attack.
+    Attacking,
+}
+
+/// Sent by the server at the moment a zombie's attack connects with a player.
+#[derive(Event, Message, Serialize, Deserialize, Clone, Debug)]
+pub struct ZombieAttackHit {
+    pub target: ClientId,
+    pub position: Vec3,
+    pub damage: f32,
+}
+
+/// Marks a killed zombie that is playing its death animation before the server removes it.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, Reflect, Default)]
+#[reflect(Component)]
+pub struct Dying;
+
+/// Walking speed animations assume until the zombie's `ZombieSpeed` is known.
 pub const ZOMBIE_SPEED: f32 = 0.5;
+/// Distance at which a zombie notices a player and starts chasing.
+pub const ZOMBIE_CHASE_RANGE: f32 = 10.0;
+/// Attacking clip speed of kinds without their own, see `ZombieKind::attack_animation_speed`.
+pub const ZOMBIE_ATTACK_ANIMATION_SPEED: f32 = 1.2;
+/// Point in the attacking clip (`zombie.glb#Animation10`) where the blow lands, in clip seconds.
+pub const ZOMBIE_ATTACK_HIT_TIME: f32 = 0.5;
+/// Length of the dying clip (`zombie.glb#Animation12`) in seconds at normal speed.
+pub const ZOMBIE_DYING_CLIP_LENGTH: f32 = 2.5;
+pub const ZOMBIE_DYING_ANIMATION_SPEED: f32 = 1.0;
+/// How long a corpse stays around so the dying clip can play to the end.
+pub const ZOMBIE_DYING_DURATION: f32 = ZOMBIE_DYING_CLIP_LENGTH / ZOMBIE_DYING_ANIMATION_SPEED;
 // Reduced from 4.0 to 2.0 to match movement speed better and reduce "tear back" effect from root motion
 pub const ZOMBIE_ANIMATION_SPEED_MULTIPLIER: f32 = 2.0;
 
 #[cfg(feature = "client")]
 #[derive(Component)]
 pub struct ZombieAnimations {
+    /// Kind whose `ZombieAnimationConfig` the clips come from.
+    pub kind: ZombieKind,
     pub idle: AnimationNodeIndex,
     pub walking: AnimationNodeIndex,
     pub attacking: AnimationNodeIndex,
@@ -21,46 +116,22 @@ pub struct ZombieAnimations {
 }
 
 #[cfg(feature = "client")]
-#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Default)]
 pub enum ZombieAnimationState {
+    #[default]
     Idle,
     Walking,
     Attacking,
     Dying,
 }
 
-#[cfg(feature = "client")]
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Reflect, Serialize, Deserialize, Message)]
-pub enum ZombieAnimationEvent {
-    Footstep,
-    AttackHit,
-}
-
-#[cfg(feature = "client")]
-impl Event for ZombieAnimationEvent {
-    type Trigger<'a> = AnimationEventTrigger;
-}
-
-#[cfg(feature = "client")]
-impl AnimationEvent for ZombieAnimationEvent {}
-
-#[cfg(feature = "client")]
-#[derive(Resource, Default)]
-pub struct ZombieAnimationEventsState {
-    pub events_added: bool,
-}
-
-#[cfg(feature = "client")]
-impl Default for ZombieAnimationState {
-    fn default() -> Self {
-        Self::Idle
-    }
-}
-
-#[cfg(feature = "client")]
 #[cfg(feature = "client")]
 pub struct ZombieAnimationConfig {
     pub model_path: &'static str,
+    /// Uniform scale of the model.
+    pub scale: f32,
+    /// Color multiplied into the model's materials, `None` keeps the original look.
+    pub tint: Option<Color>,
     pub idle_animation: AnimationClipConfig,
     pub walking_animation: AnimationClipConfig,
     pub attacking_animation: AnimationClipConfig,
@@ -79,6 +150,8 @@ impl Default for ZombieAnimationConfig {
     fn default() -> Self {
         Self {
             model_path: "zombie.glb#Scene0",
+            scale: 1.0,
+            tint: None,
             idle_animation: AnimationClipConfig {
                 path: "zombie.glb#Animation0",
                 speed: 1.0,
@@ -86,17 +159,18 @@ impl Default for ZombieAnimationConfig {
             },
             walking_animation: AnimationClipConfig {
                 path: "zombie.glb#Animation11",
-                speed: ZOMBIE_SPEED * ZOMBIE_ANIMATION_SPEED_MULTIPLIER,
+                speed: Self::walking_speed(ZOMBIE_SPEED),
                 repeat: true,
             },
+            // Played once per attack, see `ZombieAiState::Attacking`
             attacking_animation: AnimationClipConfig {
                 path: "zombie.glb#Animation10",
-                speed: 1.2,
-                repeat: true,
+                speed: ZOMBIE_ATTACK_ANIMATION_SPEED,
+                repeat: false,
             },
             dying_animation: AnimationClipConfig {
                 path: "zombie.glb#Animation12",
-                speed: 1.0,
+                speed: ZOMBIE_DYING_ANIMATION_SPEED,
                 repeat: false,
             },
         }
@@ -104,25 +178,62 @@ impl Default for ZombieAnimationConfig {
 }
 
 #[cfg(feature = "client")]
-pub fn spawn_zombie(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let config = ZombieAnimationConfig::default();
-    commands.spawn((
-        SceneRoot(asset_server.load(config.model_path)),
-        Transform::from_xyz(1.0, 0.0, 1.0).with_scale(Vec3::splat(1.0)),
-        Zombie,
-    ));
+impl ZombieAnimationConfig {
+    /// Visual settings of a zombie kind. The attacking clip speed comes from
+    /// `ZombieKind::attack_animation_speed` and the dying clip speed stays shared, because
+    /// the server times hits and corpse removal from them. The walking speed follows
+    /// `ZombieSpeed`.
+    pub fn for_kind(kind: ZombieKind) -> Self {
+        let mut config = Self::default();
+        config.attacking_animation.speed = kind.attack_animation_speed();
+        match kind {
+            ZombieKind::Walker => {}
+            ZombieKind::Runner => {
+                config.scale = 0.9;
+                config.tint = Some(Color::srgb(0.95, 0.8, 0.65));
+            }
+            ZombieKind::Tank => {
+                config.scale = 1.4;
+                config.tint = Some(Color::srgb(0.55, 0.55, 0.65));
+                config.idle_animation.speed = 0.7;
+            }
+            ZombieKind::Spitter => {
+                config.tint = Some(Color::srgb(0.6, 0.95, 0.5));
+            }
+            ZombieKind::Exploder => {
+                config.scale = 1.1;
+                config.tint = Some(Color::srgb(1.0, 0.5, 0.35));
+            }
+        }
+        config
+    }
+
+    /// Walking clip speed of a zombie moving at `speed`, so its feet keep up with it.
+    pub fn walking_speed(speed: f32) -> f32 {
+        speed * ZOMBIE_ANIMATION_SPEED_MULTIPLIER
+    }
 }
 
 #[cfg(feature = "client")]
 pub fn setup_zombie_animation(
     mut commands: Commands,
     mut animation_players: Query<(Entity, &mut AnimationPlayer), Added<AnimationPlayer>>,
+    zombie_query: Query<(&ZombieKind, Option<&ZombieSpeed>), With<Zombie>>,
+    parents: Query<&ChildOf>,
     asset_server: Res<AssetServer>,
     mut graphs: ResMut<Assets<AnimationGraph>>,
 ) {
-    let config = ZombieAnimationConfig::default();
-
     for (entity, mut player) in &mut animation_players {
+        let (kind, speed) = parents
+            .iter_ancestors(entity)
+            .find_map(|ancestor| zombie_query.get(ancestor).ok())
+            .map(|(kind, speed)| (*kind, speed.copied()))
+            .unwrap_or_default();
+        let mut config = ZombieAnimationConfig::for_kind(kind);
+        if let Some(speed) = speed {
+            config.walking_animation.speed = ZombieAnimationConfig::walking_speed(speed.0);
+        }
+
         let mut graph = AnimationGraph::new();
 
         let idle_node = graph.add_clip(
@@ -150,6 +261,7 @@ pub fn setup_zombie_animation(
             .entity(entity)
             .insert(AnimationGraphHandle(graphs.add(graph)));
         commands.entity(entity).insert(ZombieAnimations {
+            kind,
             idle: idle_node,
             walking: walking_node,
             attacking: attacking_node,
@@ -164,33 +276,69 @@ pub fn setup_zombie_animation(
     }
 }
 
+/// Tints the materials of a zombie's model once its scene has spawned.
 #[cfg(feature = "client")]
-pub fn update_zombie_animation_state(
-    mut zombie_query: Query<(&mut ZombieAnimationState, &GlobalTransform), With<Zombie>>,
-    player_query: Query<&GlobalTransform, With<crate::players::player::Player>>,
+pub fn tint_zombie_materials(
+    mut mesh_query: Query<
+        (Entity, &mut MeshMaterial3d<StandardMaterial>),
+        Added<MeshMaterial3d<StandardMaterial>>,
+    >,
+    zombie_query: Query<&ZombieKind, With<Zombie>>,
+    parents: Query<&ChildOf>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    const CHASE_RANGE: f32 = 10.0;
-    const ATTACK_RANGE: f32 = 1.5;
+    for (entity, mut material_handle) in &mut mesh_query {
+        let Some(kind) = parents
+            .iter_ancestors(entity)
+            .find_map(|ancestor| zombie_query.get(ancestor).ok())
+        else {
+            continue;
+        };
+        let Some(tint) = ZombieAnimationConfig::for_kind(*kind).tint else {
+            continue;
+        };
+        let Some(material) = materials.get(&material_handle.0) else {
+            continue;
+        };
 
-    for (mut anim_state, zombie_transform) in &mut zombie_query {
-        let zombie_pos = zombie_transform.translation();
+        // Materials are shared between all zombies, give this one its own copy
+        let mut tinted = material.clone();
+        let base = tinted.base_color.to_linear();
+        let tint = tint.to_linear();
+        tinted.base_color = Color::linear_rgba(
+            base.red * tint.red,
+            base.green * tint.green,
+            base.blue * tint.blue,
+            base.alpha,
+        );
+        material_handle.0 = materials.add(tinted);
+    }
+}
 
-        // Find nearest player
-        let mut nearest_distance = f32::MAX;
-        for player_transform in &player_query {
-            let distance = zombie_pos.distance(player_transform.translation());
-            if distance < nearest_distance {
-                nearest_distance = distance;
-            }
-        }
+#[cfg(feature = "client")]
+pub fn update_zombie_animation_state(
+    mut animation_query: Query<(Entity, &mut ZombieAnimationState)>,
+    zombie_query: Query<(&ZombieAiState, Has<Dying>), With<Zombie>>,
+    parents: Query<&ChildOf>,
+) {
+    for (entity, mut anim_state) in &mut animation_query {
+        // The animation player lives inside the zombie's scene, look up the zombie itself
+        let Some((ai_state, is_dying)) = parents
+            .iter_ancestors(entity)
+            .find_map(|ancestor| zombie_query.get(ancestor).ok())
+        else {
+            continue;
+        };
 
-        // Determine animation state based on distance to nearest player
-        let new_state = if nearest_distance < ATTACK_RANGE {
-            ZombieAnimationState::Attacking
-        } else if nearest_distance < CHASE_RANGE {
-            ZombieAnimationState::Walking
+        // Follow the replicated server state
+        let new_state = if is_dying {
+            ZombieAnimationState::Dying
         } else {
-            ZombieAnimationState::Idle
+            match ai_state {
+                ZombieAiState::Idle | ZombieAiState::InReach => ZombieAnimationState::Idle,
+                ZombieAiState::Wandering | ZombieAiState::Chasing => ZombieAnimationState::Walking,
+                ZombieAiState::Attacking => ZombieAnimationState::Attacking,
+            }
         };
 
         if *anim_state != new_state {
@@ -210,9 +358,10 @@ pub fn control_zombie_animation(
         Changed<ZombieAnimationState>,
     >,
 ) {
-    let config = ZombieAnimationConfig::default();
-
     for (mut player, animations, state) in &mut animation_players {
+        let config = ZombieAnimationConfig::for_kind(animations.kind);
+        // Only the clip of the current state plays, and every attack starts its clip over
+        player.stop_all();
         match *state {
             ZombieAnimationState::Idle => {
                 if config.idle_animation.repeat {
@@ -245,48 +394,3 @@ pub fn control_zombie_animation(
         }
     }
 }
-
-#[cfg(feature = "client")]
-pub fn add_zombie_animation_events(
-    mut events_state: ResMut<ZombieAnimationEventsState>,
-    asset_server: Res<AssetServer>,
-    mut clips: ResMut<Assets<AnimationClip>>,
-) {
-    if events_state.events_added {
-        return;
-    }
-
-    let config = ZombieAnimationConfig::default();
-
-    // Check if clips are loaded
-    let walking_handle = asset_server.load(config.walking_animation.path);
-    let attacking_handle = asset_server.load(config.attacking_animation.path);
-
-    if let Some(clip) = clips.get_mut(&walking_handle) {
-        // Add footsteps at 0.0s and 0.5s (assuming 1s loop for simplicity, adjust as needed)
-        clip.add_event(0.2, ZombieAnimationEvent::Footstep);
-        clip.add_event(0.7, ZombieAnimationEvent::Footstep);
-        events_state.events_added = true; // Mark as done (at least for walking)
-        println!("Added footstep events to walking animation");
-    }
-
-    if let Some(clip) = clips.get_mut(&attacking_handle) {
-        // Add attack hit at 0.5s
-        clip.add_event(0.5, ZombieAnimationEvent::AttackHit);
-        println!("Added attack hit event to attacking animation");
-    }
-}
-
-#[cfg(feature = "client")]
-pub fn handle_zombie_animation_events(mut animation_events: MessageReader<ZombieAnimationEvent>) {
-    for event in animation_events.read() {
-        match event {
-            ZombieAnimationEvent::Footstep => {
-                println!("Zombie Footstep");
-            }
-            ZombieAnimationEvent::AttackHit => {
-                println!("Zombie Attack Hit");
-            }
-        }
-    }
-}